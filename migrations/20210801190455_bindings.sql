@@ -0,0 +1,12 @@
+-- create bindings table
+CREATE TABLE IF NOT EXISTS bindings (
+  voice_channel BIGINT PRIMARY KEY,
+  guild_id BIGINT NOT NULL,
+  output_channel BIGINT NOT NULL
+);
+
+-- carry over the binding every guild already has
+INSERT INTO bindings (voice_channel, guild_id, output_channel)
+  SELECT default_bind, guild_id, output_channel FROM guilds
+    WHERE default_bind IS NOT NULL AND output_channel IS NOT NULL
+  ON CONFLICT (voice_channel) DO NOTHING;
@@ -0,0 +1,2 @@
+-- let guilds put every voice session into its own thread
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS session_threads BOOLEAN NOT NULL DEFAULT false;
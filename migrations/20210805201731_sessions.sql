@@ -0,0 +1,16 @@
+-- one row per voice session, filled in when the session ends
+CREATE TABLE IF NOT EXISTS sessions (
+    session_id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    voice_channel BIGINT NOT NULL,
+    output_channel BIGINT NOT NULL,
+    thread_id BIGINT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ended_at TIMESTAMPTZ,
+    participants BIGINT[] NOT NULL DEFAULT '{}',
+    utterances INTEGER NOT NULL DEFAULT 0,
+    ms_transcribed BIGINT NOT NULL DEFAULT 0
+);
+
+-- NULL means transcripts aren't uploaded at the end of sessions
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS transcript_format TEXT;
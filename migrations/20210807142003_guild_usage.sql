@@ -0,0 +1,9 @@
+-- milliseconds transcribed per guild per month, for enforcing the premium tier quotas
+CREATE TABLE IF NOT EXISTS guild_usage (
+    guild_id BIGINT NOT NULL,
+    month DATE NOT NULL,
+    ms_transcribed BIGINT NOT NULL DEFAULT 0,
+    warned BOOLEAN NOT NULL DEFAULT false,
+    paused BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (guild_id, month)
+);
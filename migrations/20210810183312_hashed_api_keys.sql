@@ -0,0 +1,23 @@
+-- users can have several keys now, each identified by its own ID
+ALTER TABLE api_keys DROP CONSTRAINT IF EXISTS api_keys_pkey;
+
+ALTER TABLE api_keys
+    ADD COLUMN IF NOT EXISTS key_id BIGSERIAL PRIMARY KEY,
+    ADD COLUMN IF NOT EXISTS key_hash TEXT,
+    ADD COLUMN IF NOT EXISTS key_prefix TEXT NOT NULL DEFAULT 'legacy',
+    ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{transcribe,stats}',
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS revoked_at TIMESTAMPTZ;
+
+-- keys used to be stored in plain text: hash them, then forget them
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM information_schema.columns
+               WHERE table_name = 'api_keys' AND column_name = 'api_key') THEN
+        UPDATE api_keys SET key_hash = encode(sha256(convert_to(api_key, 'UTF8')), 'hex');
+        ALTER TABLE api_keys DROP COLUMN api_key;
+    END IF;
+END $$;
+
+CREATE UNIQUE INDEX IF NOT EXISTS api_keys_key_hash ON api_keys (key_hash);
@@ -0,0 +1,12 @@
+-- token buckets are kept in memory, and saved here every so often so restarts don't refill them
+ALTER TABLE api_keys
+    ADD COLUMN IF NOT EXISTS rate_tokens DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS rate_updated_at TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS api_key_usage (
+    key_id BIGINT NOT NULL,
+    month DATE NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    audio_ms BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (key_id, month)
+);
@@ -0,0 +1,2 @@
+-- hash of the token that lets someone watch a guild's live transcript feed, NULL if it's off
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS live_token_hash TEXT;
@@ -0,0 +1,21 @@
+-- audio too long to transcribe in one request, queued up for the background workers
+CREATE TABLE IF NOT EXISTS transcription_jobs (
+    job_id TEXT PRIMARY KEY,
+    key_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    -- 16 bit little endian mono PCM, dropped once the job finishes
+    audio BYTEA,
+    sample_rate INTEGER NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    callback_url TEXT,
+    callback_secret TEXT,
+    result JSONB,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS transcription_jobs_queued
+    ON transcription_jobs (created_at) WHERE status = 'queued';
@@ -0,0 +1,5 @@
+-- text channels where audio files and voice messages get transcribed as they're posted
+CREATE TABLE IF NOT EXISTS attachment_channels (
+    channel_id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL
+);
@@ -0,0 +1,15 @@
+-- GitHub sponsors, and the Discord user each one linked with `link_github`
+CREATE TABLE IF NOT EXISTS sponsors (
+    -- always lowercase, GitHub logins aren't case sensitive
+    github_login TEXT PRIMARY KEY,
+    user_id BIGINT UNIQUE,
+    monthly_dollars INTEGER NOT NULL DEFAULT 0,
+    active BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- every sponsor webhook delivery already handled, so redeliveries aren't applied twice
+CREATE TABLE IF NOT EXISTS sponsor_deliveries (
+    delivery_id TEXT PRIMARY KEY,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
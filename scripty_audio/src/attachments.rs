@@ -0,0 +1,190 @@
+use crate::{check_quota, record_usage};
+use scripty_audio_utils::{decode_audio, model, run_stt_mono, DecodeError, SAMPLE_RATE};
+use scripty_db::{
+    fetch_attachment_channel_premium, fetch_guild_premium, max_attachment_audio_secs,
+};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    model::prelude::{Attachment, GuildId, Message},
+};
+use sqlx::PgPool;
+
+/// Extensions of the files worth downloading. Discord's voice messages are `.ogg`.
+const AUDIO_EXTENSIONS: [&str; 8] = ["wav", "flac", "mp3", "ogg", "oga", "opus", "webm", "weba"];
+
+/// The biggest file that gets downloaded, in bytes. How long the audio in it is gets checked while
+/// decoding, since that depends on the format.
+const MAX_ATTACHMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Embed descriptions can't be any longer than this.
+const MAX_DESCRIPTION_LEN: usize = 4096;
+
+/// Whether an attachment looks like audio, going by its file name.
+pub fn is_audio_attachment(attachment: &Attachment) -> bool {
+    attachment
+        .filename
+        .rsplit_once('.')
+        .map_or(false, |(_, ext)| {
+            AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Get the premium level of a guild, or 0 if it was never set up.
+pub async fn guild_premium_level(db: &PgPool, guild_id: GuildId) -> u8 {
+    match fetch_guild_premium(db, guild_id).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!("Couldn't fetch the premium level of {}: {}", guild_id, e);
+            0
+        }
+    }
+}
+
+/// Get the premium level of the guild a channel belongs to, if audio posted in the channel
+/// should be transcribed.
+pub async fn attachment_channel_premium(db: &PgPool, msg: &Message) -> Option<u8> {
+    match fetch_attachment_channel_premium(db, msg.channel_id).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!(
+                "Couldn't check if {} transcribes attachments: {}",
+                msg.channel_id,
+                e
+            );
+            None
+        }
+    }
+}
+
+fn too_long(max_secs: u64) -> String {
+    format!(
+        "That's too long, I can only transcribe up to {} seconds of audio at this server's \
+        premium level.",
+        max_secs
+    )
+}
+
+/// Something went wrong on our end, rather than with the file.
+fn internal_error() -> String {
+    "Something went wrong while transcribing that. I just let my developer know.".to_string()
+}
+
+/// Downloads an audio attachment posted in `guild_id` and runs speech to text on it, failing if
+/// it's bigger than `MAX_ATTACHMENT_BYTES`, longer than `max_secs` or the guild is out of
+/// transcription time. What was transcribed
+/// counts towards the guild's quota like voice chat does. The error is meant to be shown to the
+/// user.
+async fn transcribe_attachment(
+    ctx: &Context,
+    guild_id: GuildId,
+    premium_level: u8,
+    attachment: &Attachment,
+    max_secs: u64,
+) -> Result<(String, u64), String> {
+    if attachment.size > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "That file is too big, I can only download audio files up to {} MB.",
+            MAX_ATTACHMENT_BYTES / 1024 / 1024
+        ));
+    }
+    if !check_quota(ctx, guild_id, premium_level).await {
+        return Err(
+            "This server is out of transcription time until the start of next month.".to_string(),
+        );
+    }
+
+    let data = attachment.download().await.map_err(|e| {
+        tracing::warn!("Couldn't download {}: {}", attachment.url, e);
+        "I couldn't download that file, try again in a bit.".to_string()
+    })?;
+
+    let audio = tokio::task::spawn_blocking(move || decode_audio(&data, max_secs * 1000))
+        .await
+        .map_err(|e| {
+            tracing::error!("decoding {} panicked: {}", attachment.url, e);
+            internal_error()
+        })?
+        .map_err(|e| match e {
+            DecodeError::Unsupported(_) => "That isn't an audio format I understand.".to_string(),
+            DecodeError::Corrupt(_) => "That file seems to be broken.".to_string(),
+            DecodeError::TooLong { .. } => too_long(max_secs),
+        })?;
+    let duration_ms = audio.duration_ms();
+
+    let metadata = run_stt_mono(audio.samples, SAMPLE_RATE, 1, model().await)
+        .await
+        .map_err(|e| {
+            tracing::error!("Speech to text failed on {}: {}", attachment.url, e);
+            internal_error()
+        })?;
+    record_usage(ctx, guild_id, premium_level, duration_ms).await;
+
+    let transcript = metadata
+        .transcripts()
+        .first()
+        .map(|t| t.tokens().iter().filter_map(|t| t.text().ok()).collect())
+        .unwrap_or_default();
+    Ok((transcript, duration_ms))
+}
+
+/// Transcribes every audio attachment on `msg` and replies to it with the transcripts, one
+/// message per attachment. Returns how many attachments were audio.
+pub async fn reply_with_transcripts(ctx: &Context, msg: &Message, premium_level: u8) -> usize {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for an audio attachment");
+            return 0;
+        }
+    };
+    let max_secs = max_attachment_audio_secs(premium_level);
+    let mut count = 0;
+
+    for attachment in msg.attachments.iter().filter(|a| is_audio_attachment(a)) {
+        count += 1;
+
+        let mut embed = CreateEmbed::default();
+        match transcribe_attachment(ctx, guild_id, premium_level, attachment, max_secs).await {
+            Ok((transcript, duration_ms)) => {
+                let mut transcript: String = transcript.trim().to_string();
+                if transcript.is_empty() {
+                    transcript = "*I couldn't make out any words.*".to_string();
+                } else if transcript.len() > MAX_DESCRIPTION_LEN {
+                    let mut end = MAX_DESCRIPTION_LEN - 3;
+                    while !transcript.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    transcript.truncate(end);
+                    transcript.push_str("...");
+                }
+                embed
+                    .title(format!("Transcript of {}", attachment.filename))
+                    .description(transcript)
+                    .footer(|f| f.text(format!("{:.1} seconds", duration_ms as f64 / 1000.0)));
+            }
+            Err(e) => {
+                embed
+                    .title(format!("Couldn't transcribe {}", attachment.filename))
+                    .description(e);
+            }
+        }
+
+        if let Err(e) = msg
+            .channel_id
+            .send_message(&ctx, |m| {
+                m.reference_message(msg)
+                    .allowed_mentions(|am| am.replied_user(false))
+                    .embed(|e| {
+                        *e = embed;
+                        e
+                    })
+            })
+            .await
+        {
+            tracing::warn!("failed to send a transcript to {}: {}", msg.channel_id, e);
+        }
+    }
+
+    count
+}
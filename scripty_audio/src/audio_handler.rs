@@ -1,9 +1,21 @@
+use crate::{
+    check_quota, execute_webhook, publish_live, record_usage, LiveUtterance, Session,
+    SessionOptions,
+};
 use dashmap::{DashMap, DashSet};
-use scripty_audio_utils::{load_model, run_stt, Model};
-use scripty_metrics::{Metrics, METRICS};
+use prometheus::IntGauge;
+use scripty_audio_utils::{model, run_stt, Model};
+use scripty_metrics::METRICS;
 use serenity::builder::ExecuteWebhook;
 use serenity::model::prelude::Embed;
-use serenity::{async_trait, model::webhook::Webhook, prelude::Context};
+use serenity::{
+    async_trait,
+    model::{
+        id::{ChannelId, GuildId},
+        webhook::Webhook,
+    },
+    prelude::Context,
+};
 use songbird::{
     model::{
         id::UserId,
@@ -12,16 +24,87 @@ use songbird::{
     Event, EventContext, EventHandler as VoiceEventHandler,
 };
 use std::{
-    hint::unreachable_unchecked,
     sync::{Arc, RwLock},
+    time::Instant,
 };
-use tokio::task;
+use tokio::{sync::Mutex, task};
 use tracing::{debug, error, trace, warn};
 
+/// Songbird decodes audio to 48kHz stereo, so this many samples make up a millisecond.
+const SAMPLES_PER_MS: usize = 96;
+
 fn do_check(user_id: &UserId, active_users: &DashSet<UserId>) -> bool {
     active_users.get(user_id).is_none()
 }
 
+/// How many bytes `samples` take up in an audio buffer.
+fn buffer_bytes(samples: usize) -> i64 {
+    (samples * std::mem::size_of::<i16>()) as i64
+}
+
+/// Counts a receiver towards the voice gauges for as long as it's alive.
+///
+/// Every clone of a `Receiver` shares one of these, so once songbird drops the last of them
+/// (the bot left, or the call was rebound) whatever that receiver still had counted is taken
+/// back out of the gauges.
+struct VoiceGauges {
+    premium_level: String,
+    audio_buffer: Arc<DashMap<u32, Vec<i16>>>,
+    active_users: Arc<DashSet<UserId>>,
+    next_users: Arc<RwLock<Vec<UserId>>>,
+}
+
+impl VoiceGauges {
+    fn new(
+        premium_level: u8,
+        audio_buffer: Arc<DashMap<u32, Vec<i16>>>,
+        active_users: Arc<DashSet<UserId>>,
+        next_users: Arc<RwLock<Vec<UserId>>>,
+    ) -> Self {
+        unsafe { METRICS.get().unwrap_unchecked() }
+            .voice_calls
+            .inc();
+        Self {
+            premium_level: premium_level.to_string(),
+            audio_buffer,
+            active_users,
+            next_users,
+        }
+    }
+
+    fn active_speakers(&self) -> IntGauge {
+        unsafe { METRICS.get().unwrap_unchecked() }
+            .active_speakers
+            .with_label_values(&[&self.premium_level])
+    }
+
+    fn waitlisted_speakers(&self) -> IntGauge {
+        unsafe { METRICS.get().unwrap_unchecked() }
+            .waitlisted_speakers
+            .with_label_values(&[&self.premium_level])
+    }
+}
+
+impl Drop for VoiceGauges {
+    fn drop(&mut self) {
+        let metrics = unsafe { METRICS.get().unwrap_unchecked() };
+        metrics.voice_calls.dec();
+        self.active_speakers().sub(self.active_users.len() as i64);
+        self.waitlisted_speakers().sub(
+            self.next_users
+                .read()
+                .expect("thread panicked while holding next user lock")
+                .len() as i64,
+        );
+        metrics.audio_buffer_bytes.sub(
+            self.audio_buffer
+                .iter()
+                .map(|b| buffer_bytes(b.len()))
+                .sum(),
+        );
+    }
+}
+
 #[derive(Clone)]
 pub struct Receiver {
     ssrc_map: Arc<DashMap<u32, UserId>>,
@@ -34,6 +117,11 @@ pub struct Receiver {
     max_users: u16, // seriously if it hits 65535 users in a VC wtf
     ds_model: Arc<std::sync::RwLock<Model>>,
     verbose: bool,
+    voice_channel: ChannelId,
+    guild_id: GuildId,
+    session_options: SessionOptions,
+    session: Arc<Mutex<Option<Arc<Session>>>>,
+    gauges: Arc<VoiceGauges>,
 }
 
 // next two both forcibly implement the required types for async code
@@ -48,6 +136,9 @@ impl Receiver {
         context: Arc<Context>,
         premium_level: u8,
         verbose: bool,
+        voice_channel: ChannelId,
+        guild_id: GuildId,
+        session_options: SessionOptions,
     ) -> Self {
         let max_users = match premium_level {
             0 => 10,
@@ -69,7 +160,13 @@ impl Receiver {
         let webhook = Arc::new(webhook);
         let active_users = Arc::new(DashSet::new());
         let next_users = Arc::new(RwLock::new(Vec::new()));
-        let ds_model = Arc::new(std::sync::RwLock::new(load_model()));
+        let ds_model = model().await;
+        let gauges = Arc::new(VoiceGauges::new(
+            premium_level,
+            Arc::clone(&audio_buffer),
+            Arc::clone(&active_users),
+            Arc::clone(&next_users),
+        ));
         Self {
             ssrc_map,
             audio_buffer,
@@ -81,6 +178,28 @@ impl Receiver {
             max_users,
             ds_model,
             verbose,
+            voice_channel,
+            guild_id,
+            session_options,
+            session: Arc::new(Mutex::new(None)),
+            gauges,
+        }
+    }
+
+    /// Starts a new session, unless one is already running.
+    async fn start_session(&self) {
+        let mut session = self.session.lock().await;
+        if session.is_none() {
+            *session = Some(Arc::new(
+                Session::start(
+                    Arc::clone(&self.context),
+                    self.guild_id,
+                    self.voice_channel,
+                    self.webhook.channel_id,
+                    self.session_options,
+                )
+                .await,
+            ));
         }
     }
 }
@@ -103,8 +222,13 @@ impl VoiceEventHandler for Receiver {
                     return None;
                 }
 
+                self.start_session().await;
                 self.ssrc_map.insert(*ssrc, *user_id);
-                self.audio_buffer.insert(*ssrc, Vec::new());
+                if let Some(old) = self.audio_buffer.insert(*ssrc, Vec::new()) {
+                    unsafe { METRICS.get().unwrap_unchecked() }
+                        .audio_buffer_bytes
+                        .sub(buffer_bytes(old.len()));
+                }
             }
             EventContext::SpeakingUpdate { ssrc, speaking } => {
                 let uid: u64 = match self.ssrc_map.get(ssrc) {
@@ -120,6 +244,9 @@ impl VoiceEventHandler for Receiver {
                         Some(mut a) => {
                             let res = a.clone();
                             a.clear();
+                            unsafe { METRICS.get().unwrap_unchecked() }
+                                .audio_buffer_bytes
+                                .sub(buffer_bytes(res.len()));
                             res
                         }
                         None => return None,
@@ -142,10 +269,35 @@ impl VoiceEventHandler for Receiver {
                     let context = Arc::clone(&self.context);
                     let model = Arc::clone(&self.ds_model);
                     let verbose = self.verbose;
+                    let stopped_at = Instant::now();
+                    let audio_ms = (audio.len() / SAMPLES_PER_MS) as u64;
+                    let session = self.session.lock().await.clone();
+                    let thread_id = session.as_ref().and_then(|s| s.thread_id());
+                    let guild_id = self.guild_id;
+                    let voice_channel = self.voice_channel;
+                    let premium_level = self.premium_level;
 
                     task::spawn(async move {
-                        match run_stt(audio, model).await {
+                        if !check_quota(&context, guild_id, premium_level).await {
+                            return;
+                        }
+
+                        let metrics = unsafe { METRICS.get().unwrap_unchecked() };
+                        let inference_start = Instant::now();
+                        let result = run_stt(audio, model).await;
+                        let inference_time = inference_start.elapsed().as_secs_f64();
+                        metrics.inference_time.observe(inference_time);
+                        if audio_ms != 0 {
+                            metrics
+                                .real_time_factor
+                                .observe(inference_time / (audio_ms as f64 / 1000.0));
+                        }
+
+                        match result {
                             Ok(r) => {
+                                metrics.ms_transcribed.inc_by(audio_ms);
+                                record_usage(&context, guild_id, premium_level, audio_ms).await;
+
                                 let mut has_result = false;
                                 let mut webhook_execute = ExecuteWebhook::default();
                                 if let Some(t) = r.transcripts().first() {
@@ -179,6 +331,27 @@ impl VoiceEventHandler for Receiver {
                                         }
                                     }
 
+                                    if let Some(session) = &session {
+                                        session.record(
+                                            serenity::model::id::UserId(uid),
+                                            &u.name,
+                                            audio_ms,
+                                            &transcription,
+                                        );
+                                    }
+
+                                    publish_live(
+                                        guild_id,
+                                        LiveUtterance {
+                                            voice_channel: voice_channel.0,
+                                            user_id: uid,
+                                            username: u.name.clone(),
+                                            avatar_url: u.face(),
+                                            text: transcription.clone(),
+                                            timestamp: chrono::Utc::now(),
+                                        },
+                                    );
+
                                     if verbose {
                                         let embed = Embed::fake(|x| {
                                             x.field("Transcription", transcription, false)
@@ -217,12 +390,22 @@ impl VoiceEventHandler for Receiver {
                                 if has_result {
                                     webhook_execute.avatar_url(u.face()).username(u.name);
 
-                                    let _ = webhook
-                                        .execute(&context, false, |m| {
-                                            *m = webhook_execute;
-                                            m
-                                        })
-                                        .await;
+                                    match execute_webhook(
+                                        &context,
+                                        &webhook,
+                                        thread_id,
+                                        webhook_execute,
+                                    )
+                                    .await
+                                    {
+                                        Ok(_) => metrics
+                                            .transcription_latency
+                                            .observe(stopped_at.elapsed().as_secs_f64()),
+                                        Err(e) => {
+                                            warn!("failed to post transcription: {}", e);
+                                            metrics.failed_webhooks.inc();
+                                        }
+                                    }
                                 }
                             }
                             Err(e) => {
@@ -240,7 +423,7 @@ impl VoiceEventHandler for Receiver {
             } => {
                 // this code needs to be insanely optimized
                 // so we're trying to do stuff with as little overhead as possible
-                let st = std::time::Instant::now();
+                let st = Instant::now();
 
                 let uid = match self.ssrc_map.get(&packet.ssrc) {
                     Some(u) => *u,
@@ -251,28 +434,20 @@ impl VoiceEventHandler for Receiver {
                     return None;
                 };
 
+                let metrics = unsafe { METRICS.get().unwrap_unchecked() };
                 if let Some(audio) = audio {
+                    metrics
+                        .ms_received
+                        .inc_by((audio.len() / SAMPLES_PER_MS) as u64);
                     if let Some(mut b) = self.audio_buffer.get_mut(&packet.ssrc) {
-                        b.extend(audio)
+                        b.extend(audio);
+                        metrics.audio_buffer_bytes.add(buffer_bytes(audio.len()));
                     };
                 }
 
-                let et = std::time::Instant::now();
-                {
-                    let client_data = self.context.data.read().await;
-                    let m = unsafe { METRICS.get().unwrap_unchecked() };
-                    let metrics = client_data
-                        .get::<Metrics>()
-                        .unwrap_or_else(|| unsafe { unreachable_unchecked() });
-                    // 20ms audio packet: if it isn't 20 but rather 30 oh well too bad, it's only 10ms we lose
-                    // anything else shouldn't ever happen
-                    metrics.ms_transcribed.inc_by(20);
-                    metrics.avg_audio_process_time.set(
-                        (et.duration_since(st).as_nanos() as i64
-                            + metrics.avg_audio_process_time.get())
-                            / 2,
-                    );
-                }
+                metrics
+                    .packet_process_time
+                    .observe(st.elapsed().as_secs_f64());
             }
             EventContext::ClientConnect(ClientConnect {
                 audio_ssrc,
@@ -280,6 +455,16 @@ impl VoiceEventHandler for Receiver {
                 user_id,
                 ..
             }) => {
+                self.start_session().await;
+                let is_bot = self
+                    .context
+                    .cache
+                    .user(user_id.0)
+                    .await
+                    .map_or(false, |u| u.bot);
+                if let (Some(session), false) = (self.session.lock().await.as_ref(), is_bot) {
+                    session.add_participant(serenity::model::id::UserId(user_id.0));
+                }
                 self.ssrc_map.insert(*audio_ssrc, *user_id);
                 {
                     if self.active_users.len() >= self.max_users as usize {
@@ -288,8 +473,9 @@ impl VoiceEventHandler for Receiver {
                             .write()
                             .expect("thread panicked while holding next user lock");
                         next_users.push(*user_id);
-                    } else {
-                        self.active_users.insert(*user_id);
+                        self.gauges.waitlisted_speakers().inc();
+                    } else if self.active_users.insert(*user_id) {
+                        self.gauges.active_speakers().inc();
                     };
                 }
             }
@@ -301,19 +487,37 @@ impl VoiceEventHandler for Receiver {
                         None
                     }
                 }) {
-                    self.audio_buffer.remove(&u);
+                    if let Some((_, b)) = self.audio_buffer.remove(&u) {
+                        unsafe { METRICS.get().unwrap_unchecked() }
+                            .audio_buffer_bytes
+                            .sub(buffer_bytes(b.len()));
+                    }
                     self.ssrc_map.remove(&u);
                     {
-                        self.active_users.remove(user_id);
                         let mut next_users = self
                             .next_users
                             .write()
                             .expect("thread panicked while holding next user lock");
-                        if let Some(user) = next_users.pop() {
-                            self.active_users.insert(user);
-                        };
+                        if self.active_users.remove(user_id).is_some() {
+                            self.gauges.active_speakers().dec();
+                            // their spot opened up, so give it to whoever's waiting
+                            if let Some(user) = next_users.pop() {
+                                self.gauges.waitlisted_speakers().dec();
+                                if self.active_users.insert(user) {
+                                    self.gauges.active_speakers().inc();
+                                }
+                            };
+                        } else if let Some(i) = next_users.iter().position(|u| u == user_id) {
+                            next_users.remove(i);
+                            self.gauges.waitlisted_speakers().dec();
+                        }
                     }
                 };
+
+                // everyone left, so this session is over
+                if self.ssrc_map.is_empty() {
+                    *self.session.lock().await = None;
+                }
             }
             _ => {}
         }
@@ -1,80 +1,197 @@
-use crate::bind;
-use scripty_db::PgPoolKey;
-use serenity::{futures::TryStreamExt, model::id::ChannelId, prelude::Context};
-use std::{convert::TryInto, sync::Arc};
+use crate::{bind, connected_channel, voice_contexts};
+use scripty_db::{bindings_with_premium, forget_binding, max_bindings, Binding, PgPoolKey};
+use serenity::{model::id::GuildId, prelude::Context};
+use std::{collections::BTreeMap, sync::Arc};
 use tracing::{debug, warn};
 
 /// Automatically joins all voice chats the bot can see in its DB.
 /// `ctx` is a Arc<Context> containing the DB pool and Songbird client
 /// `force` decides whether to forcibly rejoin a voice chat. This will result in errors at some point.
+///
+/// A guild gets one voice connection from the main bot and one from each worker in it, so if it
+/// has more bindings than that the bot rotates to whichever bound voice chats have people in
+/// them. See `pick_bindings` for the exact rules.
 pub async fn auto_join(ctx: Arc<Context>, force: bool) {
+    join_bindings(ctx, None, force).await
+}
+
+/// Forcibly rejoins every voice chat bound in `guild_id`, like `auto_join` does for every guild.
+pub async fn rejoin_guild(ctx: Arc<Context>, guild_id: GuildId) {
+    join_bindings(ctx, Some(guild_id), true).await
+}
+
+/// Leaves every voice chat the bot and its workers are in in `guild_id`, returning how many
+/// there were.
+///
+/// Bindings are left alone, so the next `auto_join` joins them again.
+pub async fn leave_guild(ctx: &Context, guild_id: GuildId) -> Result<usize, String> {
+    let mut left = 0;
+    for c in voice_contexts(ctx, guild_id).await {
+        if connected_channel(&c, guild_id).await.is_none() {
+            continue;
+        }
+        let manager = unsafe { songbird::get(&c).await.unwrap_unchecked() };
+        manager
+            .remove(guild_id)
+            .await
+            .map_err(|e| format!("failed to leave VC in {}: {}", guild_id, e))?;
+        left += 1;
+    }
+    Ok(left)
+}
+
+/// Joins the bound voice chats of `guild_id`, or of every guild if it's `None`.
+async fn join_bindings(ctx: Arc<Context>, guild_id: Option<GuildId>, force: bool) {
     let data = ctx.data.read().await;
     let pool = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
-    let mut query = sqlx::query!("SELECT * FROM guilds").fetch(pool);
-    while let Ok(row) = query.try_next().await {
-        match row {
-            Some(row) => {
-                let guild_id = row.guild_id;
 
-                unsafe {
-                    if !force {
-                        let already_connected = songbird::get(&ctx)
-                            .await
-                            .unwrap_unchecked()
-                            .get::<u64>(guild_id as u64)
-                            .is_some();
-                        if already_connected {
-                            continue;
-                        };
-                    }
+    let rows = match bindings_with_premium(pool, guild_id).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            warn!("failed to fetch bindings: {}", e);
+            return;
+        }
+    };
+    let mut guilds: BTreeMap<GuildId, Vec<Binding>> = BTreeMap::new();
+    for (binding, premium_level) in rows {
+        let bindings = guilds.entry(binding.guild_id).or_default();
+        // guilds that lost premium keep their bindings, but only as many as their premium
+        // level allows get joined
+        if bindings.len() < max_bindings(premium_level) {
+            bindings.push(binding);
+        }
+    }
+
+    for (guild_id, bindings) in guilds {
+        let contexts = voice_contexts(&ctx, guild_id).await;
+        let mut connected = Vec::with_capacity(contexts.len());
+        for c in contexts.iter() {
+            if let Some(id) = connected_channel(c, guild_id).await {
+                connected.push((id, c));
+            }
+        }
+
+        let chosen = pick_bindings(
+            &ctx,
+            guild_id,
+            &bindings,
+            &connected.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            contexts.len(),
+        )
+        .await;
+
+        // free up the connections held in voice chats that lost their spot first, so `bind`
+        // has somewhere to put the new ones
+        for (id, c) in connected.iter() {
+            if !chosen.iter().any(|b| b.voice_channel.0 == *id) {
+                let manager = unsafe { songbird::get(c).await.unwrap_unchecked() };
+                if let Err(e) = manager.remove(guild_id).await {
+                    warn!("failed to leave VC {} in {}: {}", id, guild_id, e);
                 }
+            }
+        }
 
-                let vc_id = match row.default_bind {
-                    Some(v) => v,
-                    None => {
-                        continue;
-                    }
-                };
-                let result_id = match row.output_channel {
-                    Some(v) => v,
-                    None => {
-                        continue;
-                    }
-                };
+        for binding in chosen {
+            if !force
+                && connected
+                    .iter()
+                    .any(|(id, _)| *id == binding.voice_channel.0)
+            {
+                continue;
+            }
 
-                if let Err(e) = bind(
-                    &ctx,
-                    (vc_id as u64).into(),
-                    (result_id as u64).into(),
-                    (guild_id as u64).into(),
-                )
-                .await
-                {
-                    warn!("failed to join VC in {}: {}", guild_id, e);
-                    if let Err(e) = ChannelId(result_id.try_into().unwrap()).send_message(&ctx, |m | {
+            if let Err(e) = bind(
+                &ctx,
+                binding.voice_channel,
+                binding.output_channel,
+                guild_id,
+            )
+            .await
+            {
+                warn!("failed to join VC in {}: {}", guild_id, e);
+                if let Err(e) = binding
+                    .output_channel
+                    .send_message(&ctx, |m| {
                         m.embed(|embed| {
                             embed
                                 .color(11534368)
-                                .description(format!("I can't join the voice chat you have set up! {}", e))
+                                .description(format!(
+                                    "I can't join the voice chat you have set up! {}",
+                                    e
+                                ))
                                 .field("Need help fixing it?", "https://discord.gg/xSpNJSjNhq", true)
                                 .footer(|c| {
-                                    c.text("This message will continually be sent until this is fixed.")
+                                    c.text(
+                                        "This message will continually be sent until this is fixed.",
+                                    )
                                 })
                                 .title("Error while joining VC!")
                         })
-                    }).await {
-                        warn!("couldn't warn users about error in {}: {}", guild_id, e);
-                        // if these queries fail so be it
-                        let _ = sqlx::query!("DELETE FROM guilds WHERE guild_id = $1", guild_id).execute(pool).await;
-                        let _ = sqlx::query!("DELETE FROM channels WHERE channel_id = $1", result_id).execute(pool).await;
-                    }
-                } else {
-                    debug!("joined VC in {} successfully", guild_id);
-                };
-            }
-            None => {
-                break;
-            }
+                    })
+                    .await
+                {
+                    warn!("couldn't warn users about error in {}: {}", guild_id, e);
+                    // if this fails so be it
+                    let _ = forget_binding(pool, &binding).await;
+                }
+            } else {
+                debug!("joined VC in {} successfully", guild_id);
+            };
         }
     }
 }
+
+/// Picks which of a guild's `bindings` should have a voice connection, given `slots`
+/// connections to go around (the main bot plus every worker in the guild).
+///
+/// Bound voice chats are ranked like this, and the first `slots` of them are returned:
+/// * voice chats a connection is already in that still have people in them, so nobody gets
+///   cut off mid-meeting
+/// * then the voice chats with the most people in them
+/// * then, with nobody around, the voice chats that already have a connection, in the order
+///   they were bound
+async fn pick_bindings(
+    ctx: &Context,
+    guild_id: GuildId,
+    bindings: &[Binding],
+    connected: &[u64],
+    slots: usize,
+) -> Vec<Binding> {
+    let humans = ctx
+        .cache
+        .guild_field(guild_id, |g| {
+            bindings
+                .iter()
+                .map(|b| {
+                    g.voice_states
+                        .values()
+                        .filter(|s| s.channel_id == Some(b.voice_channel))
+                        .filter(|s| !g.members.get(&s.user_id).map_or(false, |m| m.user.bot))
+                        .count()
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_else(|| vec![0; bindings.len()]);
+
+    let mut ranked: Vec<(bool, usize, bool, usize)> = bindings
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            let is_connected = connected.contains(&b.voice_channel.0);
+            (is_connected && humans[i] > 0, humans[i], is_connected, i)
+        })
+        .collect();
+    // sort by everything but the index descending, then the index ascending
+    ranked.sort_by(|a, b| {
+        (b.0, b.1, b.2)
+            .cmp(&(a.0, a.1, a.2))
+            .then_with(|| a.3.cmp(&b.3))
+    });
+
+    ranked
+        .into_iter()
+        .take(slots)
+        .map(|(_, _, _, i)| bindings[i])
+        .collect()
+}
@@ -1,13 +1,13 @@
 use super::audio_handler::Receiver;
-use scripty_db::PgPoolKey;
+use crate::{connected_channel, voice_contexts, SessionOptions, TranscriptFormat};
+use scripty_db::{fetch_guild_config, fetch_output_channel, PgPoolKey};
 use serenity::{
     http::CacheHttp,
     model::prelude::{Channel, ChannelId, ChannelType, GuildId},
     prelude::Context,
 };
 use songbird::CoreEvent;
-use sqlx::query;
-use std::{convert::TryInto, sync::Arc};
+use std::sync::Arc;
 
 pub async fn bind(
     ctx: &Context,
@@ -36,62 +36,66 @@ pub async fn bind(
         _ => return Err("Not a guild channel.".to_string()),
     };
 
-    let premium_level: u8 = match query!(
-        "SELECT premium_level FROM guilds WHERE guild_id = $1",
-        i64::from(guild_id)
-    )
-    .fetch_optional(unsafe { db.unwrap_unchecked() })
-    .await
-    {
-        Ok(result) => {
-            let result = match result {
-                Some(r) => r,
-                None => return Err("Guild not found in DB.".to_string()),
-            };
-
-            match result.premium_level.try_into() {
-                Ok(r) => r,
-                Err(e) => return Err(format!("Failed to convert premium level to a u8: {}", e)),
-            }
-        }
-        Err(e) => return Err(format!("DB returned a error: {:?}", e)),
+    let db = unsafe { db.unwrap_unchecked() };
+    let guild = match fetch_guild_config(db, guild_id).await {
+        Ok(Some(g)) => g,
+        Ok(None) => return Err("Guild not found in DB.".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let premium_level = guild.premium_level;
+    let session_options = SessionOptions {
+        threads: guild.session_threads,
+        transcript_format: guild
+            .transcript_format
+            .as_deref()
+            .and_then(TranscriptFormat::from_name),
     };
 
-    let (token, id): (String, u64) = match query!(
-        "SELECT webhook_token, webhook_id FROM channels WHERE channel_id = $1",
-        i64::from(transcription_channel)
-    )
-    .fetch_optional(unsafe { db.unwrap_unchecked() })
-    .await
-    {
-        Ok(result) => {
-            let result = match result {
-                Some(r) => r,
-                None => return Err("Channel not found in DB.".to_string()),
-            };
-
-            let token = match result.webhook_token {
-                Some(r) => r,
-                None => return Err("Couldn't get webhook token from DB".to_string()),
-            };
-            let id = match result.webhook_id {
-                Some(r) => match r.try_into() {
-                    Ok(r) => r,
-                    Err(e) => return Err(format!("Couldn't convert webhook ID to i64: {}", e)),
-                },
-                None => return Err("Couldn't get webhook ID".to_string()),
-            };
-            (token, id)
-        }
-        Err(e) => return Err(format!("DB returned a error: {:?}", e)),
+    let webhook = match fetch_output_channel(db, transcription_channel).await {
+        Ok(Some(c)) => match c.webhook() {
+            Some(w) => w,
+            None => return Err("Couldn't get webhook from DB".to_string()),
+        },
+        Ok(None) => return Err("Channel not found in DB.".to_string()),
+        Err(e) => return Err(e.to_string()),
     };
 
-    let webhook = match ctx.http.http().get_webhook_with_token(id, &*token).await {
+    let webhook = match ctx
+        .http
+        .http()
+        .get_webhook_with_token(webhook.id.0, &webhook.token)
+        .await
+    {
         Ok(w) => w,
         Err(e) => return Err(format!("Error while fetching webhook: {}", e)),
     };
 
-    let manager = songbird::get(ctx)
+    // the main bot and each worker can hold one call per guild: reuse whichever one is already
+    // in `bind_channel`, otherwise take the first free one, otherwise move the main bot
+    let mut voice_ctx = None;
+    let mut free_ctx = None;
+    let contexts = voice_contexts(ctx, guild_id).await;
+    for c in contexts.iter() {
+        match connected_channel(c, guild_id).await {
+            Some(id) if id == bind_channel.0 => {
+                voice_ctx = Some(c);
+                break;
+            }
+            Some(_) => {}
+            None => {
+                if free_ctx.is_none() {
+                    free_ctx = Some(c)
+                }
+            }
+        }
+    }
+    let voice_ctx = Arc::clone(
+        voice_ctx
+            .or(free_ctx)
+            .unwrap_or_else(|| unsafe { contexts.first().unwrap_unchecked() }),
+    );
+
+    let manager = songbird::get(&voice_ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
@@ -103,13 +107,23 @@ pub async fn bind(
             // NOTE: this skips listening for the actual connection result.
             let mut handler = handler_lock.lock().await;
 
-            let ctx1 = Arc::new(ctx.clone());
-
-            let receiver =
-                Receiver::new(webhook, ctx1, premium_level, guild_id == 675390855716274216).await;
+            let receiver = Receiver::new(
+                webhook,
+                voice_ctx,
+                premium_level,
+                guild_id == 675390855716274216,
+                bind_channel,
+                guild_id,
+                session_options,
+            )
+            .await;
 
             let _ = handler.mute(true).await;
 
+            // drop the receiver from any previous bind in this guild, otherwise both would
+            // transcribe (and post) everything said from now on
+            handler.remove_all_global_events();
+
             handler.add_global_event(CoreEvent::SpeakingStateUpdate.into(), receiver.clone());
             handler.add_global_event(CoreEvent::SpeakingUpdate.into(), receiver.clone());
             handler.add_global_event(CoreEvent::VoicePacket.into(), receiver.clone());
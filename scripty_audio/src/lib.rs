@@ -2,10 +2,24 @@
 #![feature(map_first_last)]
 #![feature(once_cell)]
 
+mod attachments;
 mod audio_handler;
 mod auto_join;
 mod bind;
+mod live;
+mod quota;
+mod session;
+mod transcript;
+mod webhook;
+mod workers;
 
+pub use attachments::*;
 pub use audio_handler::*;
 pub use auto_join::*;
 pub use bind::*;
+pub use live::*;
+pub use quota::*;
+pub use session::*;
+pub use transcript::*;
+pub use webhook::*;
+pub use workers::*;
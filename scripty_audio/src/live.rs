@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use serenity::model::id::GuildId;
+use std::lazy::SyncLazy;
+use tokio::sync::broadcast;
+
+/// How many utterances a slow subscriber can fall behind by before it starts missing some.
+const FEED_CAPACITY: usize = 64;
+
+/// Something said in a voice chat, as sent to the guild's live feed.
+#[derive(Clone, Debug, Serialize)]
+pub struct LiveUtterance {
+    pub voice_channel: u64,
+    pub user_id: u64,
+    pub username: String,
+    pub avatar_url: String,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Guilds someone is watching the live feed of. Feeds are only kept around while they have
+/// subscribers.
+static LIVE_FEEDS: SyncLazy<DashMap<GuildId, broadcast::Sender<LiveUtterance>>> =
+    SyncLazy::new(DashMap::new);
+
+/// Subscribes to everything transcribed in `guild_id` from now on.
+pub fn subscribe_live_feed(guild_id: GuildId) -> broadcast::Receiver<LiveUtterance> {
+    LIVE_FEEDS
+        .entry(guild_id)
+        .or_insert_with(|| broadcast::channel(FEED_CAPACITY).0)
+        .subscribe()
+}
+
+/// Sends `utterance` to everyone watching `guild_id`'s live feed.
+pub fn publish_live(guild_id: GuildId, utterance: LiveUtterance) {
+    let delivered = match LIVE_FEEDS.get(&guild_id) {
+        Some(feed) => feed.send(utterance).is_ok(),
+        None => return,
+    };
+    if !delivered {
+        // everyone stopped watching
+        LIVE_FEEDS.remove_if(&guild_id, |_, feed| feed.receiver_count() == 0);
+    }
+}
+
+/// Disconnects everyone watching `guild_id`'s live feed, like when its token changes.
+pub fn close_live_feed(guild_id: GuildId) {
+    // dropping the sender ends every subscription
+    LIVE_FEEDS.remove(&guild_id);
+}
@@ -0,0 +1,315 @@
+use chrono::{Datelike, Utc};
+use dashmap::DashMap;
+use scripty_db::{
+    add_usage, claim_usage_notice, monthly_quota_ms, monthly_usage, PgPoolKey, UsageNotice,
+};
+use scripty_metrics::METRICS;
+use serenity::{
+    builder::CreateEmbed,
+    model::{
+        guild::Guild,
+        id::{GuildId, UserId},
+        permissions::Permissions,
+    },
+    prelude::Context,
+};
+use std::lazy::SyncLazy;
+use tracing::warn;
+
+/// How many people a notice is tried on before giving up, in case most have their DMs closed.
+const MAX_NOTICE_RECIPIENTS: usize = 5;
+
+/// A guild's usage as of the last time it was fetched or added to.
+struct CachedUsage {
+    /// The month this is for, as returned by `current_month`.
+    month: i32,
+    used: u64,
+    /// Whether this month's `Warning` notice was already claimed, by this process or another one.
+    warning_claimed: bool,
+    /// Whether this month's `Paused` notice was already claimed, by this process or another one.
+    paused_claimed: bool,
+}
+
+impl CachedUsage {
+    fn new(month: i32, used: u64) -> Self {
+        Self {
+            month,
+            used,
+            warning_claimed: false,
+            paused_claimed: false,
+        }
+    }
+
+    fn claimed(&self, notice: UsageNotice) -> bool {
+        match notice {
+            UsageNotice::Warning => self.warning_claimed,
+            UsageNotice::Paused => self.paused_claimed,
+        }
+    }
+
+    fn set_claimed(&mut self, notice: UsageNotice) {
+        match notice {
+            UsageNotice::Warning => self.warning_claimed = true,
+            UsageNotice::Paused => self.paused_claimed = true,
+        }
+    }
+}
+
+/// Each guild's usage this month, so checking the quota doesn't take a trip to the database for
+/// every utterance. Entries are tagged with the month they're for, so they're fetched again once
+/// it's over.
+static MONTHLY_USAGE: SyncLazy<DashMap<GuildId, CachedUsage>> = SyncLazy::new(DashMap::new);
+
+/// The current month, counted from year 0.
+fn current_month() -> i32 {
+    let today = Utc::today();
+    today.year() * 12 + today.month0() as i32
+}
+
+/// Caches `used` as `guild_id`'s usage in `month`. Usage only goes up within a month, so a fetch
+/// that finishes after a later `add_usage` can't overwrite it with something older.
+fn remember_usage(guild_id: GuildId, month: i32, used: u64) {
+    let mut entry = MONTHLY_USAGE
+        .entry(guild_id)
+        .or_insert_with(|| CachedUsage::new(month, used));
+    if entry.month != month {
+        *entry = CachedUsage::new(month, used);
+    } else if entry.used < used {
+        entry.used = used;
+    }
+}
+
+/// Whether `guild_id`'s `notice` for `month` is known to have been claimed already.
+fn notice_claimed(guild_id: GuildId, month: i32, notice: UsageNotice) -> bool {
+    MONTHLY_USAGE
+        .get(&guild_id)
+        .filter(|u| u.month == month)
+        .map_or(false, |u| u.claimed(notice))
+}
+
+/// Remembers that `guild_id`'s `notice` for `month` was claimed, so it isn't claimed again for
+/// every utterance after it.
+fn remember_notice(guild_id: GuildId, month: i32, notice: UsageNotice) {
+    if let Some(mut entry) = MONTHLY_USAGE.get_mut(&guild_id) {
+        if entry.month == month {
+            entry.set_claimed(notice);
+        }
+    }
+}
+
+/// Checks whether `guild_id` has any of this month's transcription quota left.
+///
+/// If it doesn't, the rejection is counted, and the first time this happens each month the
+/// guild's managers are told. Usage is only fetched from the DB the first time a guild is
+/// checked each month, `record_usage` keeps it up to date after that. DB errors let the
+/// transcription through: it's better to transcribe a little too much than to stop working
+/// because of a hiccup.
+pub async fn check_quota(ctx: &Context, guild_id: GuildId, premium_level: u8) -> bool {
+    let quota = match monthly_quota_ms(premium_level) {
+        Some(q) => q,
+        None => return true,
+    };
+
+    let month = current_month();
+    let cached = MONTHLY_USAGE
+        .get(&guild_id)
+        .filter(|u| u.month == month)
+        .map(|u| u.used);
+    let used = match cached {
+        Some(u) => u,
+        None => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+            match monthly_usage(db, guild_id).await {
+                Ok(u) => {
+                    remember_usage(guild_id, month, u);
+                    u
+                }
+                Err(e) => {
+                    warn!("failed to fetch usage of {}: {}", guild_id, e);
+                    return true;
+                }
+            }
+        }
+    };
+    if used < quota {
+        return true;
+    }
+
+    unsafe { METRICS.get().unwrap_unchecked() }
+        .quota_rejections
+        .inc();
+    send_notice(ctx, guild_id, UsageNotice::Paused, used, quota).await;
+    false
+}
+
+/// Adds `ms` milliseconds to `guild_id`'s usage this month, and lets its managers know if that
+/// crossed 80% or 100% of its quota.
+pub async fn record_usage(ctx: &Context, guild_id: GuildId, premium_level: u8, ms: u64) {
+    let month = current_month();
+    let data = ctx.data.read().await;
+    let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+    let used = match add_usage(db, guild_id, ms).await {
+        Ok(u) => u,
+        Err(e) => {
+            warn!("failed to record usage of {}: {}", guild_id, e);
+            return;
+        }
+    };
+    drop(data);
+    remember_usage(guild_id, month, used);
+
+    if let Some(quota) = monthly_quota_ms(premium_level) {
+        if used >= quota {
+            send_notice(ctx, guild_id, UsageNotice::Paused, used, quota).await;
+        } else if used * 5 >= quota * 4 {
+            send_notice(ctx, guild_id, UsageNotice::Warning, used, quota).await;
+        }
+    }
+}
+
+/// Who to tell about `guild_id`'s usage: its owner first, then members who can manage it. The
+/// guild has to be cached to find the managers, otherwise only the owner is fetched.
+async fn notice_recipients(ctx: &Context, guild_id: GuildId) -> Vec<UserId> {
+    let guild: Option<Guild> = ctx.cache.guild(guild_id).await;
+    let guild = match guild {
+        Some(g) => g,
+        None => {
+            return match guild_id.to_partial_guild(&ctx.http).await {
+                Ok(g) => vec![g.owner_id],
+                Err(e) => {
+                    warn!("failed to fetch {} to find its owner: {}", guild_id, e);
+                    Vec::new()
+                }
+            }
+        }
+    };
+
+    let mut recipients = vec![guild.owner_id];
+    recipients.extend(
+        guild
+            .members
+            .values()
+            .filter(|m| {
+                !m.user.bot
+                    && m.user.id != guild.owner_id
+                    && guild
+                        .member_permissions(m)
+                        .contains(Permissions::MANAGE_GUILD)
+            })
+            .map(|m| m.user.id),
+    );
+    recipients.truncate(MAX_NOTICE_RECIPIENTS);
+    recipients
+}
+
+/// DMs `notice` to whoever manages `guild_id`, unless it was already sent this month. It goes to
+/// the first of them that takes DMs, so a guild only gets each notice once.
+///
+/// Claimed notices are remembered alongside the cached usage, so once a guild is out of quota the
+/// utterances rejected after that don't each take a trip to the DB to find out it was told.
+async fn send_notice(ctx: &Context, guild_id: GuildId, notice: UsageNotice, used: u64, quota: u64) {
+    let month = current_month();
+    if notice_claimed(guild_id, month, notice) {
+        return;
+    }
+
+    {
+        let data = ctx.data.read().await;
+        let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+        let claimed = claim_usage_notice(db, guild_id, notice).await;
+        if claimed.is_ok() {
+            remember_notice(guild_id, month, notice);
+        }
+        match claimed {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                warn!("failed to claim usage notice for {}: {}", guild_id, e);
+                return;
+            }
+        }
+    }
+
+    let name = guild_id
+        .name(ctx)
+        .await
+        .unwrap_or_else(|| "One of your servers".to_string());
+    let (title, description) = match notice {
+        UsageNotice::Warning => (
+            format!("{} is running low on transcription time", name),
+            "Transcription will pause once it's all used up, until the start of next month.",
+        ),
+        UsageNotice::Paused => (
+            format!("{} is out of transcription time", name),
+            "Transcription is paused until the start of next month.",
+        ),
+    };
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(title)
+        .description(format!(
+            "{} of {} minutes used this month. {}\n\
+            Get premium for more: https://github.com/sponsors/tazz4843",
+            used / 60_000,
+            quota / 60_000,
+            description
+        ))
+        .footer(|f| f.text("Check how much is left at any time with `usage` in the server."));
+
+    for user_id in notice_recipients(ctx, guild_id).await {
+        let channel = match user_id.create_dm_channel(&ctx.http).await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("failed to open a DM with {}: {}", user_id, e);
+                continue;
+            }
+        };
+        let result = channel
+            .send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    *e = embed.clone();
+                    e
+                })
+            })
+            .await;
+        match result {
+            Ok(_) => return,
+            Err(e) => warn!("failed to send usage notice to {}: {}", user_id, e),
+        }
+    }
+    warn!(
+        "nobody managing {} could be sent its usage notice",
+        guild_id
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claimed_notices_last_until_the_month_changes() {
+        let guild_id = GuildId(1);
+        remember_usage(guild_id, 100, 5);
+        assert!(!notice_claimed(guild_id, 100, UsageNotice::Paused));
+
+        remember_notice(guild_id, 100, UsageNotice::Paused);
+        remember_usage(guild_id, 100, 10);
+        assert!(notice_claimed(guild_id, 100, UsageNotice::Paused));
+        assert!(!notice_claimed(guild_id, 100, UsageNotice::Warning));
+
+        remember_usage(guild_id, 101, 1);
+        assert!(!notice_claimed(guild_id, 101, UsageNotice::Paused));
+        assert!(!notice_claimed(guild_id, 100, UsageNotice::Paused));
+    }
+
+    #[test]
+    fn stale_usage_doesnt_overwrite_newer_usage() {
+        let guild_id = GuildId(2);
+        remember_usage(guild_id, 100, 10);
+        remember_usage(guild_id, 100, 5);
+        assert_eq!(MONTHLY_USAGE.get(&guild_id).unwrap().used, 10);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{TranscriptFormat, Utterance};
+use chrono::{DateTime, Utc};
+use scripty_db::{finish_session, start_session, PgPoolKey};
+use serenity::{
+    http::AttachmentType,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::{Context, Mentionable},
+};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tracing::warn;
+
+/// How a guild wants its sessions handled, as stored in the `guilds` table.
+#[derive(Clone, Copy)]
+pub struct SessionOptions {
+    /// Whether each session gets its own thread in the output channel.
+    pub threads: bool,
+    /// What format to upload the transcript in when a session ends, if at all.
+    pub transcript_format: Option<TranscriptFormat>,
+}
+
+/// One stretch of people talking in a bound voice chat, from the first person showing up to the
+/// last one leaving.
+///
+/// Sessions end when they're dropped, which also covers the bot leaving the voice chat or being
+/// rebound: songbird drops the `Receiver` holding the session either way. Transcriptions still
+/// running hold on to the session too, so they make it into the transcript.
+pub struct Session {
+    ctx: Arc<Context>,
+    session_id: Option<i64>,
+    voice_channel: ChannelId,
+    output_channel: ChannelId,
+    thread_id: Option<ChannelId>,
+    transcript_format: Option<TranscriptFormat>,
+    started_at: DateTime<Utc>,
+    log: Mutex<SessionLog>,
+}
+
+#[derive(Default)]
+struct SessionLog {
+    participants: HashSet<UserId>,
+    utterances: Vec<Utterance>,
+    ms_transcribed: u64,
+}
+
+impl Session {
+    /// Starts a session in `voice_channel`, and records it in the `sessions` table.
+    ///
+    /// If the guild has threads turned on, a thread is created in `output_channel` for this
+    /// session's transcriptions, named after the date and the voice chat.
+    pub async fn start(
+        ctx: Arc<Context>,
+        guild_id: GuildId,
+        voice_channel: ChannelId,
+        output_channel: ChannelId,
+        options: SessionOptions,
+    ) -> Self {
+        let thread_id = if options.threads {
+            match create_thread(&ctx, voice_channel, output_channel).await {
+                Ok(t) => Some(t),
+                Err(e) => {
+                    warn!(
+                        "failed to create session thread in {}: {}",
+                        output_channel, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let session_id = {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+            match start_session(db, guild_id, voice_channel, output_channel, thread_id).await {
+                Ok(session_id) => Some(session_id),
+                Err(e) => {
+                    warn!("failed to record session in {}: {}", guild_id, e);
+                    None
+                }
+            }
+        };
+
+        Self {
+            ctx,
+            session_id,
+            voice_channel,
+            output_channel,
+            thread_id,
+            transcript_format: options.transcript_format,
+            started_at: Utc::now(),
+            log: Mutex::new(SessionLog::default()),
+        }
+    }
+
+    /// The thread this session's transcriptions go to, if it has one.
+    pub fn thread_id(&self) -> Option<ChannelId> {
+        self.thread_id
+    }
+
+    /// Adds `user_id` to the people who took part in this session.
+    pub fn add_participant(&self, user_id: UserId) {
+        self.log
+            .lock()
+            .expect("thread panicked while holding session lock")
+            .participants
+            .insert(user_id);
+    }
+
+    /// Records something `user_id` said. `audio_ms` is how much audio it took to say it.
+    pub fn record(&self, user_id: UserId, username: &str, audio_ms: u64, text: &str) {
+        let offset_ms = (Utc::now() - self.started_at).num_milliseconds().max(0) as u64;
+
+        let mut log = self
+            .log
+            .lock()
+            .expect("thread panicked while holding session lock");
+        log.participants.insert(user_id);
+        log.ms_transcribed += audio_ms;
+        log.utterances.push(Utterance {
+            user_id: user_id.0,
+            username: username.to_string(),
+            offset_ms: offset_ms.saturating_sub(audio_ms),
+            duration_ms: audio_ms,
+            text: text.to_string(),
+        });
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let log = std::mem::take(
+            &mut *self
+                .log
+                .lock()
+                .expect("thread panicked while holding session lock"),
+        );
+        let ctx = Arc::clone(&self.ctx);
+        let session_id = self.session_id;
+        let voice_channel = self.voice_channel;
+        let output_channel = self.output_channel;
+        let thread_id = self.thread_id;
+        let transcript_format = self.transcript_format;
+        let started_at = self.started_at;
+
+        tokio::spawn(async move {
+            let participants: Vec<i64> = log.participants.iter().map(|u| u.0 as i64).collect();
+
+            if let Some(session_id) = session_id {
+                let data = ctx.data.read().await;
+                let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+                if let Err(e) = finish_session(
+                    db,
+                    session_id,
+                    log.participants.iter().copied(),
+                    log.utterances.len(),
+                    log.ms_transcribed,
+                )
+                .await
+                {
+                    warn!("failed to finish session {}: {}", session_id, e);
+                }
+            }
+
+            if let (Some(format), false) = (transcript_format, log.utterances.is_empty()) {
+                let voice_name = voice_channel
+                    .name(&ctx.cache)
+                    .await
+                    .unwrap_or_else(|| "voice chat".to_string());
+                let transcript = format.render(
+                    &voice_name,
+                    started_at,
+                    Utc::now(),
+                    &participants,
+                    &log.utterances,
+                );
+                let filename = format!(
+                    "transcript-{}.{}",
+                    started_at.format("%Y-%m-%d-%H%M"),
+                    format.extension()
+                );
+
+                if let Err(e) = thread_id
+                    .unwrap_or(output_channel)
+                    .send_files(
+                        &ctx,
+                        vec![AttachmentType::Bytes {
+                            data: Cow::from(transcript.into_bytes()),
+                            filename,
+                        }],
+                        |m| {
+                            m.content(format!(
+                                "Transcript of the session in {}.",
+                                voice_channel.mention()
+                            ))
+                        },
+                    )
+                    .await
+                {
+                    warn!("failed to upload transcript to {}: {}", output_channel, e);
+                }
+            }
+
+            if let Some(thread_id) = thread_id {
+                if let Err(e) = thread_id.edit_thread(&ctx, |t| t.archived(true)).await {
+                    warn!("failed to archive session thread {}: {}", thread_id, e);
+                }
+            }
+        });
+    }
+}
+
+async fn create_thread(
+    ctx: &Context,
+    voice_channel: ChannelId,
+    output_channel: ChannelId,
+) -> Result<ChannelId, serenity::Error> {
+    let name = format!(
+        "{} {}",
+        Utc::now().format("%Y-%m-%d %H:%M"),
+        voice_channel
+            .name(&ctx.cache)
+            .await
+            .unwrap_or_else(|| "voice chat".to_string())
+    );
+    // thread names can't be any longer than 100 characters
+    let name: String = name.chars().take(100).collect();
+
+    let start_message = output_channel
+        .send_message(ctx, |m| {
+            m.content(format!(
+                "Transcribing {} in the thread below.",
+                voice_channel.mention()
+            ))
+        })
+        .await?;
+    let thread = output_channel
+        .create_public_thread(ctx, start_message.id, |t| {
+            t.name(name).auto_archive_duration(1440)
+        })
+        .await?;
+
+    Ok(thread.id)
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Something one person said during a session.
+#[derive(Clone, Serialize)]
+pub struct Utterance {
+    pub user_id: u64,
+    pub username: String,
+    /// How long after the session started they started talking, in milliseconds.
+    pub offset_ms: u64,
+    pub duration_ms: u64,
+    pub text: String,
+}
+
+/// The formats a session's transcript can be uploaded in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonTranscript<'a> {
+    voice_channel: &'a str,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    participants: &'a [i64],
+    utterances: &'a [Utterance],
+}
+
+impl TranscriptFormat {
+    /// Parses a format as stored in the DB or typed in a command. Returns `None` for anything
+    /// unknown.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "text" | "txt" | "plain" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// The name this format is stored under in the DB.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+
+    /// The file extension to upload transcripts in this format with.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Text => "txt",
+            Self::Json => "json",
+        }
+    }
+
+    /// Renders a whole session's transcript in this format.
+    pub fn render(
+        self,
+        voice_channel: &str,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+        participants: &[i64],
+        utterances: &[Utterance],
+    ) -> String {
+        let mut out = String::new();
+        // writing to a String never fails, so the results below are ignored
+        match self {
+            Self::Markdown => {
+                let _ = writeln!(out, "# Transcript of {}\n", escape_markdown(voice_channel));
+                let _ = writeln!(
+                    out,
+                    "{} to {} UTC, {} participant(s)\n",
+                    started_at.format("%Y-%m-%d %H:%M:%S"),
+                    ended_at.format("%Y-%m-%d %H:%M:%S"),
+                    participants.len()
+                );
+                for u in utterances {
+                    let _ = writeln!(
+                        out,
+                        "**{}** `{}`: {}\n",
+                        escape_markdown(&u.username),
+                        format_offset(u.offset_ms),
+                        u.text
+                    );
+                }
+            }
+            Self::Text => {
+                let _ = writeln!(out, "Transcript of {}", voice_channel);
+                let _ = writeln!(
+                    out,
+                    "{} to {} UTC, {} participant(s)\n",
+                    started_at.format("%Y-%m-%d %H:%M:%S"),
+                    ended_at.format("%Y-%m-%d %H:%M:%S"),
+                    participants.len()
+                );
+                for u in utterances {
+                    let _ = writeln!(
+                        out,
+                        "[{}] {}: {}",
+                        format_offset(u.offset_ms),
+                        u.username,
+                        u.text
+                    );
+                }
+            }
+            Self::Json => {
+                out = serde_json::to_string_pretty(&JsonTranscript {
+                    voice_channel,
+                    started_at,
+                    ended_at,
+                    participants,
+                    utterances,
+                })
+                .expect("transcripts are always valid JSON");
+            }
+        }
+        out
+    }
+}
+
+/// Formats an offset in milliseconds as `HH:MM:SS`.
+fn format_offset(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+/// Escapes the characters that would change how `text` is formatted in Markdown, so a username
+/// like `__init__` shows up as it is rather than in bold.
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '~') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn render(format: TranscriptFormat) -> String {
+        let utterances = [
+            Utterance {
+                user_id: 1,
+                username: "alice".to_string(),
+                offset_ms: 1_500,
+                duration_ms: 2_000,
+                text: "hello there".to_string(),
+            },
+            Utterance {
+                user_id: 2,
+                username: "*bob_the_`builder`*".to_string(),
+                offset_ms: 3_723_000,
+                duration_ms: 500,
+                text: "hi".to_string(),
+            },
+        ];
+        format.render(
+            "general",
+            Utc.ymd(2021, 8, 1).and_hms(12, 0, 0),
+            Utc.ymd(2021, 8, 1).and_hms(13, 5, 0),
+            &[1, 2],
+            &utterances,
+        )
+    }
+
+    #[test]
+    fn offsets_are_formatted() {
+        assert_eq!(format_offset(0), "00:00:00");
+        assert_eq!(format_offset(999), "00:00:00");
+        assert_eq!(format_offset(61_000), "00:01:01");
+        assert_eq!(format_offset(3_723_000), "01:02:03");
+        assert_eq!(format_offset(100 * 3_600_000), "100:00:00");
+    }
+
+    #[test]
+    fn markdown_is_rendered() {
+        assert_eq!(
+            render(TranscriptFormat::Markdown),
+            "# Transcript of general\n\n\
+            2021-08-01 12:00:00 to 2021-08-01 13:05:00 UTC, 2 participant(s)\n\n\
+            **alice** `00:00:01`: hello there\n\n\
+            **\\*bob\\_the\\_\\`builder\\`\\*** `01:02:03`: hi\n\n"
+        );
+    }
+
+    #[test]
+    fn text_is_rendered() {
+        assert_eq!(
+            render(TranscriptFormat::Text),
+            "Transcript of general\n\
+            2021-08-01 12:00:00 to 2021-08-01 13:05:00 UTC, 2 participant(s)\n\n\
+            [00:00:01] alice: hello there\n\
+            [01:02:03] *bob_the_`builder`*: hi\n"
+        );
+    }
+
+    #[test]
+    fn json_is_rendered() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render(TranscriptFormat::Json)).unwrap();
+        assert_eq!(json["voice_channel"], "general");
+        assert_eq!(json["started_at"], "2021-08-01T12:00:00Z");
+        assert_eq!(json["ended_at"], "2021-08-01T13:05:00Z");
+        assert_eq!(json["participants"], serde_json::json!([1, 2]));
+        assert_eq!(json["utterances"][1]["username"], "*bob_the_`builder`*");
+        assert_eq!(json["utterances"][1]["offset_ms"], 3_723_000);
+        assert_eq!(json["utterances"][0]["duration_ms"], 2_000);
+    }
+
+    #[test]
+    fn formats_round_trip_through_their_names() {
+        for &format in &[
+            TranscriptFormat::Markdown,
+            TranscriptFormat::Text,
+            TranscriptFormat::Json,
+        ] {
+            assert_eq!(TranscriptFormat::from_name(format.name()), Some(format));
+            assert_eq!(
+                TranscriptFormat::from_name(format.extension()),
+                Some(format)
+            );
+        }
+        assert_eq!(
+            TranscriptFormat::from_name("TXT"),
+            Some(TranscriptFormat::Text)
+        );
+        assert_eq!(TranscriptFormat::from_name("pdf"), None);
+    }
+}
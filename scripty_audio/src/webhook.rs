@@ -0,0 +1,53 @@
+use scripty_utils::ReqwestClient;
+use serenity::{
+    builder::ExecuteWebhook,
+    model::{id::ChannelId, webhook::Webhook},
+    prelude::Context,
+    utils::hashmap_to_json_map,
+};
+
+/// Executes `webhook` with the contents of `execute`, posting into `thread_id` if there is one.
+///
+/// Serenity's `Webhook::execute` has no way to target a thread, so messages headed for one go
+/// straight to Discord's API through reqwest instead.
+pub async fn execute_webhook(
+    ctx: &Context,
+    webhook: &Webhook,
+    thread_id: Option<ChannelId>,
+    execute: ExecuteWebhook,
+) -> Result<(), String> {
+    let thread_id = match thread_id {
+        Some(t) => t,
+        None => {
+            return webhook
+                .execute(ctx, false, |m| {
+                    *m = execute;
+                    m
+                })
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    };
+
+    let token = match webhook.token.as_ref() {
+        Some(t) => t,
+        None => return Err("webhook has no token".to_string()),
+    };
+    let client = match ctx.data.read().await.get::<ReqwestClient>() {
+        Some(c) => c.clone(),
+        None => return Err("no reqwest client in the type map".to_string()),
+    };
+
+    client
+        .post(format!(
+            "https://discord.com/api/v9/webhooks/{}/{}?thread_id={}",
+            webhook.id, token, thread_id
+        ))
+        .json(&hashmap_to_json_map(execute.0))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,44 @@
+use dashmap::DashMap;
+use serenity::{
+    model::id::{GuildId, UserId},
+    prelude::Context,
+};
+use std::{lazy::SyncLazy, sync::Arc};
+use tracing::info;
+
+/// Every worker bot that has finished connecting, keyed by the worker's user ID.
+///
+/// Songbird only allows one call per guild per bot user, so workers are what let a single guild
+/// have more than one voice chat transcribed at the same time.
+static WORKERS: SyncLazy<DashMap<UserId, Arc<Context>>> = SyncLazy::new(DashMap::new);
+
+/// Makes a worker available for voice connections. Call this from the worker's `ready` event.
+pub async fn register_worker(ctx: Context) {
+    let user_id = ctx.cache.current_user_id().await;
+    info!("worker {} is ready", user_id);
+    WORKERS.insert(user_id, Arc::new(ctx));
+}
+
+/// Returns every context that can hold a voice connection in `guild_id`: the main bot first,
+/// followed by each worker that is also in the guild.
+pub async fn voice_contexts(ctx: &Context, guild_id: GuildId) -> Vec<Arc<Context>> {
+    let mut contexts = vec![Arc::new(ctx.clone())];
+
+    let workers: Vec<Arc<Context>> = WORKERS.iter().map(|w| Arc::clone(w.value())).collect();
+    for worker in workers {
+        if worker.cache.guild_field(guild_id, |_| ()).await.is_some() {
+            contexts.push(worker);
+        }
+    }
+
+    contexts
+}
+
+/// Gets the voice chat `ctx`'s bot user is connected to in `guild_id`, if any.
+pub async fn connected_channel(ctx: &Context, guild_id: GuildId) -> Option<u64> {
+    let manager = unsafe { songbird::get(ctx).await.unwrap_unchecked() };
+    match manager.get(guild_id) {
+        Some(call) => call.lock().await.current_channel().map(|c| c.0),
+        None => None,
+    }
+}
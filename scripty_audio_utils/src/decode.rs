@@ -0,0 +1,538 @@
+//! Decoding audio files into what the model takes: mono 16 bit PCM at `SAMPLE_RATE`.
+//!
+//! WAV, FLAC, MP3 and Ogg/Vorbis are decoded with symphonia. It doesn't do Opus, so Ogg/Opus
+//! (which Discord voice messages are) and WebM/Opus (which browsers record) are demuxed here
+//! and decoded with libopus.
+use crate::{hz_to_hz, webm, SAMPLE_RATE};
+use audiopus::{coder::Decoder as OpusDecoder, Channels, SampleRate};
+use std::{error::Error, fmt, io::Cursor};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// The most samples one Opus packet can decode to, per channel: 120ms at 48kHz.
+const MAX_OPUS_FRAME: usize = 5760;
+
+/// The audio formats `decode_audio` understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    Mp3,
+    OggVorbis,
+    OggOpus,
+    WebmOpus,
+}
+
+impl AudioFormat {
+    /// Works out the format of `data` from how it starts.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+            Some(Self::Wav)
+        } else if data.starts_with(b"fLaC") {
+            Some(Self::Flac)
+        } else if data.starts_with(b"OggS") {
+            // the first packet of the first page says what codec the stream is
+            let segments = *data.get(26)? as usize;
+            let packet = data.get(27 + segments..)?;
+            if packet.starts_with(b"OpusHead") {
+                Some(Self::OggOpus)
+            } else if packet.starts_with(b"\x01vorbis") {
+                Some(Self::OggVorbis)
+            } else {
+                None
+            }
+        } else if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+            Some(Self::WebmOpus)
+        } else if data.starts_with(b"ID3")
+            || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0)
+        {
+            Some(Self::Mp3)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wav => "WAV",
+            Self::Flac => "FLAC",
+            Self::Mp3 => "MP3",
+            Self::OggVorbis => "Ogg/Vorbis",
+            Self::OggOpus => "Ogg/Opus",
+            Self::WebmOpus => "WebM/Opus",
+        }
+    }
+
+    /// A file extension for this format, to help symphonia along.
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Mp3 => "mp3",
+            Self::OggVorbis | Self::OggOpus => "ogg",
+            Self::WebmOpus => "webm",
+        }
+    }
+}
+
+/// Why some audio couldn't be decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The audio isn't in a format that can be decoded.
+    Unsupported(String),
+    /// The audio is in a format that can be decoded, but it's broken.
+    Corrupt(String),
+    /// The audio is longer than it was allowed to be.
+    TooLong { max_ms: u64 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(e) => write!(f, "unsupported audio: {}", e),
+            Self::Corrupt(e) => write!(f, "corrupt audio: {}", e),
+            Self::TooLong { max_ms } => {
+                write!(f, "audio can be at most {} seconds long", max_ms / 1000)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Audio decoded by `decode_audio`.
+pub struct DecodedAudio {
+    /// Mono 16 bit PCM at `SAMPLE_RATE`.
+    pub samples: Vec<i16>,
+    pub format: AudioFormat,
+    pub source_sample_rate: u32,
+    pub source_channels: u16,
+}
+
+impl DecodedAudio {
+    /// How long this audio is, in milliseconds.
+    pub fn duration_ms(&self) -> u64 {
+        self.samples.len() as u64 * 1000 / SAMPLE_RATE as u64
+    }
+}
+
+/// Mixes decoded audio down to mono as it comes in, keeping an eye on how long it's getting.
+struct Downmix {
+    samples: Vec<i16>,
+    sample_rate: u32,
+    channels: u16,
+    max_ms: u64,
+}
+
+impl Downmix {
+    fn new(sample_rate: u32, channels: u16, max_ms: u64) -> Self {
+        Self {
+            samples: Vec::new(),
+            sample_rate,
+            channels,
+            max_ms,
+        }
+    }
+
+    /// Adds interleaved samples with `self.channels` channels.
+    fn push(&mut self, interleaved: &[i16]) -> Result<(), DecodeError> {
+        let channels = self.channels.max(1) as usize;
+        self.samples.extend(
+            interleaved.chunks_exact(channels).map(|frame| {
+                (frame.iter().map(|s| *s as i32).sum::<i32>() / channels as i32) as i16
+            }),
+        );
+
+        if self.samples.len() as u64 * 1000
+            > self.max_ms.saturating_mul(u64::from(self.sample_rate))
+        {
+            Err(DecodeError::TooLong {
+                max_ms: self.max_ms,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish(self, format: AudioFormat) -> DecodedAudio {
+        let samples = if self.sample_rate == SAMPLE_RATE {
+            self.samples
+        } else {
+            hz_to_hz(
+                self.samples,
+                f64::from(self.sample_rate),
+                f64::from(SAMPLE_RATE),
+            )
+        };
+        DecodedAudio {
+            samples,
+            format,
+            source_sample_rate: self.sample_rate,
+            source_channels: self.channels,
+        }
+    }
+}
+
+/// Decodes an audio file to mono 16 bit PCM at `SAMPLE_RATE`, whatever its sample rate and
+/// channel count.
+///
+/// Fails with `DecodeError::TooLong` as soon as the audio gets longer than `max_ms`, rather
+/// than decoding all of it first. This is CPU heavy, so call it from a blocking thread.
+pub fn decode_audio(data: &[u8], max_ms: u64) -> Result<DecodedAudio, DecodeError> {
+    let format = AudioFormat::detect(data).ok_or_else(|| {
+        DecodeError::Unsupported(
+            "use WAV, FLAC, MP3, Ogg/Vorbis, Ogg/Opus or WebM/Opus".to_string(),
+        )
+    })?;
+
+    match format {
+        AudioFormat::OggOpus => decode_ogg_opus(data, max_ms),
+        AudioFormat::WebmOpus => decode_webm_opus(data, max_ms),
+        _ => decode_symphonia(data, format, max_ms),
+    }
+}
+
+fn decode_symphonia(
+    data: &[u8],
+    format: AudioFormat,
+    max_ms: u64,
+) -> Result<DecodedAudio, DecodeError> {
+    let corrupt = |e: SymphoniaError| DecodeError::Corrupt(format!("{}: {}", format.name(), e));
+
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(data.to_vec())), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(format.extension());
+    let mut reader = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(corrupt)?
+        .format;
+
+    let track = reader
+        .default_track()
+        .ok_or_else(|| DecodeError::Corrupt(format!("{} file has no audio", format.name())))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| DecodeError::Unsupported(format!("{}: {}", format.name(), e)))?;
+
+    let mut downmix: Option<Downmix> = None;
+    let mut buffer: Option<SampleBuffer<i16>> = None;
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(p) => p,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(corrupt(e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(d) => d,
+            // one bad frame isn't worth throwing the rest away for
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(corrupt(e)),
+        };
+        let spec = *decoded.spec();
+        // packets can decode to more samples than the first one did
+        let needed = decoded.capacity() * spec.channels.count();
+        if buffer.as_ref().map_or(false, |b| b.capacity() < needed) {
+            buffer = None;
+        }
+        let buffer =
+            buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        buffer.copy_interleaved_ref(decoded);
+        downmix
+            .get_or_insert_with(|| Downmix::new(spec.rate, spec.channels.count() as u16, max_ms))
+            .push(buffer.samples())?;
+    }
+
+    downmix
+        .map(|d| d.finish(format))
+        .ok_or_else(|| DecodeError::Corrupt(format!("{} file has no audio", format.name())))
+}
+
+/// Reads the channel count and pre-skip out of an `OpusHead` packet.
+fn parse_opus_head(head: &[u8]) -> Result<(u16, usize), DecodeError> {
+    if head.len() < 19 || !head.starts_with(b"OpusHead") {
+        return Err(DecodeError::Corrupt(
+            "Opus stream has no OpusHead".to_string(),
+        ));
+    }
+    let channels = u16::from(head[9]);
+    if channels != 1 && channels != 2 {
+        return Err(DecodeError::Unsupported(format!(
+            "Opus audio with {} channels, only mono and stereo are supported",
+            channels
+        )));
+    }
+    let pre_skip = u16::from_le_bytes([head[10], head[11]]) as usize;
+    Ok((channels, pre_skip))
+}
+
+/// Decodes Opus packets into `downmix`, dropping the first `pre_skip` samples of each channel
+/// like the format asks.
+fn decode_opus_packets<'a>(
+    packets: impl IntoIterator<Item = &'a [u8]>,
+    pre_skip: usize,
+    downmix: &mut Downmix,
+) -> Result<(), DecodeError> {
+    let channels = downmix.channels as usize;
+    let mut decoder = OpusDecoder::new(
+        SampleRate::Hz48000,
+        if channels == 1 {
+            Channels::Mono
+        } else {
+            Channels::Stereo
+        },
+    )
+    .map_err(|e| DecodeError::Corrupt(format!("couldn't set up the Opus decoder: {}", e)))?;
+
+    let mut output = vec![0; MAX_OPUS_FRAME * channels];
+    let mut to_skip = pre_skip;
+    for packet in packets {
+        let len = match decoder.decode(Some(packet), &mut output[..], false) {
+            Ok(l) => l,
+            // one bad packet isn't worth throwing the rest away for
+            Err(_) => continue,
+        };
+        let skipped = to_skip.min(len);
+        to_skip -= skipped;
+        downmix.push(&output[skipped * channels..len * channels])?;
+    }
+    Ok(())
+}
+
+fn decode_ogg_opus(data: &[u8], max_ms: u64) -> Result<DecodedAudio, DecodeError> {
+    let mut reader = ogg::PacketReader::new(Cursor::new(data));
+    let corrupt = |e: ogg::OggReadError| DecodeError::Corrupt(format!("Ogg/Opus: {}", e));
+
+    // only the first logical stream is decoded, the rest are skipped
+    let first = reader
+        .read_packet()
+        .map_err(corrupt)?
+        .ok_or_else(|| DecodeError::Corrupt("Ogg file is empty".to_string()))?;
+    let serial = first.stream_serial();
+    let (channels, pre_skip) = parse_opus_head(&first.data)?;
+
+    let mut packets = Vec::new();
+    let mut seen_tags = false;
+    while let Some(packet) = reader.read_packet().map_err(corrupt)? {
+        if packet.stream_serial() != serial {
+            continue;
+        }
+        // the packet right after OpusHead is OpusTags, which isn't audio
+        if !seen_tags {
+            seen_tags = true;
+            continue;
+        }
+        packets.push(packet.data);
+    }
+
+    let mut downmix = Downmix::new(48_000, channels, max_ms);
+    decode_opus_packets(packets.iter().map(|p| &p[..]), pre_skip, &mut downmix)?;
+    Ok(downmix.finish(AudioFormat::OggOpus))
+}
+
+fn decode_webm_opus(data: &[u8], max_ms: u64) -> Result<DecodedAudio, DecodeError> {
+    let track = webm::demux_opus(data)
+        .map_err(DecodeError::Corrupt)?
+        .ok_or_else(|| {
+            DecodeError::Unsupported("only WebM files with Opus audio are supported".to_string())
+        })?;
+
+    // the OpusHead is optional in WebM, fall back on the track's channel count without it
+    let (channels, pre_skip) = if track.head.is_empty() {
+        match track.channels {
+            0 | 1 => (1, 0),
+            2 => (2, 0),
+            n => {
+                return Err(DecodeError::Unsupported(format!(
+                    "Opus audio with {} channels, only mono and stereo are supported",
+                    n
+                )))
+            }
+        }
+    } else {
+        parse_opus_head(&track.head)?
+    };
+
+    let mut downmix = Downmix::new(48_000, channels, max_ms);
+    decode_opus_packets(track.packets, pre_skip, &mut downmix)?;
+    Ok(downmix.finish(AudioFormat::WebmOpus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16 bit PCM WAV file of `frames` frames of `frame`.
+    fn wav(sample_rate: u32, frame: &[i16], frames: usize) -> Vec<u8> {
+        let channels = frame.len() as u16;
+        let data_len = (frames * frame.len() * 2) as u32;
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16_u32.to_le_bytes());
+        out.extend_from_slice(&1_u16.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * u32::from(channels) * 2).to_le_bytes());
+        out.extend_from_slice(&(channels * 2).to_le_bytes());
+        out.extend_from_slice(&16_u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for _ in 0..frames {
+            for s in frame {
+                out.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// The start of an Ogg page holding one packet that starts with `packet`.
+    fn ogg_page(packet: &[u8]) -> Vec<u8> {
+        let mut out = b"OggS\x00\x02".to_vec();
+        out.extend_from_slice(&[0; 20]);
+        out.push(1);
+        out.push(packet.len() as u8);
+        out.extend_from_slice(packet);
+        out
+    }
+
+    fn is_rejected(result: Result<DecodedAudio, DecodeError>) -> bool {
+        matches!(
+            result,
+            Err(DecodeError::Corrupt(_)) | Err(DecodeError::Unsupported(_))
+        )
+    }
+
+    #[test]
+    fn formats_are_detected() {
+        assert_eq!(
+            AudioFormat::detect(&wav(16_000, &[0], 1)),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(
+            AudioFormat::detect(b"fLaC\x00\x00\x00\x22"),
+            Some(AudioFormat::Flac)
+        );
+        assert_eq!(
+            AudioFormat::detect(&ogg_page(b"OpusHead\x01\x02")),
+            Some(AudioFormat::OggOpus)
+        );
+        assert_eq!(
+            AudioFormat::detect(&ogg_page(b"\x01vorbis\x00\x00")),
+            Some(AudioFormat::OggVorbis)
+        );
+        assert_eq!(
+            AudioFormat::detect(&[0x1A, 0x45, 0xDF, 0xA3, 0x80]),
+            Some(AudioFormat::WebmOpus)
+        );
+        assert_eq!(AudioFormat::detect(b"ID3\x04"), Some(AudioFormat::Mp3));
+        assert_eq!(
+            AudioFormat::detect(&[0xFF, 0xFB, 0x90]),
+            Some(AudioFormat::Mp3)
+        );
+    }
+
+    #[test]
+    fn unknown_and_short_input_isnt_detected() {
+        assert_eq!(AudioFormat::detect(b""), None);
+        assert_eq!(AudioFormat::detect(b"hello world, not audio"), None);
+        // too short to hold WAVE
+        assert_eq!(AudioFormat::detect(b"RIFF\x00\x00"), None);
+        // an Ogg page cut off before its segment table
+        assert_eq!(AudioFormat::detect(b"OggS\x00\x02"), None);
+        // Ogg with a codec that isn't supported
+        assert_eq!(AudioFormat::detect(&ogg_page(b"\x80theora")), None);
+        assert_eq!(AudioFormat::detect(&[0xFF]), None);
+    }
+
+    #[test]
+    fn wav_is_mixed_down_and_resampled() {
+        let decoded = decode_audio(&wav(16_000, &[100, 300], 1600), 10_000).unwrap();
+        assert_eq!(decoded.format, AudioFormat::Wav);
+        assert_eq!(decoded.source_channels, 2);
+        assert_eq!(decoded.source_sample_rate, 16_000);
+        assert_eq!(decoded.duration_ms(), 100);
+        assert!(decoded.samples.iter().all(|s| *s == 200));
+
+        let decoded = decode_audio(&wav(48_000, &[0], 4800), 10_000).unwrap();
+        assert_eq!(decoded.source_sample_rate, 48_000);
+        assert!((decoded.samples.len() as i64 - 1600).abs() <= 2);
+    }
+
+    #[test]
+    fn long_audio_is_refused() {
+        assert!(matches!(
+            decode_audio(&wav(16_000, &[0], 16_000), 500),
+            Err(DecodeError::TooLong { max_ms: 500 })
+        ));
+    }
+
+    #[test]
+    fn garbage_is_unsupported() {
+        assert!(matches!(
+            decode_audio(b"definitely not audio", 1000),
+            Err(DecodeError::Unsupported(_))
+        ));
+        assert!(matches!(
+            decode_audio(&[], 1000),
+            Err(DecodeError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let full = wav(16_000, &[0], 16);
+        assert!(is_rejected(decode_audio(&full[..12], 1000)));
+        assert!(is_rejected(decode_audio(&full[..30], 1000)));
+        assert!(is_rejected(decode_audio(b"fLaC", 1000)));
+        assert!(is_rejected(decode_audio(b"fLaC\x00\x00\x00\x22\x12", 1000)));
+        assert!(is_rejected(decode_audio(b"ID3\x04\x00", 1000)));
+        // the page's checksum is wrong and its OpusHead is cut off
+        assert!(is_rejected(decode_audio(&ogg_page(b"OpusHead\x01"), 1000)));
+        // an EBML header whose size is cut off
+        assert!(is_rejected(decode_audio(
+            &[0x1A, 0x45, 0xDF, 0xA3, 0x08],
+            1000
+        )));
+        // a well formed WebM file with no tracks at all
+        assert!(is_rejected(decode_audio(
+            &[0x1A, 0x45, 0xDF, 0xA3, 0x80],
+            1000
+        )));
+    }
+
+    #[test]
+    fn opus_heads_are_checked() {
+        let mut head = b"OpusHead\x01\x02\x38\x01".to_vec();
+        head.extend_from_slice(&[0; 7]);
+        assert_eq!(parse_opus_head(&head).unwrap(), (2, 312));
+
+        assert!(matches!(
+            parse_opus_head(&head[..18]),
+            Err(DecodeError::Corrupt(_))
+        ));
+        assert!(matches!(
+            parse_opus_head(b"NotAHead\x01\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00"),
+            Err(DecodeError::Corrupt(_))
+        ));
+        head[9] = 6;
+        assert!(matches!(
+            parse_opus_head(&head),
+            Err(DecodeError::Unsupported(_))
+        ));
+    }
+}
@@ -1,9 +1,11 @@
-use deepspeech::{errors::DeepspeechError, Metadata, Model as DsModel};
+pub use deepspeech::Metadata;
+use deepspeech::{errors::DeepspeechError, Model as DsModel};
 use scripty_config::BotConfig;
 use std::{
     path::Path,
     sync::{Arc, RwLock},
 };
+use tokio::sync::OnceCell;
 
 // The model has been trained on this specific
 // sample rate. This is in Hz.
@@ -31,8 +33,10 @@ impl Model {
     pub fn speech_to_text_with_metadata(
         &self,
         buffer: &[i16],
+        num_results: u32,
     ) -> Result<Metadata, DeepspeechError> {
-        self.ds_model.speech_to_text_with_metadata(buffer, 1)
+        self.ds_model
+            .speech_to_text_with_metadata(buffer, num_results)
     }
 
     pub fn enable_external_scorer(&mut self, scorer_path: &Path) -> Result<(), DeepspeechError> {
@@ -73,6 +77,28 @@ pub fn load_model() -> Model {
     m
 }
 
+/// The model shared by voice chats, attachments and the API.
+static SHARED_MODEL: OnceCell<Arc<RwLock<Model>>> = OnceCell::new();
+
+/// Gets the shared model, loading it on a blocking thread if nothing has yet. Everything that
+/// asks while it's loading waits for that one load.
+pub async fn model() -> Arc<RwLock<Model>> {
+    Arc::clone(
+        SHARED_MODEL
+            .get_or_init(|| async {
+                tokio::task::spawn_blocking(|| Arc::new(RwLock::new(load_model())))
+                    .await
+                    .expect("Failed to spawn blocking!")
+            })
+            .await,
+    )
+}
+
+/// Whether the shared model has finished loading.
+pub fn model_loaded() -> bool {
+    SHARED_MODEL.initialized()
+}
+
 pub async fn run_stt(
     input_data: Vec<i16>,
     m: Arc<RwLock<Model>>,
@@ -91,7 +117,32 @@ pub async fn run_stt(
             .expect("a thread panicked while trying to load the model");
 
         // and finally run the actual speech to text algorithm
-        model.speech_to_text_with_metadata(&audio_buf)
+        model.speech_to_text_with_metadata(&audio_buf, 1)
+    })
+    .await
+    .expect("Failed to spawn blocking!")
+}
+
+/// Runs speech to text on mono audio at any sample rate, returning up to `num_results`
+/// candidate transcripts.
+pub async fn run_stt_mono(
+    input_data: Vec<i16>,
+    sample_rate: u32,
+    num_results: u32,
+    m: Arc<RwLock<Model>>,
+) -> Result<Metadata, DeepspeechError> {
+    tokio::task::spawn_blocking(move || {
+        let audio_buf = if sample_rate == SAMPLE_RATE {
+            input_data
+        } else {
+            super::hz_to_hz(input_data, sample_rate as f64, SAMPLE_RATE as f64)
+        };
+
+        let model = m
+            .read()
+            .expect("a thread panicked while trying to load the model");
+
+        model.speech_to_text_with_metadata(&audio_buf, num_results)
     })
     .await
     .expect("Failed to spawn blocking!")
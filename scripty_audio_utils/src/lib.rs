@@ -1,9 +1,12 @@
 #![feature(slice_as_chunks)]
 
+mod decode;
 mod deepspeech;
 mod interpolate;
 mod stereo_to_mono;
+mod webm;
 
 pub use crate::deepspeech::*;
+pub use decode::*;
 pub use interpolate::*;
 pub use stereo_to_mono::*;
@@ -0,0 +1,328 @@
+//! Just enough of a Matroska demuxer to pull the Opus packets out of a WebM file.
+//!
+//! The file is read as a flat list of elements: the master elements leading to tracks and
+//! blocks are stepped into rather than skipped, and everything else is skipped. That way
+//! clusters of unknown size, which browsers write when recording, work like any other.
+use std::convert::TryInto;
+
+const SEGMENT: u32 = 0x1853_8067;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const AUDIO: u32 = 0xE1;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43_B675;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// Master elements whose children are read rather than skipped.
+const STEP_INTO: [u32; 6] = [SEGMENT, TRACKS, TRACK_ENTRY, AUDIO, CLUSTER, BLOCK_GROUP];
+
+#[derive(Default)]
+struct Track {
+    number: u64,
+    codec_id: String,
+    codec_private: Vec<u8>,
+    channels: u64,
+}
+
+/// The Opus track of a WebM file.
+pub(crate) struct OpusTrack<'a> {
+    /// The track's `OpusHead`, if it has one.
+    pub head: Vec<u8>,
+    pub channels: u64,
+    pub packets: Vec<&'a [u8]>,
+}
+
+/// Reads a variable length integer, returning its value and how many bytes it took up.
+fn read_vint(data: &[u8], at: usize) -> Option<(u64, usize)> {
+    let first = *data.get(at)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let bytes = data.get(at..at + len)?;
+    let mut value = u64::from(first & (0xFF_u16 >> len) as u8);
+    for b in &bytes[1..] {
+        value = value << 8 | u64::from(*b);
+    }
+    Some((value, len))
+}
+
+/// Reads an element ID, which unlike other variable length integers keeps its length marker.
+fn read_id(data: &[u8], at: usize) -> Option<(u32, usize)> {
+    let len = data.get(at)?.leading_zeros() as usize + 1;
+    if len > 4 {
+        return None;
+    }
+    let id = data
+        .get(at..at + len)?
+        .iter()
+        .fold(0, |id, b| id << 8 | u32::from(*b));
+    Some((id, len))
+}
+
+/// Reads an element's size. The size is `None` if it's unknown, meaning the element runs until
+/// something that can't be inside it.
+fn read_size(data: &[u8], at: usize) -> Option<(Option<u64>, usize)> {
+    let (value, len) = read_vint(data, at)?;
+    let unknown = (1 << (7 * len)) - 1;
+    Some(((value != unknown).then(|| value), len))
+}
+
+fn read_uint(body: &[u8]) -> u64 {
+    body.iter().fold(0, |n, b| n << 8 | u64::from(*b))
+}
+
+/// Splits a `SimpleBlock` or `Block` into its track number and frames.
+fn block_frames(body: &[u8]) -> Option<(u64, Vec<&[u8]>)> {
+    let (track, len) = read_vint(body, 0)?;
+    // skip the 16 bit timecode
+    let flags = *body.get(len + 2)?;
+    let data = body.get(len + 3..)?;
+
+    let lacing = flags & 0x06;
+    if lacing == 0 {
+        return Some((track, vec![data]));
+    }
+
+    let count = *data.first()? as usize + 1;
+    let mut at = 1;
+    let mut sizes: Vec<usize> = Vec::with_capacity(count);
+    match lacing {
+        // Xiph lacing: each size is a run of 255s plus whatever's left
+        0x02 => {
+            for _ in 1..count {
+                let mut size = 0;
+                loop {
+                    let b = *data.get(at)?;
+                    at += 1;
+                    size += b as usize;
+                    if b != 255 {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+        // EBML lacing: the first size, then signed differences from the one before
+        0x06 => {
+            if count > 1 {
+                let (first, len) = read_vint(data, at)?;
+                at += len;
+                sizes.push(first.try_into().ok()?);
+                for _ in 2..count {
+                    let (raw, len) = read_vint(data, at)?;
+                    at += len;
+                    let bias = (1_i64 << (7 * len - 1)) - 1;
+                    let previous = *sizes.last()? as i64;
+                    sizes.push((previous + raw as i64 - bias).try_into().ok()?);
+                }
+            }
+        }
+        // fixed size lacing
+        _ => {
+            let rest = data.len().checked_sub(at)?;
+            if rest % count != 0 {
+                return None;
+            }
+            sizes = vec![rest / count; count - 1];
+        }
+    }
+
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        frames.push(data.get(at..at + size)?);
+        at += size;
+    }
+    frames.push(data.get(at..)?);
+    Some((track, frames))
+}
+
+/// Finds the Opus track in a WebM file and every packet in it. Returns `None` if there isn't
+/// one, and an error if the file is broken.
+pub(crate) fn demux_opus(data: &[u8]) -> Result<Option<OpusTrack<'_>>, String> {
+    let mut tracks: Vec<Track> = Vec::new();
+    let mut blocks: Vec<(u64, &[u8])> = Vec::new();
+
+    let mut at = 0;
+    while at < data.len() {
+        let (id, id_len) = read_id(data, at).ok_or("WebM file has a broken element ID")?;
+        let (size, size_len) =
+            read_size(data, at + id_len).ok_or("WebM file has a broken element size")?;
+        at += id_len + size_len;
+
+        if STEP_INTO.contains(&id) {
+            if id == TRACK_ENTRY {
+                tracks.push(Track::default());
+            }
+            continue;
+        }
+
+        let size = size.ok_or("WebM file has an element of unknown size")? as usize;
+        // a recording that was cut off is still worth transcribing up to where it stops
+        let body = &data[at..at.saturating_add(size).min(data.len())];
+        at = at.saturating_add(size);
+
+        match (id, tracks.last_mut()) {
+            (TRACK_NUMBER, Some(track)) => track.number = read_uint(body),
+            (CODEC_ID, Some(track)) => {
+                track.codec_id = String::from_utf8_lossy(body)
+                    .trim_end_matches('\0')
+                    .to_string()
+            }
+            (CODEC_PRIVATE, Some(track)) => track.codec_private = body.to_vec(),
+            (CHANNELS, Some(track)) => track.channels = read_uint(body),
+            (SIMPLE_BLOCK, _) | (BLOCK, _) => {
+                let (track, frames) = block_frames(body).ok_or("WebM file has a broken block")?;
+                blocks.extend(frames.into_iter().map(|f| (track, f)));
+            }
+            _ => {}
+        }
+    }
+
+    let track = match tracks.into_iter().find(|t| t.codec_id == "A_OPUS") {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    Ok(Some(OpusTrack {
+        head: track.codec_private,
+        channels: track.channels,
+        packets: blocks
+            .into_iter()
+            .filter(|(number, _)| *number == track.number)
+            .map(|(_, packet)| packet)
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `SimpleBlock` body for track 1 with the given lacing flags and lacing data.
+    fn block(lacing: u8, laced: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x81, 0x00, 0x00, 0x80 | lacing];
+        out.extend_from_slice(laced);
+        out
+    }
+
+    #[test]
+    fn vints_are_read() {
+        assert_eq!(read_vint(&[0x81], 0), Some((1, 1)));
+        assert_eq!(read_vint(&[0x40, 0x02], 0), Some((2, 2)));
+        assert_eq!(read_vint(&[0x00, 0x80], 1), Some((0, 1)));
+        assert_eq!(read_vint(&[0x01, 0, 0, 0, 0, 0, 0, 0x05], 0), Some((5, 8)));
+    }
+
+    #[test]
+    fn broken_vints_arent_read() {
+        assert_eq!(read_vint(&[], 0), None);
+        // no length marker in the first byte
+        assert_eq!(read_vint(&[0x00, 0x81], 0), None);
+        // says it's two bytes long but isn't
+        assert_eq!(read_vint(&[0x40], 0), None);
+        assert_eq!(read_vint(&[0x81], 1), None);
+    }
+
+    #[test]
+    fn sizes_are_read() {
+        assert_eq!(read_size(&[0x85], 0), Some((Some(5), 1)));
+        assert_eq!(read_size(&[0x40, 0x7F], 0), Some((Some(127), 2)));
+        // all ones means the size is unknown, whatever the length
+        assert_eq!(read_size(&[0xFF], 0), Some((None, 1)));
+        assert_eq!(read_size(&[0x7F, 0xFF], 0), Some((None, 2)));
+        assert_eq!(
+            read_size(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], 0),
+            Some((None, 8))
+        );
+        assert_eq!(read_size(&[0x7F], 0), None);
+    }
+
+    #[test]
+    fn unlaced_blocks_are_one_frame() {
+        let body = [0x81, 0x00, 0x00, 0x80, 1, 2, 3];
+        assert_eq!(block_frames(&body), Some((1, vec![&[1, 2, 3][..]])));
+    }
+
+    #[test]
+    fn xiph_laced_blocks_are_split() {
+        // three frames of 2, 256 and 1 bytes
+        let mut laced = vec![2, 2, 255, 1];
+        laced.extend_from_slice(&[0xAA; 2]);
+        laced.extend_from_slice(&[0xBB; 256]);
+        laced.push(0xCC);
+        let (track, frames) = block_frames(&block(0x02, &laced)).unwrap();
+        assert_eq!(track, 1);
+        assert_eq!(frames, vec![&[0xAA; 2][..], &[0xBB; 256][..], &[0xCC][..]]);
+    }
+
+    #[test]
+    fn ebml_laced_blocks_are_split() {
+        // three frames of 3, 1 and 2 bytes: 3, then a difference of -2 as a one byte signed vint
+        let mut laced = vec![2, 0x83, 0x80 | (63 - 2)];
+        laced.extend_from_slice(&[1, 1, 1, 2, 3, 3]);
+        let (_, frames) = block_frames(&block(0x06, &laced)).unwrap();
+        assert_eq!(frames, vec![&[1, 1, 1][..], &[2][..], &[3, 3][..]]);
+    }
+
+    #[test]
+    fn fixed_laced_blocks_are_split() {
+        let (_, frames) = block_frames(&block(0x04, &[2, 1, 1, 2, 2, 3, 3])).unwrap();
+        assert_eq!(frames, vec![&[1, 1][..], &[2, 2][..], &[3, 3][..]]);
+        // six bytes can't be split into four frames
+        assert_eq!(block_frames(&block(0x04, &[3, 1, 1, 2, 2, 3, 3])), None);
+    }
+
+    #[test]
+    fn broken_blocks_are_refused() {
+        assert_eq!(block_frames(&[]), None);
+        assert_eq!(block_frames(&[0x81, 0x00]), None);
+        // no frame count
+        assert_eq!(block_frames(&block(0x02, &[])), None);
+        // Xiph sizes that run off the end
+        assert_eq!(block_frames(&block(0x02, &[1, 255])), None);
+        // frames that are bigger than the block
+        assert_eq!(block_frames(&block(0x02, &[1, 10, 1])), None);
+        assert_eq!(block_frames(&block(0x06, &[1, 0x8A, 1])), None);
+        // an EBML difference that makes a size negative
+        assert_eq!(block_frames(&block(0x06, &[2, 0x81, 0x80, 1])), None);
+    }
+
+    #[test]
+    fn unknown_size_clusters_are_demuxed() {
+        let mut file = vec![0x1A, 0x45, 0xDF, 0xA3, 0x80];
+        // a segment and a cluster, both of unknown size, like a browser recording
+        file.extend_from_slice(&[0x18, 0x53, 0x80, 0x67, 0xFF]);
+        file.extend_from_slice(&[0x16, 0x54, 0xAE, 0x6B, 0x8D]);
+        file.extend_from_slice(&[0xAE, 0x8B, 0xD7, 0x81, 0x01, 0x86, 0x86]);
+        file.extend_from_slice(b"A_OPUS");
+        file.extend_from_slice(&[0x1F, 0x43, 0xB6, 0x75, 0xFF]);
+        file.extend_from_slice(&[0xA3, 0x86, 0x81, 0x00, 0x00, 0x80, 0xF8, 0xFF]);
+        // a block for some other track, which is left out
+        file.extend_from_slice(&[0xA3, 0x85, 0x82, 0x00, 0x00, 0x80, 0x00]);
+
+        let track = demux_opus(&file).unwrap().unwrap();
+        assert!(track.head.is_empty());
+        assert_eq!(track.packets, vec![&[0xF8, 0xFF][..]]);
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        // no Opus track
+        assert!(demux_opus(&[0x1A, 0x45, 0xDF, 0xA3, 0x80])
+            .unwrap()
+            .is_none());
+        // an element ID with no length marker
+        assert!(demux_opus(&[0x00, 0x80]).is_err());
+        // a size that's cut off
+        assert!(demux_opus(&[0x1A, 0x45, 0xDF, 0xA3, 0x40]).is_err());
+        // an unknown size on something that isn't stepped into
+        assert!(demux_opus(&[0xEC, 0xFF]).is_err());
+        // a block that's cut off
+        assert!(demux_opus(&[0xA3, 0x84, 0x81, 0x00]).is_err());
+    }
+}
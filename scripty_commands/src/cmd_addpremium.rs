@@ -1,11 +1,10 @@
-use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_db::{set_guild_premium, PgPoolKey};
+use scripty_metrics::UserError;
 use serenity::{
     client::Context,
     framework::standard::{macros::command, Args, CommandResult},
-    model::channel::Message,
+    model::{channel::Message, id::GuildId},
 };
-use sqlx::query;
 use std::hint::unreachable_unchecked;
 
 #[command("add_premium")]
@@ -17,54 +16,34 @@ async fn cmd_add_premium(ctx: &Context, msg: &Message, mut args: Args) -> Comman
         let db = data
             .get::<PgPoolKey>()
             .unwrap_or_else(|| unsafe { unreachable_unchecked() });
-        let level = match args.single::<i16>() {
+        let level = match args.single::<u8>() {
             Ok(l) => l,
             Err(e) => {
-                if let Err(e) = msg
-                    .channel_id
-                    .send_message(&ctx, |m| {
-                        m.content(format!("failed to parse #1 as i64 {}", e))
-                    })
-                    .await
-                {
-                    handle_serenity_error!(e);
-                }
-                return Ok(());
+                let error = format!("failed to parse #1 as u8 {}", e);
+                msg.channel_id
+                    .send_message(&ctx, |m| m.content(&error))
+                    .await?;
+                return Err(UserError(error).into());
             }
         };
-        let guild_id = match args.single::<i64>() {
-            Ok(l) => l,
+        let guild_id = match args.single::<u64>() {
+            Ok(l) => GuildId(l),
             Err(e) => {
-                if let Err(e) = msg
-                    .channel_id
-                    .send_message(&ctx, |m| {
-                        m.content(format!("failed to parse #2 as i64 {}", e))
-                    })
-                    .await
-                {
-                    handle_serenity_error!(e);
-                }
-
-                return Ok(());
+                let error = format!("failed to parse #2 as u64 {}", e);
+                msg.channel_id
+                    .send_message(&ctx, |m| m.content(&error))
+                    .await?;
+                return Err(UserError(error).into());
             }
         };
-        query!(
-            "UPDATE guilds SET premium_level = $1 WHERE guild_id = $2",
-            level,
-            guild_id
-        )
-        .execute(db)
-        .await
+        set_guild_premium(db, guild_id, level).await
     };
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.content(format!("here's the result: {:?}", success))
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
+    success?;
     Ok(())
 }
@@ -0,0 +1,75 @@
+use scripty_db::{api_key_usage, api_rate_limit, fetch_user_premium, PgPoolKey};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, CommandResult},
+    model::channel::Message,
+};
+use std::fmt::Write;
+
+#[command("api_usage")]
+#[aliases("key_usage")]
+#[bucket = "general"]
+#[description = "See how much each of your API keys has been used this month, and how fast \
+they can make requests."]
+async fn cmd_api_usage(ctx: &Context, msg: &Message) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+
+    let usage = {
+        let data = ctx.data.read().await;
+        let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+        let premium_level = fetch_user_premium(db, msg.author.id).await;
+        let keys = api_key_usage(db, msg.author.id).await;
+
+        premium_level.and_then(|l| keys.map(|k| (l, k)))
+    };
+
+    let mut result: CommandResult = Ok(());
+    match usage {
+        Ok((_, keys)) if keys.is_empty() => {
+            embed
+                .title("You don't have any API keys.")
+                .description("Premium subscribers can make one with `get_key`.");
+        }
+        Ok((premium_level, keys)) => {
+            let limit = api_rate_limit(premium_level);
+            let mut description = format!(
+                "Each key can make {} requests at once, and {} more every minute.\n\n",
+                limit.burst, limit.per_minute
+            );
+            for k in keys.iter() {
+                let _ = writeln!(
+                    description,
+                    "`{}...`: {} request(s), {:.1} minutes of audio",
+                    k.prefix,
+                    k.requests,
+                    k.audio_ms as f64 / 60_000.0
+                );
+            }
+            embed
+                .title("API usage this month")
+                .description(description)
+                .footer(|f| f.text("Usage is saved every minute, so the last one may be missing"));
+        }
+        Err(e) => {
+            tracing::error!("Couldn't fetch API usage: {}", e);
+            embed.title("Error from database").description(format!(
+                "A unknown error happened while trying to query the database: {}",
+                e
+            ));
+            result = Err(e.into());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
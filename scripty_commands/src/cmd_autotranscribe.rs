@@ -0,0 +1,85 @@
+use scripty_db::{set_attachment_channel, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("auto_transcribe")]
+#[aliases("autotranscribe", "auto-transcribe")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Turn automatic transcription of audio files and voice messages on or off in \
+this channel. When it's on, I reply to every one posted here with its transcript.\n\
+How long the audio can be depends on the server's premium level."]
+#[usage = "<on|off>"]
+#[example = "on"]
+async fn cmd_auto_transcribe(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for the auto_transcribe command");
+            return Ok(());
+        }
+    };
+
+    let enabled = match args.single::<String>().map(|a| a.to_lowercase()).as_deref() {
+        Ok("on") | Ok("true") | Ok("yes") => Some(true),
+        Ok("off") | Ok("false") | Ok("no") => Some(false),
+        _ => None,
+    };
+
+    match enabled {
+        None => {
+            embed
+                .title("Should automatic transcription be on or off?")
+                .description("Run this again with either `on` or `off` after it.");
+            result = Err(UserError("no on or off given".to_string()).into());
+        }
+        Some(enabled) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match set_attachment_channel(db, guild_id, msg.channel_id, enabled).await {
+                Err(err) => {
+                    tracing::info!("Couldn't update attachment_channels: {}", err);
+                    embed
+                        .title("Ugh, I couldn't write that down..")
+                        .description(
+                            "I just let my developer know, until then you could just try again",
+                        );
+                    result = Err(err.into());
+                }
+                Ok(false) => {
+                    embed
+                        .title("This server isn't set up yet.")
+                        .description("Run `setup` first, then try this again.");
+                    result = Err(UserError("server isn't set up".to_string()).into());
+                }
+                Ok(true) => {
+                    embed.description(if enabled {
+                        "Done! I'll transcribe every audio file and voice message posted here."
+                    } else {
+                        "Done! Audio posted here only gets transcribed with `transcribe` now."
+                    });
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+    result
+}
@@ -0,0 +1,180 @@
+use scripty_db::{
+    count_other_bindings, ensure_guild, fetch_guild_config, fetch_output_channel, max_bindings,
+    set_binding, set_output_webhook, Binding, OutputWebhook, PgPoolKey,
+};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandError, CommandResult},
+    model::prelude::{Channel, ChannelId, ChannelType, GuildId, Message},
+    prelude::Mentionable,
+};
+use std::hint::unreachable_unchecked;
+
+#[command("bind")]
+#[aliases("add_bind", "add_binding")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Transcribe another voice chat. Arg 1 is the voice chat, arg 2 is the channel \
+to send transcriptions to (defaults to this one).\nHow many voice chats can be bound depends on \
+the server's premium level."]
+#[usage = "<voice chat ID> [transcription channel]"]
+#[example = "675390856253014039 #meeting-room-2"]
+async fn cmd_bind(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let voice_channel = match args.single::<ChannelId>() {
+        Ok(id) => id,
+        Err(_) => {
+            let error = "The voice chat ID you gave was invalid.";
+            msg.channel_id
+                .send_message(&ctx, |m| m.content(error))
+                .await?;
+            return Err(UserError(error.to_string()).into());
+        }
+    };
+    let output_channel = args.single::<ChannelId>().unwrap_or(msg.channel_id);
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::error!("msg.guild_id is None for the bind command");
+            return Ok(());
+        }
+    };
+
+    let mut embed = CreateEmbed::default();
+    let result = add_binding(ctx, guild_id, voice_channel, output_channel).await;
+    match &result {
+        Ok(_) => {
+            embed.title("Bound successfully!").description(format!(
+                "Transcriptions from {} will be sent to {}.\n\n\
+                Each of my accounts can only be in one voice chat per server, so if more are \
+                bound than I have accounts here, I'll move to whichever ones people are in.",
+                voice_channel.mention(),
+                output_channel.mention()
+            ));
+        }
+        Err(e) => {
+            embed
+                .title("Couldn't bind that voice chat.")
+                .description(e.to_string());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
+
+/// Binds `voice_channel` to `output_channel`. Errors are what to tell the user, as a `UserError`
+/// if it's something they can fix.
+async fn add_binding(
+    ctx: &Context,
+    guild_id: GuildId,
+    voice_channel: ChannelId,
+    output_channel: ChannelId,
+) -> CommandResult {
+    let user_error = |e: String| -> CommandError { UserError(e).into() };
+
+    match voice_channel.to_channel(&ctx).await {
+        Ok(Channel::Guild(c)) if c.guild_id == guild_id => match c.kind {
+            ChannelType::Voice | ChannelType::Stage => {}
+            _ => {
+                return Err(user_error(format!(
+                    "{} isn't a voice chat.",
+                    voice_channel.mention()
+                )))
+            }
+        },
+        Ok(_) => {
+            return Err(user_error(
+                "That voice chat isn't in this server.".to_string(),
+            ))
+        }
+        Err(e) => return Err(user_error(format!("I can't find that voice chat. {}", e))),
+    };
+    let output = match output_channel.to_channel(&ctx).await {
+        Ok(Channel::Guild(c)) if c.guild_id == guild_id => match c.kind {
+            ChannelType::Text | ChannelType::News => c,
+            _ => {
+                return Err(user_error(format!(
+                    "{} isn't a text channel.",
+                    output_channel.mention()
+                )))
+            }
+        },
+        Ok(_) => {
+            return Err(user_error(
+                "That text channel isn't in this server.".to_string(),
+            ))
+        }
+        Err(e) => return Err(user_error(format!("I can't find that text channel. {}", e))),
+    };
+
+    let data = ctx.data.read().await;
+    let db = data
+        .get::<PgPoolKey>()
+        .unwrap_or_else(|| unsafe { unreachable_unchecked() });
+
+    let premium_level = match fetch_guild_config(db, guild_id).await? {
+        Some(g) => g.premium_level,
+        None => {
+            ensure_guild(db, guild_id).await?;
+            0
+        }
+    };
+
+    let bound = count_other_bindings(db, guild_id, voice_channel).await?;
+    let max = max_bindings(premium_level);
+    if bound >= max {
+        return Err(user_error(format!(
+            "This server can only have {} voice chat(s) bound at once. Remove one with `unbind`, \
+            or get premium to bind more: https://github.com/sponsors/tazz4843",
+            max
+        )));
+    }
+
+    let has_webhook = fetch_output_channel(db, output_channel)
+        .await?
+        .map_or(false, |c| c.webhook().is_some());
+    if !has_webhook {
+        let webhook = match output.create_webhook(&ctx, "Scripty Transcriptions").await {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(user_error(format!(
+                    "I failed to create a webhook for transcriptions! Make sure I have the \
+                    Manage Webhooks permission and try again! {}",
+                    e
+                )))
+            }
+        };
+        let token = match webhook.token {
+            Some(t) => t,
+            None => {
+                return Err("Discord never sent the bot a token for the webhook. \
+                This should never happen. Try again."
+                    .into())
+            }
+        };
+        let webhook = OutputWebhook {
+            id: webhook.id,
+            token,
+        };
+        set_output_webhook(db, output_channel, &webhook).await?;
+    }
+
+    let binding = Binding {
+        voice_channel,
+        guild_id,
+        output_channel,
+    };
+    set_binding(db, &binding).await?;
+    Ok(())
+}
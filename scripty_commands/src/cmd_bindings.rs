@@ -0,0 +1,88 @@
+use scripty_db::{fetch_guild_premium, guild_bindings, max_bindings, PgPoolKey};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, CommandResult},
+    model::prelude::Message,
+    prelude::Mentionable,
+};
+use std::{fmt::Write, hint::unreachable_unchecked};
+
+#[command("bindings")]
+#[aliases("binds", "list_bindings")]
+#[only_in("guilds")]
+#[bucket = "general"]
+#[description = "List the voice chats I transcribe in this server, and where the transcriptions go."]
+async fn cmd_bindings(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::error!("msg.guild_id is None for the bindings command");
+            return Ok(());
+        }
+    };
+
+    let mut embed = CreateEmbed::default();
+    let bindings = {
+        let data = ctx.data.read().await;
+        let db = data
+            .get::<PgPoolKey>()
+            .unwrap_or_else(|| unsafe { unreachable_unchecked() });
+
+        let premium_level = fetch_guild_premium(db, guild_id).await;
+        let bindings = guild_bindings(db, guild_id).await;
+
+        premium_level.and_then(|l| bindings.map(|b| (l, b)))
+    };
+
+    let mut result: CommandResult = Ok(());
+    match bindings {
+        Ok((_, bindings)) if bindings.is_empty() => {
+            embed
+                .title("Nothing is bound in this server.")
+                .description("Run `setup` to get started, or `bind` to add a voice chat.");
+        }
+        Ok((premium_level, bindings)) => {
+            let max = max_bindings(premium_level);
+            let mut description = String::new();
+            for (i, b) in bindings.iter().enumerate() {
+                let _ = writeln!(
+                    description,
+                    "{} → {}{}",
+                    b.voice_channel.mention(),
+                    b.output_channel.mention(),
+                    if i >= max {
+                        " (inactive, over limit)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            embed
+                .title(if max == usize::MAX {
+                    format!("{} bindings used", bindings.len())
+                } else {
+                    format!("{} of {} bindings used", bindings.len(), max)
+                })
+                .description(description);
+        }
+        Err(err) => {
+            tracing::error!("Couldn't fetch bindings: {}", err);
+            embed
+                .title("Ugh, I couldn't read that..")
+                .description("I just let my developer know, until then you could just try again");
+            result = Err(err.into());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
@@ -1,4 +1,3 @@
-use scripty_macros::handle_serenity_error;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
@@ -30,17 +29,13 @@ async fn cmd_credits(ctx: &Context, msg: &Message) -> CommandResult {
         "https://commonvoice.mozilla.org/\nPublic audio dataset, with around 10,000 hours of audio across 30 languages",
         false
     );
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
     Ok(())
 }
@@ -1,4 +1,3 @@
-use scripty_macros::handle_serenity_error;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
@@ -14,19 +13,15 @@ async fn cmd_donate(ctx: &Context, msg: &Message) -> CommandResult {
     let mut embed = CreateEmbed::default();
     embed.title("Donating");
     embed.description("Donating helps pay for Scripty's server costs (which are higher than you might think because it needs GPUs)\n\
-    You can donate at https://github.com/sponsors/tazz4843");
+    You can donate at https://github.com/sponsors/tazz4843, then run `link_github` with your GitHub username to get your perks.");
     embed.field("Current Donors", "1 anonymous", true);
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
     Ok(())
 }
@@ -1,6 +1,6 @@
 use eval::Expr;
 use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_metrics::UserError;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
@@ -78,7 +78,7 @@ async fn cmd_eval(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         (compile_time, output)
     };
 
-    embed.field("Output", output.0, false);
+    embed.field("Output", &output.0, false);
     embed.field(
         "Duration",
         if let Some(o) = output.2 {
@@ -89,18 +89,18 @@ async fn cmd_eval(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         false,
     );
 
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
-    Ok(())
+    if output.1 {
+        Ok(())
+    } else {
+        Err(UserError(output.0).into())
+    }
 }
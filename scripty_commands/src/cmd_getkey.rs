@@ -1,127 +1,162 @@
-use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_db::{
+    count_active_api_keys, create_api_key, delete_api_key, fetch_user_premium, generate_api_key,
+    ApiScope, PgPoolKey, MAX_API_KEYS,
+};
+use scripty_metrics::UserError;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
-    framework::standard::{macros::command, CommandResult},
-    model::channel::Message,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, user::User},
 };
-use sqlx::query;
-use std::hint::unreachable_unchecked;
+use sqlx::PgPool;
 
 #[command("get_key")]
+#[aliases("new_key", "create_key")]
 #[bucket = "expensive"]
-#[description = "Get your API key if you are a premium subscriber."]
-async fn cmd_getkey(ctx: &Context, msg: &Message) -> CommandResult {
-    let is_allowed = {
-        let data = ctx.data.read().await;
-        let db = data
-            .get::<PgPoolKey>()
-            .unwrap_or_else(|| unsafe { unreachable_unchecked() });
+#[description = "Create a new API key if you are a premium subscriber. The key is DMed to you, \
+and can't be shown again.\n\
+Keys can be limited to some scopes: `transcribe` for speech to text, `stats` for reading the \
+bot's stats. With no scopes the key can do both."]
+#[usage = "[scopes...]"]
+#[example = "transcribe"]
+async fn cmd_getkey(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
 
-        match query!(
-            "SELECT premium_level FROM users WHERE user_id = $1",
-            *msg.author.id.as_u64() as i64
-        )
-        .fetch_one(db)
-        .await
-        {
-            Ok(val) => {
-                if let Some(val) = val.premium_level {
-                    val >= 1
-                } else {
-                    false
-                }
-            }
-            Err(_) => false,
+    match parse_scopes(&args) {
+        Err(arg) => {
+            embed.title("That isn't a scope.").description(format!(
+                "`{}` isn't something a key can do. Scopes can be `transcribe` or `stats`.",
+                arg
+            ));
+            result = Err(UserError(format!("{} isn't a scope", arg)).into());
         }
-    };
-    let mut embed = CreateEmbed::default();
-    let mut error = false;
-    if !is_allowed {
-        embed.title("Only premium subscribers can use this command!");
-        embed.description(
-            "To get access to Scripty's speech to text API, become a monthly subscriber \
-        at https://github.com/sponsors/tazz4843",
-        );
-    } else {
-        let data = ctx.data.read().await;
-        let db = data
-            .get::<PgPoolKey>()
-            .unwrap_or_else(|| unsafe { unreachable_unchecked() });
-        match query!(
-            "SELECT api_key FROM api_keys WHERE user_id = $1",
-            *msg.author.id.as_u64() as i64
-        )
-        .fetch_optional(db)
-        .await
-        {
-            Ok(val) => {
-                let key = match val {
-                    Some(k) => k.api_key,
-                    None => {
-                        let mut key = String::new();
-                        for _ in 0..32 {
-                            key.push(rand::random::<char>())
-                        }
-                        if let Err(e) = query!(
-                            "INSERT INTO api_keys VALUES ($1, $2)",
-                            key,
-                            *msg.author.id.as_u64() as i64
-                        )
-                        .execute(db)
-                        .await
-                        {
-                            embed.title("Error from database");
-                            embed.description(format!(
-                                "A unknown error happened while trying to query the database: {}",
-                                e
-                            ));
-                            error = true;
-                        }
-                        key
-                    }
-                };
-                if !error {
-                    match msg
-                        .author
-                        .direct_message(ctx, |m| m.content(format!("Your API key is {}", key)))
-                        .await
-                    {
-                        Err(e) => {
-                            embed.title("Couldn't DM you.");
-                            embed.description(format!("Make sure you have DMs allowed! {}", e));
-                        }
-                        Ok(_) => {
-                            embed.title("DMed your API key to you!");
-                            embed.description(
-                                "Docs can be found at https://api.scripty.imaskeleton.me/help",
-                            );
-                        }
-                    };
+        Ok(scopes) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            let premium_level = fetch_user_premium(db, msg.author.id).await;
+            let active_keys = count_active_api_keys(db, msg.author.id).await;
+
+            match premium_level.and_then(|l| active_keys.map(|k| (l, k))) {
+                Ok((0, _)) => {
+                    embed
+                        .title("Only premium subscribers can use this command!")
+                        .description(
+                            "To get access to Scripty's speech to text API, become a monthly \
+                            subscriber at https://github.com/sponsors/tazz4843",
+                        );
+                    result = Err(UserError("not a premium subscriber".to_string()).into());
+                }
+                Ok((_, active_keys)) if active_keys >= MAX_API_KEYS => {
+                    embed.title("You have too many keys.").description(format!(
+                        "You can have up to {} keys at once. Run `keys` to see them, and \
+                        `revoke_key` to get rid of one you don't use.",
+                        MAX_API_KEYS
+                    ));
+                    result = Err(UserError("too many keys".to_string()).into());
+                }
+                Ok(_) => {
+                    let (e, r) = send_new_key(ctx, db, &msg.author, &scopes).await;
+                    embed = e;
+                    result = r;
+                }
+                Err(e) => {
+                    tracing::error!("Couldn't check if {} can get a key: {}", msg.author.id, e);
+                    embed.title("Error from database").description(format!(
+                        "A unknown error happened while trying to query the database: {}",
+                        e
+                    ));
+                    result = Err(e.into());
                 }
-            }
-            Err(e) => {
-                embed.title("Error from database");
-                embed.description(format!(
-                    "A unknown error happened while trying to query the database: {}",
-                    e
-                ));
             }
         }
     }
-    if let Err(e) = msg
-        .channel_id
+
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
+        .await?;
+
+    result
+}
+
+/// Reads the scopes in `args`, defaulting to every scope if there are none. Returns the first
+/// argument that isn't a scope if there is one.
+pub(crate) fn parse_scopes(args: &Args) -> Result<Vec<ApiScope>, String> {
+    let mut scopes = Vec::new();
+    for arg in args.raw() {
+        match ApiScope::from_name(arg) {
+            Some(scope) if !scopes.contains(&scope) => scopes.push(scope),
+            Some(_) => {}
+            None => return Err(arg.to_string()),
+        }
+    }
+    if scopes.is_empty() {
+        scopes.extend_from_slice(&ApiScope::ALL);
+    }
+    Ok(scopes)
+}
+
+/// Creates a key for `user` with `scopes`, and DMs it to them.
+///
+/// Returns the embed to reply with, and an `Err` if the key couldn't be stored or delivered.
+pub(crate) async fn send_new_key(
+    ctx: &Context,
+    db: &PgPool,
+    user: &User,
+    scopes: &[ApiScope],
+) -> (CreateEmbed, CommandResult) {
+    let mut embed = CreateEmbed::default();
+    let new_key = generate_api_key();
+    let scope_names: Vec<&str> = scopes.iter().map(|s| s.name()).collect();
+
+    if let Err(e) = create_api_key(db, user.id, &new_key, scopes).await {
+        tracing::error!("Couldn't store a new API key: {}", e);
+        embed.title("Error from database").description(format!(
+            "A unknown error happened while trying to query the database: {}",
+            e
+        ));
+        return (embed, Err(e.into()));
+    }
+
+    match user
+        .direct_message(ctx, |m| {
+            m.content(format!(
+                "Your new API key is `{}`\nIt can use: {}\n\
+                This is the only time it'll be shown, so keep it somewhere safe!",
+                new_key.key,
+                scope_names.join(", ")
+            ))
+        })
         .await
     {
-        handle_serenity_error!(e);
+        Err(e) => {
+            // nobody will ever see this key, so don't leave it lying around
+            if let Err(e) = delete_api_key(db, &new_key.hash).await {
+                tracing::error!("Couldn't delete an undelivered API key: {}", e);
+            }
+            embed
+                .title("Couldn't DM you.")
+                .description(format!("Make sure you have DMs allowed! {}", e));
+            (
+                embed,
+                Err(UserError(format!("couldn't DM the key: {}", e)).into()),
+            )
+        }
+        Ok(_) => {
+            embed
+                .title(format!(
+                    "DMed your new API key `{}...` to you!",
+                    new_key.prefix
+                ))
+                .description("Docs can be found at https://api.scripty.imaskeleton.me/help");
+            (embed, Ok(()))
+        }
     }
-
-    Ok(())
 }
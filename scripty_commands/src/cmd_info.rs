@@ -1,5 +1,4 @@
 use scripty_config::BotConfig;
-use scripty_macros::handle_serenity_error;
 use scripty_utils::BotInfo;
 use serenity::{
     builder::CreateEmbed,
@@ -44,18 +43,14 @@ async fn cmd_info(ctx: &Context, msg: &Message) -> CommandResult {
     };
     embed.field("Support Server", "https://discord.gg/VT7EgQ3RQW", true);
     embed.field("Bot Version", env!("CARGO_PKG_VERSION"), true);
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
     Ok(())
 }
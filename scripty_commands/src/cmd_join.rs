@@ -1,5 +1,5 @@
 use scripty_audio::bind;
-use scripty_macros::handle_serenity_error;
+use scripty_metrics::UserError;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
@@ -19,16 +19,11 @@ async fn cmd_join(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
     let bind_channel = match args.single::<u64>() {
         Ok(id) => ChannelId(id),
         Err(_) => {
-            if let Err(e) = msg
-                .channel_id
-                .send_message(&ctx, |m| {
-                    m.content("The snowflake ID you gave was invalid.")
-                })
-                .await
-            {
-                handle_serenity_error!(e);
-            }
-            return Ok(());
+            let error = "The snowflake ID you gave was invalid.";
+            msg.channel_id
+                .send_message(&ctx, |m| m.content(error))
+                .await?;
+            return Err(UserError(error.to_string()).into());
         }
     };
     let guild_id = msg.guild_id.unwrap_or_else(|| {
@@ -39,38 +34,33 @@ async fn cmd_join(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
 
     let mut embed = CreateEmbed::default();
 
-    embed.description(
-        match bind(ctx, bind_channel, transcription_channel, guild_id).await {
-            Ok(_) => {
-                format!(
-                    "Joined {} and bound to {} successfully.",
-                    bind_channel.mention(),
-                    transcription_channel.mention()
-                )
-            }
-            Err(e) => {
-                let err = format!(
-                    "Failed to join {} because {}",
-                    transcription_channel.mention(),
-                    e
-                );
-                error!("{}", err);
-                err
-            }
-        },
-    );
-    if let Err(e) = msg
-        .channel_id
+    let result = bind(ctx, bind_channel, transcription_channel, guild_id).await;
+    embed.description(match &result {
+        Ok(_) => {
+            format!(
+                "Joined {} and bound to {} successfully.",
+                bind_channel.mention(),
+                transcription_channel.mention()
+            )
+        }
+        Err(e) => {
+            let err = format!(
+                "Failed to join {} because {}",
+                transcription_channel.mention(),
+                e
+            );
+            error!("{}", err);
+            err
+        }
+    });
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
-    Ok(())
+    result.map_err(Into::into)
 }
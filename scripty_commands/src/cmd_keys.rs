@@ -0,0 +1,75 @@
+use scripty_db::{list_api_keys, PgPoolKey};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, CommandResult},
+    model::channel::Message,
+};
+use std::fmt::Write;
+
+#[command("keys")]
+#[aliases("api_keys", "list_keys")]
+#[bucket = "general"]
+#[description = "List your API keys, what they can do, and when they were last used."]
+async fn cmd_keys(ctx: &Context, msg: &Message) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+
+    let keys = {
+        let data = ctx.data.read().await;
+        let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+        list_api_keys(db, msg.author.id).await
+    };
+
+    let mut result: CommandResult = Ok(());
+    match keys {
+        Ok(keys) if keys.is_empty() => {
+            embed
+                .title("You don't have any API keys.")
+                .description("Premium subscribers can make one with `get_key`.");
+        }
+        Ok(keys) => {
+            let mut description = String::new();
+            for k in keys.iter() {
+                let _ = writeln!(
+                    description,
+                    "`{}...`: {}\ncreated {}, {}\n",
+                    k.prefix,
+                    k.scopes
+                        .iter()
+                        .map(|s| s.name())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    k.created_at.format("%Y-%m-%d"),
+                    k.last_used_at.map_or_else(
+                        || "never used".to_string(),
+                        |t| format!("last used {}", t.format("%Y-%m-%d %H:%M UTC"))
+                    )
+                );
+            }
+            embed
+                .title(format!("You have {} API key(s)", keys.len()))
+                .description(description)
+                .footer(|f| f.text("Use rotate_key or revoke_key with the start of a key"));
+        }
+        Err(e) => {
+            tracing::error!("Couldn't list API keys: {}", e);
+            embed.title("Error from database").description(format!(
+                "A unknown error happened while trying to query the database: {}",
+                e
+            ));
+            result = Err(e.into());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
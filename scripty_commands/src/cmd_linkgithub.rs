@@ -0,0 +1,84 @@
+use scripty_db::{link_sponsor, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("link_github")]
+#[aliases("linkgithub", "link-github", "sponsor")]
+#[bucket = "expensive"]
+#[description = "Link your GitHub account, so sponsoring Scripty on GitHub gives you premium \
+automatically. Your premium follows your sponsorship from then on, and I'll DM you when it \
+changes."]
+#[usage = "<GitHub username>"]
+#[example = "octocat"]
+async fn cmd_link_github(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    let login = args
+        .single::<String>()
+        .ok()
+        .map(|l| l.trim_start_matches('@').to_string())
+        .filter(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+
+    match login {
+        None => {
+            embed
+                .title("Which GitHub account?")
+                .description("Run this again with your GitHub username after it.");
+            result = Err(UserError("no GitHub username given".to_string()).into());
+        }
+        Some(login) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match link_sponsor(db, &login, msg.author.id.0).await {
+                Err(e) => {
+                    tracing::error!("Couldn't link a GitHub account: {}", e);
+                    embed
+                        .title("Ugh, I couldn't write that down..")
+                        .description(
+                            "I just let my developer know, until then you could just try again",
+                        );
+                    result = Err(e.into());
+                }
+                Ok(None) => {
+                    embed
+                        .title("Someone else already linked that account.")
+                        .description("If it's yours, get in touch with my developer.");
+                    result = Err(UserError("GitHub account already linked".to_string()).into());
+                }
+                Ok(Some(sponsor)) if sponsor.active => {
+                    let (level, count) = sponsor.premium();
+                    embed.title("Linked!").description(format!(
+                        "Thanks for sponsoring! You now have premium level {}, and can give \
+                        premium to {} server{}.",
+                        level,
+                        count,
+                        if count == 1 { "" } else { "s" }
+                    ));
+                }
+                Ok(Some(_)) => {
+                    embed.title("Linked!").description(
+                        "Sponsor Scripty at https://github.com/sponsors/tazz4843 with that \
+                        account, and you'll get premium straight away.",
+                    );
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+    result
+}
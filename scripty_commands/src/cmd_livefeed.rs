@@ -0,0 +1,117 @@
+use scripty_audio::close_live_feed;
+use scripty_config::BotConfig;
+use scripty_db::{generate_live_token, set_live_token_hash, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("live_feed")]
+#[aliases("overlay", "live")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Get a link to a live feed of everything transcribed in this server, along with \
+a caption overlay page you can add to OBS as a browser source. The links are DMed to you.\n\
+Running this again makes new links and stops the old ones, and `off` stops them without making \
+new ones."]
+#[usage = "[off]"]
+async fn cmd_live_feed(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for the live_feed command");
+            return Ok(());
+        }
+    };
+
+    let turn_off = matches!(
+        args.single::<String>().map(|a| a.to_lowercase()).as_deref(),
+        Ok("off") | Ok("disable") | Ok("stop")
+    );
+    let token = if turn_off {
+        None
+    } else {
+        Some(generate_live_token())
+    };
+
+    let updated = {
+        let data = ctx.data.read().await;
+        let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+        set_live_token_hash(db, guild_id, token.as_ref().map(|t| t.hash.as_str())).await
+    };
+    if let Ok(true) = updated {
+        // anyone still watching with the old token gets cut off
+        close_live_feed(guild_id);
+    }
+
+    match (updated, token) {
+        (Err(err), _) => {
+            tracing::info!("Couldn't update live_token_hash: {}", err);
+            embed
+                .title("Ugh, I couldn't write that down..")
+                .description("I just let my developer know, until then you could just try again");
+            result = Err(err.into());
+        }
+        (Ok(false), _) => {
+            embed
+                .title("This server isn't set up yet.")
+                .description("Run `setup` first, then try again.");
+            result = Err(UserError("server isn't set up".to_string()).into());
+        }
+        (Ok(true), None) => {
+            embed
+                .title("Live feed turned off.")
+                .description("The old links won't work anymore.");
+        }
+        (Ok(true), Some(token)) => {
+            let api_url = unsafe { BotConfig::get().unwrap_unchecked() }.api_url();
+            match msg
+                .author
+                .direct_message(ctx, |m| {
+                    m.content(format!(
+                        "Caption overlay: {api}/overlay?guild={guild}&token={token}\n\
+                        Live feed (Server-Sent Events): {api}/v1/live/{guild}?token={token}\n\
+                        Anyone with these links can read everything said in your voice chats, \
+                        so keep them to yourself!",
+                        api = api_url,
+                        guild = guild_id.0,
+                        token = token.key
+                    ))
+                })
+                .await
+            {
+                Ok(_) => {
+                    embed
+                        .title("DMed you the live feed links!")
+                        .description("Any old links won't work anymore.");
+                }
+                Err(e) => {
+                    embed.title("Couldn't DM you.").description(format!(
+                        "Make sure you have DMs allowed, then run this again! {}",
+                        e
+                    ));
+                    result = Err(UserError(format!("couldn't DM the links: {}", e)).into());
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
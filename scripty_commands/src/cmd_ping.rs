@@ -1,5 +1,4 @@
-use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_db::{fetch_guild_config, PgPoolKey};
 use scripty_utils::{get_avg_ws_latency, ContextTypes};
 use serenity::model::prelude::GuildId;
 use serenity::{
@@ -8,7 +7,6 @@ use serenity::{
     framework::standard::{macros::command, CommandResult},
     model::prelude::Message,
 };
-use sqlx::query;
 use std::time::SystemTime;
 
 #[command("ping")]
@@ -25,11 +23,9 @@ async fn cmd_ping(ctx: &Context, msg: &Message) -> CommandResult {
     let db_latency = {
         let data = ctx.data.read().await;
         let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
-        let guild_id = *msg.guild_id.unwrap_or(GuildId(675390855716274216)).as_u64();
+        let guild_id = msg.guild_id.unwrap_or(GuildId(675390855716274216));
         let st = SystemTime::now();
-        query!("SELECT * FROM guilds WHERE guild_id = $1", guild_id as i64)
-            .fetch_optional(db)
-            .await?;
+        fetch_guild_config(db, guild_id).await?;
         st.elapsed()?.as_nanos() as f64
     };
     let mut embed = CreateEmbed::default();
@@ -41,18 +37,14 @@ async fn cmd_ping(ctx: &Context, msg: &Message) -> CommandResult {
         false,
     );
     embed.field("PSQL", format!("{}ms", db_latency / 1_000_000.0), false);
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
     Ok(())
 }
@@ -1,12 +1,11 @@
-use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_db::{fetch_prefix, set_prefix, PgPoolKey};
+use scripty_metrics::UserError;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
     framework::standard::{macros::command, Args, CommandResult},
     model::prelude::Message,
 };
-use sqlx::query;
 
 #[command("prefix")]
 #[aliases(
@@ -25,6 +24,7 @@ use sqlx::query;
 #[example = "."]
 async fn cmd_prefix(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
 
     let data = ctx.data.read().await;
     let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
@@ -44,25 +44,13 @@ async fn cmd_prefix(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         embed
             .title("Your prefix can't be longer than 10 characters")
             .description("Why would you want it that long anyway..");
-    } else if let Err(err) = query!(
-        "INSERT INTO prefixes
-                 (guild_id, prefix)
-             VALUES
-                 ($1, $2)
-             ON CONFLICT
-                 (guild_id)
-             DO UPDATE SET
-                 prefix = $2;",
-        guild_id.0 as i64,
-        prefix,
-    )
-    .execute(db)
-    .await
-    {
+        result = Err(UserError("prefix is too long".to_string()).into());
+    } else if let Err(err) = set_prefix(db, guild_id, prefix).await {
         tracing::info!("Couldn't insert to prefixes: {}", err);
         embed
             .title("Ugh, I couldn't write that down..")
             .description("I just let my developer know, until then you could just try again");
+        result = Err(err.into());
     } else {
         embed.description(if !prefix.is_empty() {
             format!("Voila! My prefix here is now `{}`", prefix)
@@ -71,19 +59,15 @@ async fn cmd_prefix(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         });
     }
 
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
-    Ok(())
+        .await?;
+    result
 }
 
 pub async fn prefix_check(ctx: &Context, msg: &Message) -> Option<String> {
@@ -92,18 +76,7 @@ pub async fn prefix_check(ctx: &Context, msg: &Message) -> Option<String> {
     let data = ctx.data.read().await;
     let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
 
-    match query!(
-        "SELECT
-           prefix
-         FROM
-           prefixes
-         WHERE
-           guild_id = $1",
-        guild_id.0 as i64
-    )
-    .fetch_optional(db)
-    .await
-    {
+    match fetch_prefix(db, guild_id).await {
         Err(err) => {
             tracing::info!(
                 "Couldn't fetch prefix from the database for the prefix check: {:?}",
@@ -111,9 +84,6 @@ pub async fn prefix_check(ctx: &Context, msg: &Message) -> Option<String> {
             );
             None
         }
-        Ok(row) => match row {
-            Some(row) => row.prefix,
-            None => None,
-        },
+        Ok(prefix) => prefix,
     }
 }
@@ -0,0 +1,66 @@
+use scripty_db::{revoke_api_key_by_prefix, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+};
+
+#[command("revoke_key")]
+#[aliases("delete_key")]
+#[bucket = "expensive"]
+#[description = "Revoke one of your API keys, so it stops working straight away. Run `keys` to \
+see the start of each key."]
+#[usage = "<key start>"]
+#[example = "scr_a1B2c3D4"]
+async fn cmd_revoke_key(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    match args.single::<String>() {
+        Err(_) => {
+            embed
+                .title("Which key?")
+                .description("Run this again with the start of the key, as shown by `keys`.");
+            result = Err(UserError("no key given".to_string()).into());
+        }
+        Ok(prefix) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match revoke_api_key_by_prefix(db, msg.author.id, prefix.trim_end_matches('.')).await {
+                Ok(false) => {
+                    embed
+                        .title("You don't have that key.")
+                        .description("Run `keys` to see the keys you have.");
+                    result = Err(UserError("no such key".to_string()).into());
+                }
+                Ok(true) => {
+                    embed
+                        .title("Revoked!")
+                        .description("That key won't work anymore.");
+                }
+                Err(e) => {
+                    tracing::error!("Couldn't revoke an API key: {}", e);
+                    embed.title("Error from database").description(format!(
+                        "A unknown error happened while trying to query the database: {}",
+                        e
+                    ));
+                    result = Err(e.into());
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
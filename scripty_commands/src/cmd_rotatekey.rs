@@ -0,0 +1,74 @@
+use crate::send_new_key;
+use scripty_db::{find_api_key, revoke_api_key, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::channel::Message,
+};
+
+#[command("rotate_key")]
+#[aliases("regenerate_key")]
+#[bucket = "expensive"]
+#[description = "Replace one of your API keys with a new one that can do the same things. The \
+new key is DMed to you, and the old one stops working once you have it."]
+#[usage = "<key start>"]
+#[example = "scr_a1B2c3D4"]
+async fn cmd_rotate_key(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    match args.single::<String>() {
+        Err(_) => {
+            embed
+                .title("Which key?")
+                .description("Run this again with the start of the key, as shown by `keys`.");
+            result = Err(UserError("no key given".to_string()).into());
+        }
+        Ok(prefix) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match find_api_key(db, msg.author.id, prefix.trim_end_matches('.')).await {
+                Ok(None) => {
+                    embed
+                        .title("You don't have that key.")
+                        .description("Run `keys` to see the keys you have.");
+                    result = Err(UserError("no such key".to_string()).into());
+                }
+                Ok(Some(old)) => {
+                    // only revoke the old key once the new one made it to its owner
+                    let (e, r) = send_new_key(ctx, db, &msg.author, &old.scopes).await;
+                    embed = e;
+                    result = match r {
+                        Ok(()) => revoke_api_key(db, old.key_id).await.map_err(|e| {
+                            tracing::error!("Couldn't revoke a rotated API key: {}", e);
+                            e.into()
+                        }),
+                        Err(e) => Err(e),
+                    };
+                }
+                Err(e) => {
+                    tracing::error!("Couldn't look up an API key to rotate: {}", e);
+                    embed.title("Error from database").description(format!(
+                        "A unknown error happened while trying to query the database: {}",
+                        e
+                    ));
+                    result = Err(e.into());
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
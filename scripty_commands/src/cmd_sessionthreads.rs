@@ -0,0 +1,85 @@
+use scripty_db::{set_session_threads, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("session_threads")]
+#[aliases("threads", "session-threads")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Turn per-session threads on or off. When they're on, every voice session gets \
+its own thread in the transcription channel, which is archived once everyone leaves.\n\
+I need the Create Public Threads permission there for this to work."]
+#[usage = "<on|off>"]
+#[example = "on"]
+async fn cmd_session_threads(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for the session_threads command");
+            return Ok(());
+        }
+    };
+
+    let enabled = match args.single::<String>().map(|a| a.to_lowercase()).as_deref() {
+        Ok("on") | Ok("true") | Ok("yes") => Some(true),
+        Ok("off") | Ok("false") | Ok("no") => Some(false),
+        _ => None,
+    };
+
+    match enabled {
+        None => {
+            embed
+                .title("Should threads be on or off?")
+                .description("Run this again with either `on` or `off` after it.");
+            result = Err(UserError("no on or off given".to_string()).into());
+        }
+        Some(enabled) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match set_session_threads(db, guild_id, enabled).await {
+                Err(err) => {
+                    tracing::info!("Couldn't update session_threads: {}", err);
+                    embed
+                        .title("Ugh, I couldn't write that down..")
+                        .description(
+                            "I just let my developer know, until then you could just try again",
+                        );
+                    result = Err(err.into());
+                }
+                Ok(false) => {
+                    embed
+                        .title("This server isn't set up yet.")
+                        .description("Run `setup` first, then try this again.");
+                    result = Err(UserError("server isn't set up".to_string()).into());
+                }
+                Ok(true) => {
+                    embed.description(if enabled {
+                        "Done! Every voice session from now on gets its own thread."
+                    } else {
+                        "Done! Transcriptions will go straight to the channel again."
+                    });
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+    result
+}
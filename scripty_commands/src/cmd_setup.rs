@@ -1,6 +1,6 @@
 use scripty_audio::bind;
-use scripty_db::PgPoolKey;
-use scripty_macros::handle_serenity_error;
+use scripty_db::{setup_guild, OutputWebhook, PgPoolKey};
+use scripty_metrics::UserError;
 use serenity::builder::CreateSelectMenuOption;
 use serenity::collector::CollectComponentInteraction;
 use serenity::model::prelude::message_component::{ButtonStyle, ComponentType};
@@ -11,7 +11,6 @@ use serenity::{
     framework::standard::{macros::command, CommandResult},
     model::prelude::{Channel, ChannelId, ChannelType, Message},
 };
-use sqlx::query;
 use std::hint;
 use tokio::time::Duration;
 
@@ -46,7 +45,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
     //////////////////////////////////////////////////////
     // make user agree to ToS + Privacy Policy as a CYA //
     //////////////////////////////////////////////////////
-    let mut m = match msg.channel_id.send_message(ctx, |f| {
+    let mut m = msg.channel_id.send_message(ctx, |f| {
         f
             .content("By using Scripty you agree to the privacy policy, found here: https://scripty.imaskeleton.me/privacy_policy . \
     Type `ok` within 5 minutes to continue.")
@@ -59,13 +58,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                     })
                 })
             })
-    }).await {
-        Ok(m) => m,
-        Err(e) => {
-            handle_serenity_error!(e);
-            return Ok(());
-        }
-    };
+    }).await?;
     if CollectComponentInteraction::new(&ctx)
         .author_id(msg.author.id)
         .channel_id(msg.channel_id)
@@ -81,7 +74,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
         let _ = m
             .edit(&ctx, |m| m.content("Timed out. Rerun setup to try again."))
             .await;
-        return Ok(());
+        return Err(UserError("setup timed out".to_string()).into());
     }
     drop(m);
 
@@ -112,7 +105,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
         channel_ids.push(opt);
     }
 
-    let mut m = match msg.channel_id.send_message(ctx, |m| {
+    let mut m = msg.channel_id.send_message(ctx, |m| {
         m.content("Select the channel you want me to send the results of transcriptions to from the dropdowns below.\n\
         **NOTE**: this only includes channels where I have the Manage Webhooks permission. \
         If the channel you want doesn't show up, give me the Manage Webhooks permission there and try rerunning setup.")
@@ -143,13 +136,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                 }
                 c
             })
-    }).await {
-        Ok(m) => m,
-        Err(e) => {
-            handle_serenity_error!(e);
-            return Ok(());
-        }
-    };
+    }).await?;
 
     let result_id: u64 = match CollectComponentInteraction::new(&ctx)
         .author_id(msg.author.id)
@@ -170,7 +157,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                                 .components(|c| c)
                         })
                         .await;
-                    return Ok(());
+                    return Err("Discord sent an invalid channel ID".into());
                 }
             },
             None => {
@@ -180,7 +167,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                             .components(|c| c)
                     })
                     .await;
-                return Ok(());
+                return Err("Discord didn't send a channel ID".into());
             }
         },
         None => {
@@ -190,7 +177,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                         .components(|c| c)
                 })
                 .await;
-            return Ok(());
+            return Err(UserError("setup timed out".to_string()).into());
         }
     };
 
@@ -223,7 +210,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
         channel_ids.push(opt);
     }
 
-    let mut m = match msg.channel_id.send_message(ctx, |m| {
+    let mut m = msg.channel_id.send_message(ctx, |m| {
         m.content("Select the voice chat you would like me to join and transcript from from the dropdowns below.\n\
         **NOTE**: you can temporarily change this at any time by dragging me to another VC, or permanently by rerunning setup.")
             .components(|c| {
@@ -253,13 +240,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                 }
                 c
             })
-    }).await {
-        Ok(m) => m,
-        Err(e) => {
-            handle_serenity_error!(e);
-            return Ok(());
-        }
-    };
+    }).await?;
 
     let voice_id: u64 = match CollectComponentInteraction::new(&ctx)
         .author_id(msg.author.id)
@@ -280,7 +261,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                                 .components(|c| c)
                         })
                         .await;
-                    return Ok(());
+                    return Err("Discord sent an invalid channel ID".into());
                 }
             },
             None => {
@@ -290,7 +271,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                             .components(|c| c)
                     })
                     .await;
-                return Ok(());
+                return Err("Discord didn't send a channel ID".into());
             }
         },
         None => {
@@ -300,7 +281,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                         .components(|c| c)
                 })
                 .await;
-            return Ok(());
+            return Err(UserError("setup timed out".to_string()).into());
         }
     };
     drop(m);
@@ -317,7 +298,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
             msg.channel_id
                 .say(&ctx, format!("I can't convert that to a channel. {:?}", e))
                 .await?;
-            return Ok(());
+            return Err(e.into());
         }
     } {
         Channel::Guild(c) => match c.kind {
@@ -345,7 +326,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                                     ),
                                 )
                                 .await?;
-                                return Ok(());
+                                return Err(e.into());
                             }
                         }
                         let token = match w.token {
@@ -358,7 +339,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                                             webhook. This should never happen. Try restarting setup.",
                                         )
                                         .await?;
-                                return Ok(());
+                                return Err("Discord didn't send a webhook token".into());
                             }
                         };
                         let webhook_id = w.id;
@@ -369,7 +350,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                                 .say(&ctx, format!("I failed to create a webhook for \
                                 transcriptions! Make sure I have the Manage Webhooks permission and try again! {}", e))
                                 .await?;
-                        return Ok(());
+                        return Err(UserError(format!("couldn't create a webhook: {}", e)).into());
                     }
                 }
             }
@@ -380,7 +361,7 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                         "Something weird happened in my code... try restarting setup?",
                     )
                     .await?;
-                return Ok(());
+                return Err("the picked channel isn't a text channel".into());
             }
         },
         _ => {
@@ -390,88 +371,63 @@ async fn cmd_setup(ctx: &Context, msg: &Message) -> CommandResult {
                     "Something weird happened in my code... try restarting setup?",
                 )
                 .await?;
-            return Ok(());
+            return Err("the picked channel isn't a text channel".into());
         }
     };
 
-    match query!(
-        "INSERT INTO guilds
-              (guild_id, default_bind, output_channel, premium_level)
-            VALUES ($1, $2, $3, $4)
-              ON CONFLICT (guild_id) DO UPDATE
-                SET default_bind = $2, output_channel = $3, premium_level = $4;",
-        guild_id.0 as i64,
-        voice_id as i64,
-        result_id as i64,
-        0 as i16
+    // rerunning setup replaces the default bind instead of adding another one
+    let result = setup_guild(
+        db,
+        guild_id,
+        ChannelId(voice_id),
+        final_id,
+        &OutputWebhook { id, token },
     )
-    .execute(db)
-    .await
-    {
+    .await;
+    match &result {
         Err(err) => {
-            tracing::error!("Couldn't insert to guilds: {}", err);
+            tracing::error!("Couldn't set up {}: {}", guild_id, err);
             embed
                 .title("Ugh, I couldn't write that down..")
                 .description("I just let my developer know, until then you could just try again");
         }
-        _ => {
-            match query!(
-                "INSERT INTO channels (channel_id, webhook_token, webhook_id)
-            VALUES($1, $2, $3) ON CONFLICT (channel_id) DO UPDATE SET webhook_token = $2, webhook_id = $3;",
-                result_id as i64,
-                token,
-                i64::from(id)
-            )
-            .execute(db)
-            .await
-            {
-                Err(err) => {
-                    tracing::error!("Couldn't insert to channels: {:?}", err);
-                    embed
-                        .title("Ugh, I couldn't write that down..")
-                        .description(
-                            "I just let my developer know, until then you could just try again",
-                        );
-                }
-                _ => {
-                    embed
-                        .title("Set up successfully!")
-                        .description("Give the bot a few moments to join the VC.\n\n\
-                        **PLEASE NOTE!**\n\
-                        Accuracy will be *extremely* low, unless you are in a perfectly silent room \
-                        with a top-of-the-line mic speaking clearly, as well as being a 18 to 24 year \
-                        old American male. The devs are trying their hardest to fix this, but it's not \
-                        easy. If you have a spare (Linux) computer with 3TB of storage and a Nvidia \
-                        GPU with at least 8GB VRAM that we can borrow, we would love to hear from you. \
-                        Please get in touch with 0/0 on the support server: https://discord.gg/zero-boats")
-                        .field(
-                            "Public Stats",
-                            "Scripty's stats are also public for anyone to view at \
-                            https://stats.imaskeleton.me\nThese stats are quite detailed and show \
-                            almost all info the bot has.",
-                            false
-                        );
-                }
-            }
+        Ok(_) => {
+            embed
+                .title("Set up successfully!")
+                .description(
+                    "Give the bot a few moments to join the VC.\n\n\
+                **PLEASE NOTE!**\n\
+                Accuracy will be *extremely* low, unless you are in a perfectly silent room \
+                with a top-of-the-line mic speaking clearly, as well as being a 18 to 24 year \
+                old American male. The devs are trying their hardest to fix this, but it's not \
+                easy. If you have a spare (Linux) computer with 3TB of storage and a Nvidia \
+                GPU with at least 8GB VRAM that we can borrow, we would love to hear from you. \
+                Please get in touch with 0/0 on the support server: https://discord.gg/zero-boats",
+                )
+                .field(
+                    "Public Stats",
+                    "Scripty's stats are also public for anyone to view at \
+                    https://stats.imaskeleton.me\nThese stats are quite detailed and show \
+                    almost all info the bot has.",
+                    false,
+                );
         }
     }
 
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
+    result?;
 
     if let Err(e) = bind(ctx, voice_id.into(), result_id.into(), guild_id).await {
         msg.reply(ctx, format!("Connecting to VC failed! `{}`. Wait a few more \
                 minutes for the bot to run auto-join, and if it still doesn't join, let the devs know.", e)).await?;
+        return Err(e.into());
     };
 
     Ok(())
@@ -1,4 +1,3 @@
-use scripty_macros::handle_serenity_error;
 use scripty_utils::ShardManagerWrapper;
 use serenity::{
     client::Context,
@@ -12,14 +11,9 @@ use std::hint::unreachable_unchecked;
 whether the stack overflows before exit."]
 #[owners_only]
 async fn cmd_shutdown(ctx: &Context, msg: &Message) -> CommandResult {
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(ctx, |m| m.content("Beginning shutdown..."))
-        .await
-    {
-        handle_serenity_error!(e);
-        return Ok(());
-    }
+        .await?;
     let data = ctx.data.write().await;
     let manager = data
         .get::<ShardManagerWrapper>()
@@ -27,13 +21,9 @@ async fn cmd_shutdown(ctx: &Context, msg: &Message) -> CommandResult {
     let manager = manager.write().await;
     manager.lock().await.shutdown_all().await;
 
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| m.content("All shards shut down."))
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
 
     Ok(())
 }
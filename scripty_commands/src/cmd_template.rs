@@ -1,4 +1,3 @@
-use scripty_macros::handle_serenity_error;
 use serenity::{
     builder::CreateEmbed,
     client::Context,
@@ -13,17 +12,13 @@ use serenity::{
 async fn cmd_ping(ctx: &Context, msg: &Message) -> CommandResult {
     let mut embed = CreateEmbed::default();
     embed.title("Template Command");
-    if let Err(e) = msg
-        .channel_id
+    msg.channel_id
         .send_message(&ctx, |m| {
             m.embed(|e| {
                 *e = embed;
                 e
             })
         })
-        .await
-    {
-        handle_serenity_error!(e);
-    }
+        .await?;
     Ok(())
 }
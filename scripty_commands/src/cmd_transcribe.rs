@@ -0,0 +1,58 @@
+use scripty_audio::{
+    attachment_channel_premium, guild_premium_level, is_audio_attachment, reply_with_transcripts,
+};
+use scripty_db::PgPoolKey;
+use scripty_metrics::UserError;
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("transcribe")]
+#[aliases("tr")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Transcribe the audio files and voice messages on a message. Reply to the \
+message with this command, or attach the audio to the command itself.\nHow long the audio can \
+be depends on the server's premium level."]
+async fn cmd_transcribe(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for the transcribe command");
+            return Ok(());
+        }
+    };
+    let target = msg.referenced_message.as_deref().unwrap_or(msg);
+
+    let premium_level = {
+        let data = ctx.data.read().await;
+        let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+        // audio on the command itself in a channel that transcribes everything already gets
+        // picked up by the message handler
+        if target.id == msg.id
+            && target.attachments.iter().any(is_audio_attachment)
+            && attachment_channel_premium(db, msg).await.is_some()
+        {
+            return Ok(());
+        }
+        guild_premium_level(db, guild_id).await
+    };
+
+    if reply_with_transcripts(ctx, target, premium_level).await == 0 {
+        msg.channel_id
+            .send_message(&ctx, |m| {
+                m.embed(|e| {
+                    e.title("There's no audio there.").description(
+                        "Reply to a message with an audio file or voice message, or attach \
+                        one to the command.",
+                    )
+                })
+            })
+            .await?;
+        return Err(UserError("no audio to transcribe".to_string()).into());
+    }
+    Ok(())
+}
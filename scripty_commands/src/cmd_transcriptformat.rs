@@ -0,0 +1,89 @@
+use scripty_audio::TranscriptFormat;
+use scripty_db::{set_transcript_format, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::Message,
+};
+
+#[command("transcript_format")]
+#[aliases("transcript", "transcript-format")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Pick the format of the transcript file uploaded when a voice session ends, or \
+turn those files off. Can be `markdown`, `text`, `json` or `off`.\n\
+The file goes to the session's thread if it has one, otherwise the transcription channel."]
+#[usage = "<markdown|text|json|off>"]
+#[example = "markdown"]
+async fn cmd_transcript_format(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut embed = CreateEmbed::default();
+    let mut result: CommandResult = Ok(());
+
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::info!("msg.guild_id is None for the transcript_format command");
+            return Ok(());
+        }
+    };
+
+    let format = match args.single::<String>().map(|a| a.to_lowercase()).as_deref() {
+        Ok("off") | Ok("none") | Ok("false") | Ok("no") => Some(None),
+        Ok(name) => TranscriptFormat::from_name(name).map(Some),
+        Err(_) => None,
+    };
+
+    match format {
+        None => {
+            embed
+                .title("Which format do you want?")
+                .description("Run this again with `markdown`, `text`, `json` or `off` after it.");
+            result = Err(UserError("no format given".to_string()).into());
+        }
+        Some(format) => {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+
+            match set_transcript_format(db, guild_id, format.map(TranscriptFormat::name)).await {
+                Err(err) => {
+                    tracing::info!("Couldn't update transcript_format: {}", err);
+                    embed
+                        .title("Ugh, I couldn't write that down..")
+                        .description(
+                            "I just let my developer know, until then you could just try again",
+                        );
+                    result = Err(err.into());
+                }
+                Ok(false) => {
+                    embed
+                        .title("This server isn't set up yet.")
+                        .description("Run `setup` first, then try this again.");
+                    result = Err(UserError("server isn't set up".to_string()).into());
+                }
+                Ok(true) => {
+                    embed.description(match format {
+                        Some(f) => format!(
+                            "Done! Sessions starting from now on will end with a `.{}` \
+                            transcript.",
+                            f.extension()
+                        ),
+                        None => "Done! Sessions won't end with a transcript anymore.".to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+    result
+}
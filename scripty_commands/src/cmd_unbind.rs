@@ -0,0 +1,93 @@
+use scripty_audio::{connected_channel, voice_contexts};
+use scripty_db::{remove_binding, PgPoolKey};
+use scripty_metrics::UserError;
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::prelude::{ChannelId, Message},
+    prelude::Mentionable,
+};
+use std::hint::unreachable_unchecked;
+
+#[command("unbind")]
+#[aliases("remove_bind", "remove_binding")]
+#[required_permissions("MANAGE_GUILD")]
+#[only_in("guilds")]
+#[bucket = "expensive"]
+#[description = "Stop transcribing a voice chat. If I'm in it, I'll leave."]
+#[usage = "<voice chat ID>"]
+#[example = "675390856253014039"]
+async fn cmd_unbind(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let voice_channel = match args.single::<ChannelId>() {
+        Ok(id) => id,
+        Err(_) => {
+            let error = "The voice chat ID you gave was invalid.";
+            msg.channel_id
+                .send_message(&ctx, |m| m.content(error))
+                .await?;
+            return Err(UserError(error.to_string()).into());
+        }
+    };
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::error!("msg.guild_id is None for the unbind command");
+            return Ok(());
+        }
+    };
+
+    let mut embed = CreateEmbed::default();
+    let removed = {
+        let data = ctx.data.read().await;
+        let db = data
+            .get::<PgPoolKey>()
+            .unwrap_or_else(|| unsafe { unreachable_unchecked() });
+
+        remove_binding(db, guild_id, voice_channel).await
+    };
+
+    let mut result: CommandResult = Ok(());
+    match removed {
+        Ok(true) => {
+            // the main bot or any of the workers could be the one in there
+            for c in voice_contexts(ctx, guild_id).await {
+                if connected_channel(&c, guild_id).await == Some(voice_channel.0) {
+                    let manager = unsafe { songbird::get(&c).await.unwrap_unchecked() };
+                    if let Err(e) = manager.remove(guild_id).await {
+                        tracing::warn!("failed to leave VC in {}: {}", guild_id, e);
+                    }
+                }
+            }
+            embed.title("Unbound successfully!").description(format!(
+                "I won't transcribe {} anymore.",
+                voice_channel.mention()
+            ));
+        }
+        Ok(false) => {
+            embed.title("Nothing to unbind.").description(format!(
+                "{} isn't bound in this server. Use `bindings` to see what is.",
+                voice_channel.mention()
+            ));
+            result = Err(UserError("voice chat isn't bound".to_string()).into());
+        }
+        Err(err) => {
+            tracing::error!("Couldn't delete from bindings: {}", err);
+            embed
+                .title("Ugh, I couldn't write that down..")
+                .description("I just let my developer know, until then you could just try again");
+            result = Err(err.into());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
@@ -0,0 +1,95 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use scripty_db::{fetch_guild_premium, monthly_quota_ms, monthly_usage, PgPoolKey};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{macros::command, CommandResult},
+    model::prelude::Message,
+};
+use std::hint::unreachable_unchecked;
+
+#[command("usage")]
+#[aliases("quota")]
+#[only_in("guilds")]
+#[bucket = "general"]
+#[description = "See how much of this month's transcription time this server has used."]
+async fn cmd_usage(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(g) => g,
+        None => {
+            tracing::error!("msg.guild_id is None for the usage command");
+            return Ok(());
+        }
+    };
+
+    let mut embed = CreateEmbed::default();
+    let usage = {
+        let data = ctx.data.read().await;
+        let db = data
+            .get::<PgPoolKey>()
+            .unwrap_or_else(|| unsafe { unreachable_unchecked() });
+
+        let premium_level = fetch_guild_premium(db, guild_id).await;
+        let used = monthly_usage(db, guild_id).await;
+
+        premium_level.and_then(|l| used.map(|u| (l, u)))
+    };
+
+    let mut result: CommandResult = Ok(());
+    match usage {
+        Ok((premium_level, used)) => {
+            let today = Utc::today().naive_utc();
+            let resets = if today.month() == 12 {
+                NaiveDate::from_ymd(today.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd(today.year(), today.month() + 1, 1)
+            };
+
+            match monthly_quota_ms(premium_level) {
+                Some(quota) => {
+                    embed
+                        .title(format!(
+                            "{} of {} minutes used this month",
+                            used / 60_000,
+                            quota / 60_000
+                        ))
+                        .description(format!(
+                            "That's {:.1}% of this server's quota. {}",
+                            used as f64 / quota as f64 * 100.0,
+                            if used >= quota {
+                                "Transcription is paused until it resets."
+                            } else {
+                                "Transcription pauses once it's all used up."
+                            }
+                        ))
+                        .field("Premium level", premium_level, true)
+                        .field("Resets on", resets.format("%B %-d"), true);
+                }
+                None => {
+                    embed
+                        .title(format!("{} minutes used this month", used / 60_000))
+                        .description("This server has no limit on transcription time.")
+                        .field("Premium level", premium_level, true);
+                }
+            }
+        }
+        Err(err) => {
+            tracing::error!("Couldn't fetch usage: {}", err);
+            embed
+                .title("Ugh, I couldn't read that..")
+                .description("I just let my developer know, until then you could just try again");
+            result = Err(err.into());
+        }
+    }
+
+    msg.channel_id
+        .send_message(&ctx, |m| {
+            m.embed(|e| {
+                *e = embed;
+                e
+            })
+        })
+        .await?;
+
+    result
+}
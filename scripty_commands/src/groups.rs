@@ -1,22 +1,45 @@
 use super::*;
-use serenity::framework::standard::macros::group;
+use serenity::framework::standard::{macros::group, CommandGroup};
 
 #[group("General Stuff")]
-#[commands(cmd_info, cmd_prefix, cmd_donate)]
+#[commands(cmd_info, cmd_prefix, cmd_donate, cmd_link_github)]
 struct General;
 
 #[group("Bot Utils")]
-#[commands(cmd_ping, cmd_credits, cmd_stats)]
+#[commands(cmd_ping, cmd_credits, cmd_stats, cmd_usage)]
 struct Utils;
 
 #[group("Voice Commands")]
-#[commands(cmd_join)]
+#[commands(cmd_join, cmd_transcribe)]
 struct Voice;
 
 #[group("Config Commands")]
-#[commands(cmd_setup)]
+#[commands(
+    cmd_setup,
+    cmd_bind,
+    cmd_unbind,
+    cmd_bindings,
+    cmd_session_threads,
+    cmd_transcript_format,
+    cmd_live_feed,
+    cmd_auto_transcribe
+)]
 struct Config;
 
+#[group("API Commands")]
+#[commands(cmd_getkey, cmd_keys, cmd_rotate_key, cmd_revoke_key, cmd_api_usage)]
+struct Api;
+
 #[group("Bot Owner Commands")]
 #[commands(cmd_rejoin_all, cmd_shutdown, cmd_add_premium, cmd_eval)]
 struct BotOwner;
+
+/// Every command group, in the order they're registered with the framework.
+pub static ALL_GROUPS: &[&CommandGroup] = &[
+    &GENERAL_GROUP,
+    &UTILS_GROUP,
+    &VOICE_GROUP,
+    &CONFIG_GROUP,
+    &API_GROUP,
+    &BOTOWNER_GROUP,
+];
@@ -3,6 +3,10 @@
 #![feature(once_cell)]
 
 mod cmd_addpremium;
+mod cmd_apiusage;
+mod cmd_autotranscribe;
+mod cmd_bind;
+mod cmd_bindings;
 mod cmd_credits;
 mod cmd_donate;
 pub mod cmd_error;
@@ -11,16 +15,30 @@ mod cmd_getkey;
 mod cmd_help;
 mod cmd_info;
 mod cmd_join;
+mod cmd_keys;
+mod cmd_linkgithub;
+mod cmd_livefeed;
 mod cmd_ping;
 mod cmd_prefix;
 mod cmd_rejoinall;
+mod cmd_revokekey;
+mod cmd_rotatekey;
+mod cmd_sessionthreads;
 mod cmd_setup;
 mod cmd_shutdown;
 mod cmd_stats;
 mod cmd_template;
+mod cmd_transcribe;
+mod cmd_transcriptformat;
+mod cmd_unbind;
+mod cmd_usage;
 pub mod groups;
 
 pub use cmd_addpremium::*;
+pub use cmd_apiusage::*;
+pub use cmd_autotranscribe::*;
+pub use cmd_bind::*;
+pub use cmd_bindings::*;
 pub use cmd_credits::*;
 pub use cmd_donate::*;
 pub use cmd_error::*;
@@ -29,12 +47,22 @@ pub use cmd_getkey::*;
 pub use cmd_help::*;
 pub use cmd_info::*;
 pub use cmd_join::*;
+pub use cmd_keys::*;
+pub use cmd_linkgithub::*;
+pub use cmd_livefeed::*;
 pub use cmd_ping::*;
 pub use cmd_prefix::*;
 pub use cmd_rejoinall::*;
+pub use cmd_revokekey::*;
+pub use cmd_rotatekey::*;
+pub use cmd_sessionthreads::*;
 pub use cmd_setup::*;
 pub use cmd_shutdown::*;
 pub use cmd_stats::*;
+pub use cmd_transcribe::*;
+pub use cmd_transcriptformat::*;
+pub use cmd_unbind::*;
+pub use cmd_usage::*;
 pub use groups::*;
 // not a real command
 // pub use cmd_template::*;
@@ -1,4 +1,4 @@
-use crate::{DatabaseConnection, BOT_CONFIG};
+use crate::{DatabaseConnection, MetricsExporterConfig, WebConfig, BOT_CONFIG};
 use serde::{Deserialize, Serialize};
 use std::{fs, io};
 
@@ -11,6 +11,9 @@ pub struct BotConfig {
     github: String,
     colour: u32,
     model_path: String,
+    /// Tokens for extra bot accounts that only hold voice connections.
+    #[serde(default)]
+    worker_tokens: Vec<String>,
 
     // DB stuff
     user: String,
@@ -21,6 +24,49 @@ pub struct BotConfig {
     host: Option<String>,
     port: Option<u16>,
     unix_socket: Option<String>,
+
+    /// Where the API and live feeds can be reached from the outside, without a trailing slash.
+    #[serde(default = "default_api_url")]
+    api_url: String,
+    /// Where the WebSocket server for live transcription listens.
+    #[serde(default = "default_stream_address")]
+    stream_address: String,
+    /// The token the admin API has to be called with. The admin API is off without one.
+    #[serde(default)]
+    admin_token: Option<String>,
+    /// The secret GitHub Sponsors signs its webhooks with. The sponsor webhook is off without one.
+    #[serde(default)]
+    sponsor_webhook_secret: Option<String>,
+    /// How many transcription jobs can run at once.
+    #[serde(default = "default_job_workers")]
+    job_workers: usize,
+    /// How long finished transcription jobs are kept around, in hours.
+    #[serde(default = "default_job_retention_hours")]
+    job_retention_hours: u32,
+
+    // tables have to come after plain values in TOML, so these go last
+    /// The web server's address, port, TLS and CORS settings.
+    #[serde(default)]
+    web: WebConfig,
+    /// Where to push metrics to, besides serving them for Prometheus.
+    #[serde(default)]
+    metrics_exporters: Vec<MetricsExporterConfig>,
+}
+
+fn default_api_url() -> String {
+    "https://api.scripty.imaskeleton.me".to_string()
+}
+
+fn default_stream_address() -> String {
+    "127.0.0.1:8001".to_string()
+}
+
+fn default_job_workers() -> usize {
+    1
+}
+
+fn default_job_retention_hours() -> u32 {
+    72
 }
 
 impl BotConfig {
@@ -36,12 +82,21 @@ impl BotConfig {
                         github: "https://github.com/tazz4843/scripty".to_string(),
                         colour: 11771355,
                         model_path: "/home/user/deepspeech".to_string(),
+                        worker_tokens: Vec::new(),
                         user: "scripty".to_string(),
                         password: "scripty".to_string(),
                         db: "scripty".to_string(),
                         host: None,
                         port: None,
                         unix_socket: Some("/var/run/postgresql/".to_string()),
+                        api_url: default_api_url(),
+                        stream_address: default_stream_address(),
+                        admin_token: None,
+                        sponsor_webhook_secret: None,
+                        job_workers: default_job_workers(),
+                        job_retention_hours: default_job_retention_hours(),
+                        web: WebConfig::default(),
+                        metrics_exporters: Vec::new(),
                     };
                     let default_cfg_str =
                         toml::to_string_pretty(&default_cfg).expect("failed to serialize config");
@@ -88,6 +143,44 @@ impl BotConfig {
     pub fn model_path(&self) -> &String {
         &self.model_path
     }
+    /// Get the tokens of the worker bots.
+    ///
+    /// Each worker can hold one voice connection per guild, on top of the main bot's.
+    pub fn worker_tokens(&self) -> &[String] {
+        &self.worker_tokens
+    }
+    /// Get the public URL of the API.
+    pub fn api_url(&self) -> &String {
+        &self.api_url
+    }
+    /// Get the address the live transcription WebSocket server listens on.
+    pub fn stream_address(&self) -> &String {
+        &self.stream_address
+    }
+    /// Get the token the admin API has to be called with, if it's turned on.
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_deref()
+    }
+    /// Get the secret sponsor webhooks are signed with, if the webhook is turned on.
+    pub fn sponsor_webhook_secret(&self) -> Option<&str> {
+        self.sponsor_webhook_secret.as_deref()
+    }
+    /// Get how many transcription jobs can run at once.
+    pub fn job_workers(&self) -> usize {
+        self.job_workers
+    }
+    /// Get how long finished transcription jobs are kept, in hours.
+    pub fn job_retention_hours(&self) -> u32 {
+        self.job_retention_hours
+    }
+    /// Get the web server's settings.
+    pub fn web(&self) -> &WebConfig {
+        &self.web
+    }
+    /// Get the metrics exporters to push to.
+    pub fn metrics_exporters(&self) -> &[MetricsExporterConfig] {
+        &self.metrics_exporters
+    }
     /// Get the database login.
     ///
     /// Returned tuple is user, password, and database respectively.
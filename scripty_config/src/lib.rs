@@ -2,9 +2,13 @@
 
 mod config;
 mod database;
+mod metrics;
+mod web;
 
 pub use config::*;
 pub use database::*;
+pub use metrics::*;
 use std::lazy::SyncOnceCell as OnceCell;
+pub use web::*;
 
 pub static BOT_CONFIG: OnceCell<BotConfig> = OnceCell::new();
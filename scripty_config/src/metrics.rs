@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_prefix() -> String {
+    "scripty".to_string()
+}
+
+/// A place metrics get pushed to, on top of being served at `/metrics` for Prometheus.
+///
+/// In the config file these go in a `[[metrics_exporters]]` table each, with a `kind` of
+/// either `statsd` or `influx`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum MetricsExporterConfig {
+    /// Push to a StatsD server over UDP.
+    Statsd {
+        /// Address of the StatsD server, like `127.0.0.1:8125`.
+        address: String,
+        /// Prepended to every metric name, followed by a dot.
+        #[serde(default = "default_prefix")]
+        prefix: String,
+        /// Seconds between pushes.
+        #[serde(default = "default_interval")]
+        interval: u64,
+    },
+    /// Push to InfluxDB over HTTP, in line protocol.
+    Influx {
+        /// The full write URL, including the database or bucket, like
+        /// `http://127.0.0.1:8086/api/v2/write?org=scripty&bucket=scripty`.
+        url: String,
+        /// Sent as `Authorization: Token <token>` if set.
+        token: Option<String>,
+        /// Seconds between pushes.
+        #[serde(default = "default_interval")]
+        interval: u64,
+    },
+}
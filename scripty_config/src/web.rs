@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::{net::IpAddr, path::Path};
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    8000
+}
+
+/// Paths to the PEM encoded certificate chain and private key the web server uses for HTTPS.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebTlsConfig {
+    pub certs: String,
+    pub key: String,
+}
+
+/// Settings for the web server behind the API, live feeds and `/metrics`.
+///
+/// In the config file this is the `[web]` table. Every field is optional.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebConfig {
+    /// Whether to start the web server at all.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// The IP address to listen on.
+    #[serde(default = "default_address")]
+    pub address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Origins browsers may call the API from, like `https://example.com`. `*` allows any.
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    /// If set, `/metrics` has to be called with this as a bearer token.
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+    /// Serve HTTPS instead of HTTP.
+    #[serde(default)]
+    pub tls: Option<WebTlsConfig>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            address: default_address(),
+            port: default_port(),
+            cors_origins: Vec::new(),
+            metrics_token: None,
+            tls: None,
+        }
+    }
+}
+
+impl WebConfig {
+    /// Whether browsers may call the API from `origin`.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.cors_origins.iter().any(|o| o == "*" || o == origin)
+    }
+
+    /// Checks that the server could actually be started with this, so a typo fails with a
+    /// useful error instead of somewhere deep inside Rocket.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.address.parse::<IpAddr>().is_err() {
+            return Err(format!(
+                "address has to be an IP address like 127.0.0.1 or ::, not {:?}",
+                self.address
+            ));
+        }
+        if let Some(tls) = &self.tls {
+            for path in [&tls.certs, &tls.key] {
+                if !Path::new(path).is_file() {
+                    return Err(format!("TLS file {} doesn't exist", path));
+                }
+            }
+        }
+        Ok(())
+    }
+}
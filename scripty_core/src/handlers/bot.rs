@@ -1,8 +1,11 @@
-use scripty_audio::auto_join;
-use scripty_metrics::spawn_updater_task;
-use scripty_utils::START_TIME;
+use scripty_audio::{
+    attachment_channel_premium, auto_join, is_audio_attachment, reply_with_transcripts,
+};
+use scripty_db::PgPoolKey;
+use scripty_metrics::{heartbeat, register_task, spawn_saver_task, spawn_updater_task};
+use scripty_utils::{BOT_CONTEXT, START_TIME};
 use serenity::model::interactions::InteractionType;
-use serenity::model::prelude::{Interaction, InteractionResponseType};
+use serenity::model::prelude::{Interaction, InteractionResponseType, Message};
 use serenity::{
     async_trait,
     client::{Context, EventHandler},
@@ -40,22 +43,29 @@ impl EventHandler for Handler {
                     .as_millis()
             );
 
+            let _ = BOT_CONTEXT.set(Arc::clone(&ctx));
+
             spawn_updater_task();
+            spawn_saver_task();
 
             let ctx1 = Arc::clone(&ctx);
             let ctx2 = Arc::clone(&ctx);
             let _ctx3 = Arc::clone(&ctx);
             let ctx4 = Arc::clone(&ctx);
+            register_task("stats_update", Duration::from_secs(30));
             tokio::spawn(async move {
                 loop {
                     scripty_utils::do_stats_update(Arc::clone(&ctx1)).await;
+                    heartbeat("stats_update", Duration::from_secs(30));
                     tokio::time::sleep(Duration::from_secs(30)).await;
                 }
             });
 
+            register_task("auto_join", Duration::from_secs(300));
             tokio::spawn(async move {
                 loop {
                     auto_join(Arc::clone(&ctx2), false).await;
+                    heartbeat("auto_join", Duration::from_secs(300));
                     tokio::time::sleep(Duration::from_secs(300)).await;
                 }
             });
@@ -69,14 +79,34 @@ impl EventHandler for Handler {
             });
             */
 
+            register_task("update_status", Duration::from_secs(30));
             tokio::spawn(async move {
                 loop {
                     scripty_utils::update_status(Arc::clone(&ctx4)).await;
+                    heartbeat("update_status", Duration::from_secs(30));
                     tokio::time::sleep(Duration::from_secs(30)).await
                 }
             });
         }
     }
+    async fn message(&self, ctx: Context, msg: Message) {
+        if msg.author.bot
+            || msg.guild_id.is_none()
+            || !msg.attachments.iter().any(is_audio_attachment)
+        {
+            return;
+        }
+
+        let premium_level = {
+            let data = ctx.data.read().await;
+            let db = unsafe { data.get::<PgPoolKey>().unwrap_unchecked() };
+            attachment_channel_premium(db, &msg).await
+        };
+        if let Some(premium_level) = premium_level {
+            reply_with_transcripts(&ctx, &msg, premium_level).await;
+        }
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction.kind() {
             InteractionType::ApplicationCommand => {
@@ -2,14 +2,16 @@
 #![feature(option_result_unwrap_unchecked)]
 
 mod handlers;
+mod workers;
 
 use crate::handlers::bot::Handler;
 use crate::handlers::raw::RawHandler;
+use crate::workers::start_workers;
 use scripty_commands::groups::*;
 use scripty_commands::{cmd_error, prefix_check, CMD_HELP};
 use scripty_config::BotConfig;
-use scripty_db::{set_db, PgPoolKey};
-use scripty_metrics::{Metrics, METRICS};
+use scripty_db::{connect_db, schema_status, set_db, PgPoolKey};
+use scripty_metrics::{register_commands, spawn_exporters, Metrics, METRICS};
 use scripty_utils::{set_dir, BotInfo, ReqwestClient, ShardManagerWrapper};
 use serenity::{
     client::{bridge::gateway::GatewayIntents, parse_token},
@@ -38,6 +40,11 @@ pub async fn entrypoint() {
     let config = BotConfig::get().expect("Couldn't access BOT_CONFIG to get the token");
     info!("Loaded config!");
 
+    if std::env::args().nth(1).as_deref() == Some("migrations") {
+        print_migrations().await;
+        return;
+    }
+
     BotInfo::set(config.token()).await;
     let bot_info = BotInfo::get();
 
@@ -85,7 +92,7 @@ pub async fn entrypoint() {
             .crypto_mode(CryptoMode::Normal),
     );
 
-    let framework = StandardFramework::new()
+    let mut framework = StandardFramework::new()
         .configure(|c| {
             c.prefix("~")
                 .no_dm_prefix(true)
@@ -115,12 +122,12 @@ pub async fn entrypoint() {
         })
         .await
         .before(scripty_metrics::before_hook)
-        .help(&CMD_HELP)
-        .group(&GENERAL_GROUP)
-        .group(&UTILS_GROUP)
-        .group(&VOICE_GROUP)
-        .group(&CONFIG_GROUP)
-        .group(&BOTOWNER_GROUP);
+        .after(scripty_metrics::after_hook)
+        .help(&CMD_HELP);
+    for group in ALL_GROUPS {
+        framework = framework.group(group);
+    }
+    register_commands(ALL_GROUPS);
 
     let token_info = parse_token(&config.token()).expect("invalid token");
     let app_id = token_info.bot_user_id.as_u64();
@@ -138,9 +145,9 @@ pub async fn entrypoint() {
             start_time: client_init_start,
         })
         .raw_event_handler(RawHandler)
-        .type_map_insert::<PgPoolKey>(db)
+        .type_map_insert::<PgPoolKey>(db.clone())
         .type_map_insert::<Metrics>(Arc::clone(&metrics))
-        .type_map_insert::<ReqwestClient>(http_client)
+        .type_map_insert::<ReqwestClient>(http_client.clone())
         .framework(framework)
         .register_songbird_with(songbird)
         .application_id(*app_id)
@@ -158,15 +165,34 @@ pub async fn entrypoint() {
             .as_millis()
     );
 
-    info!("Starting metrics server...");
-    let st = SystemTime::now();
-    let server_shutdown = scripty_webserver::start()
-        .await
-        .unwrap_or_else(|_| unsafe { unreachable_unchecked() });
-    info!(
-        "Started metrics server in {}ms!",
-        st.elapsed().expect("system clock rolled back").as_millis()
-    );
+    let server_shutdown = if config.web().enabled {
+        info!("Starting metrics server...");
+        let st = SystemTime::now();
+        let shutdown = scripty_webserver::start()
+            .await
+            .unwrap_or_else(|e| panic!("Couldn't start the web server: {}", e));
+        info!(
+            "Started metrics server in {}ms!",
+            st.elapsed().expect("system clock rolled back").as_millis()
+        );
+        Some(shutdown)
+    } else {
+        info!("The web server is turned off, not starting it");
+        None
+    };
+
+    if !config.metrics_exporters().is_empty() {
+        info!(
+            "Starting {} metrics exporters...",
+            config.metrics_exporters().len()
+        );
+        spawn_exporters(config.metrics_exporters()).await;
+    }
+
+    if !config.worker_tokens().is_empty() {
+        info!("Starting {} workers...", config.worker_tokens().len());
+        start_workers(db, Arc::clone(&metrics), http_client).await;
+    }
 
     let shard_manager = client.shard_manager.clone();
 
@@ -180,6 +206,35 @@ pub async fn entrypoint() {
         error!("Couldn't start the client: {}", e);
     }
 
-    server_shutdown.notify();
+    if let Some(server_shutdown) = server_shutdown {
+        server_shutdown.notify();
+    }
     metrics.save_metrics().await;
 }
+
+/// Prints which migrations have been applied to the database, for `scripty migrations`.
+async fn print_migrations() {
+    let db = connect_db().await;
+    let status = match schema_status(&db).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Couldn't read the database's migrations: {}", e);
+            return;
+        }
+    };
+
+    if !status.tracked {
+        println!(
+            "This database has never been migrated, every migration will run on the next start."
+        );
+    }
+    for m in &status.migrations {
+        println!("{} {:<24} {}", m.version, m.description, m.state);
+    }
+    for v in &status.unknown {
+        println!(
+            "{} {:<24} unknown to this version, so it won't start",
+            v, "?"
+        );
+    }
+}
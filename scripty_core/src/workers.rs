@@ -0,0 +1,76 @@
+use scripty_audio::register_worker;
+use scripty_config::BotConfig;
+use scripty_db::PgPoolKey;
+use scripty_metrics::Metrics;
+use scripty_utils::ReqwestClient;
+use serenity::{
+    async_trait,
+    client::{bridge::gateway::GatewayIntents, Context, EventHandler},
+    model::prelude::Ready,
+    Client,
+};
+use songbird::{
+    driver::{Config as DriverConfig, CryptoMode, DecodeMode},
+    SerenityInit, Songbird,
+};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::{error, info};
+
+struct WorkerHandler;
+
+#[async_trait]
+impl EventHandler for WorkerHandler {
+    async fn ready(&self, ctx: Context, _ready: Ready) {
+        register_worker(ctx).await;
+    }
+}
+
+/// Starts a client for every worker token in the config.
+///
+/// Workers only hold voice connections, so they don't get a framework or a raw event handler.
+/// They do share the main bot's DB pool, metrics and HTTP client, so a `Receiver` works the same
+/// on either.
+pub async fn start_workers(
+    db: Pool<Postgres>,
+    metrics: Arc<Metrics>,
+    http_client: reqwest::Client,
+) {
+    let config = BotConfig::get().expect("Couldn't access BOT_CONFIG to get the worker tokens");
+
+    for (i, token) in config.worker_tokens().iter().enumerate() {
+        let songbird = Songbird::serenity();
+        songbird.set_config(
+            DriverConfig::default()
+                .decode_mode(DecodeMode::Decode)
+                .crypto_mode(CryptoMode::Normal),
+        );
+
+        let mut client = match Client::builder(token)
+            .intents(
+                GatewayIntents::GUILDS
+                    | GatewayIntents::GUILD_VOICE_STATES
+                    | GatewayIntents::GUILD_MEMBERS,
+            )
+            .event_handler(WorkerHandler)
+            .type_map_insert::<PgPoolKey>(db.clone())
+            .type_map_insert::<Metrics>(Arc::clone(&metrics))
+            .type_map_insert::<ReqwestClient>(http_client.clone())
+            .register_songbird_with(songbird)
+            .await
+        {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Couldn't create worker {}: {}", i, e);
+                continue;
+            }
+        };
+
+        info!("Starting worker {}...", i);
+        tokio::spawn(async move {
+            if let Err(e) = client.start_autosharded().await {
+                error!("Couldn't start worker {}: {}", i, e);
+            }
+        });
+    }
+}
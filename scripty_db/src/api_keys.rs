@@ -0,0 +1,292 @@
+use crate::{premium_from_db, DbResult};
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serenity::model::id::UserId;
+use sha2::{Digest, Sha256};
+use sqlx::{query, PgPool};
+
+/// Every API key starts with this, so they're easy to spot (and to scan for if one leaks).
+pub const API_KEY_PREFIX: &str = "scr_";
+
+/// Live feed tokens start with this instead.
+pub const LIVE_TOKEN_PREFIX: &str = "scl_";
+
+/// How many characters of a key, after `API_KEY_PREFIX`, are stored in plain text to tell keys
+/// apart.
+const VISIBLE_CHARS: usize = 8;
+
+/// How many random characters make up a key. Alphanumeric characters carry just under 6 bits
+/// each, so this is about 238 bits.
+const KEY_CHARS: usize = 40;
+
+/// The most API keys one user can have at once, not counting revoked ones.
+pub const MAX_API_KEYS: i64 = 5;
+
+/// A freshly generated API key. `key` is only ever shown to its owner once: only `hash` is
+/// stored.
+pub struct NewApiKey {
+    pub key: String,
+    /// The start of the key, stored in plain text so owners can tell their keys apart.
+    pub prefix: String,
+    pub hash: String,
+}
+
+/// Generates a new random API key.
+pub fn generate_api_key() -> NewApiKey {
+    generate_key(API_KEY_PREFIX)
+}
+
+/// Generates a new random token for a guild's live feed. These are hashed just like API keys.
+pub fn generate_live_token() -> NewApiKey {
+    generate_key(LIVE_TOKEN_PREFIX)
+}
+
+fn generate_key(prefix: &str) -> NewApiKey {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_CHARS)
+        .map(char::from)
+        .collect();
+    let key = format!("{}{}", prefix, random);
+    NewApiKey {
+        prefix: key[..prefix.len() + VISIBLE_CHARS].to_string(),
+        hash: hash_api_key(&key),
+        key,
+    }
+}
+
+/// Hashes an API key the way it's stored in the `api_keys` table.
+///
+/// Keys are long and random, so a plain SHA-256 is enough: there's nothing to brute force.
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Something an API key is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    /// Use the speech to text endpoints.
+    Transcribe,
+    /// Read the bot's stats.
+    Stats,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 2] = [ApiScope::Transcribe, ApiScope::Stats];
+
+    /// The name this scope is stored under in the DB.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Transcribe => "transcribe",
+            Self::Stats => "stats",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "transcribe" | "stt" => Some(Self::Transcribe),
+            "stats" | "read-stats" | "read_stats" => Some(Self::Stats),
+            _ => None,
+        }
+    }
+}
+
+fn scopes_from_db(names: &[String]) -> Vec<ApiScope> {
+    names
+        .iter()
+        .filter_map(|s| ApiScope::from_name(s))
+        .collect()
+}
+
+/// An API key, as stored in the `api_keys` table.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub key_id: i64,
+    pub user_id: UserId,
+    /// The start of the key, as shown to its owner.
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// An unrevoked API key looked up by its hash, with what's needed to let a request through.
+pub struct KeyAuthorization {
+    pub key_id: i64,
+    pub user_id: UserId,
+    pub scopes: Vec<ApiScope>,
+    /// The premium level of the key's owner.
+    pub premium_level: u8,
+    /// The key's rate limit bucket as it was last saved, as tokens left and when that was.
+    pub saved_rate_limit: Option<(f64, DateTime<Utc>)>,
+}
+
+/// Stores a new API key for `user_id`.
+pub async fn create_api_key(
+    db: &PgPool,
+    user_id: UserId,
+    key: &NewApiKey,
+    scopes: &[ApiScope],
+) -> DbResult<()> {
+    let scope_names: Vec<String> = scopes.iter().map(|s| s.name().to_string()).collect();
+    query!(
+        "INSERT INTO api_keys (user_id, key_hash, key_prefix, scopes) VALUES ($1, $2, $3, $4)",
+        user_id.0 as i64,
+        key.hash,
+        key.prefix,
+        &scope_names[..]
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Deletes a key outright, rather than revoking it. Only for keys that never reached their owner.
+pub async fn delete_api_key(db: &PgPool, hash: &str) -> DbResult<()> {
+    query!("DELETE FROM api_keys WHERE key_hash = $1", hash)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// How many unrevoked keys a user has.
+pub async fn count_active_api_keys(db: &PgPool, user_id: UserId) -> DbResult<i64> {
+    Ok(query!(
+        r#"SELECT count(*) AS "count!" FROM api_keys
+        WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id.0 as i64
+    )
+    .fetch_one(db)
+    .await?
+    .count)
+}
+
+/// A user's unrevoked keys, oldest first.
+pub async fn list_api_keys(db: &PgPool, user_id: UserId) -> DbResult<Vec<ApiKey>> {
+    Ok(query!(
+        "SELECT key_id, key_prefix, scopes, created_at, last_used_at FROM api_keys
+        WHERE user_id = $1 AND revoked_at IS NULL ORDER BY created_at",
+        user_id.0 as i64
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| ApiKey {
+        key_id: r.key_id,
+        user_id,
+        prefix: r.key_prefix,
+        scopes: scopes_from_db(&r.scopes),
+        created_at: r.created_at,
+        last_used_at: r.last_used_at,
+    })
+    .collect())
+}
+
+/// Finds one of a user's unrevoked keys by its prefix.
+pub async fn find_api_key(db: &PgPool, user_id: UserId, prefix: &str) -> DbResult<Option<ApiKey>> {
+    Ok(query!(
+        "SELECT key_id, key_prefix, scopes, created_at, last_used_at FROM api_keys
+        WHERE user_id = $1 AND key_prefix = $2 AND revoked_at IS NULL",
+        user_id.0 as i64,
+        prefix
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|r| ApiKey {
+        key_id: r.key_id,
+        user_id,
+        prefix: r.key_prefix,
+        scopes: scopes_from_db(&r.scopes),
+        created_at: r.created_at,
+        last_used_at: r.last_used_at,
+    }))
+}
+
+/// Looks up an unrevoked key by its hash, for authenticating a request with it.
+pub async fn authorize_api_key(db: &PgPool, hash: &str) -> DbResult<Option<KeyAuthorization>> {
+    let r = match query!(
+        "SELECT api_keys.key_id, api_keys.user_id, api_keys.scopes, api_keys.rate_tokens,
+          api_keys.rate_updated_at, users.premium_level
+        FROM api_keys LEFT JOIN users ON users.user_id = api_keys.user_id
+        WHERE api_keys.key_hash = $1 AND api_keys.revoked_at IS NULL",
+        hash
+    )
+    .fetch_optional(db)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let user_id = match r.user_id {
+        Some(u) => UserId(u as u64),
+        None => return Ok(None),
+    };
+
+    Ok(Some(KeyAuthorization {
+        key_id: r.key_id,
+        user_id,
+        scopes: scopes_from_db(&r.scopes),
+        premium_level: match r.premium_level {
+            Some(l) => premium_from_db("premium_level", l)?,
+            None => 0,
+        },
+        saved_rate_limit: r.rate_tokens.zip(r.rate_updated_at),
+    }))
+}
+
+/// Records that a key was just used.
+pub async fn touch_api_key(db: &PgPool, key_id: i64) -> DbResult<()> {
+    query!(
+        "UPDATE api_keys SET last_used_at = now() WHERE key_id = $1",
+        key_id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Saves where a key's rate limit bucket stands, so restarts don't refill it.
+pub async fn save_rate_limit(
+    db: &PgPool,
+    key_id: i64,
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+) -> DbResult<()> {
+    query!(
+        "UPDATE api_keys SET rate_tokens = $2, rate_updated_at = $3 WHERE key_id = $1",
+        key_id,
+        tokens,
+        updated_at
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Revokes a key, so it stops working straight away.
+pub async fn revoke_api_key(db: &PgPool, key_id: i64) -> DbResult<()> {
+    query!(
+        "UPDATE api_keys SET revoked_at = now() WHERE key_id = $1",
+        key_id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Revokes one of a user's keys by its prefix. Returns `false` if they don't have it.
+pub async fn revoke_api_key_by_prefix(
+    db: &PgPool,
+    user_id: UserId,
+    prefix: &str,
+) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE api_keys SET revoked_at = now()
+        WHERE user_id = $1 AND key_prefix = $2 AND revoked_at IS NULL",
+        user_id.0 as i64,
+        prefix
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
@@ -0,0 +1,144 @@
+use crate::{premium_from_db, DbResult};
+use serenity::model::id::{ChannelId, GuildId};
+use sqlx::{query, Executor, PgPool, Postgres};
+
+/// A voice chat that gets transcribed, and where its transcriptions go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub voice_channel: ChannelId,
+    pub guild_id: GuildId,
+    pub output_channel: ChannelId,
+}
+
+/// Every binding in a guild, in the order of their voice chats' IDs.
+pub async fn guild_bindings(db: &PgPool, guild_id: GuildId) -> DbResult<Vec<Binding>> {
+    Ok(query!(
+        "SELECT voice_channel, output_channel FROM bindings WHERE guild_id = $1
+        ORDER BY voice_channel",
+        guild_id.0 as i64
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| Binding {
+        voice_channel: ChannelId(r.voice_channel as u64),
+        guild_id,
+        output_channel: ChannelId(r.output_channel as u64),
+    })
+    .collect())
+}
+
+/// Every binding of `guild_id`, or of every guild if it's `None`, along with the premium level
+/// of the guild it's in.
+pub async fn bindings_with_premium(
+    db: &PgPool,
+    guild_id: Option<GuildId>,
+) -> DbResult<Vec<(Binding, u8)>> {
+    query!(
+        "SELECT bindings.voice_channel, bindings.guild_id, bindings.output_channel, guilds.premium_level
+        FROM bindings INNER JOIN guilds ON guilds.guild_id = bindings.guild_id
+        WHERE $1::BIGINT IS NULL OR bindings.guild_id = $1
+        ORDER BY bindings.voice_channel",
+        guild_id.map(|g| g.0 as i64)
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok((
+            Binding {
+                voice_channel: ChannelId(r.voice_channel as u64),
+                guild_id: GuildId(r.guild_id as u64),
+                output_channel: ChannelId(r.output_channel as u64),
+            },
+            premium_from_db("premium_level", r.premium_level)?,
+        ))
+    })
+    .collect()
+}
+
+/// How many voice chats a guild has bound, not counting `voice_channel`.
+pub async fn count_other_bindings(
+    db: &PgPool,
+    guild_id: GuildId,
+    voice_channel: ChannelId,
+) -> DbResult<usize> {
+    Ok(query!(
+        "SELECT count(*) AS \"count!\" FROM bindings WHERE guild_id = $1 AND voice_channel != $2",
+        guild_id.0 as i64,
+        voice_channel.0 as i64
+    )
+    .fetch_one(db)
+    .await?
+    .count as usize)
+}
+
+/// Binds a voice chat, or changes where its transcriptions go if it's already bound.
+pub async fn set_binding<'e, E>(db: E, binding: &Binding) -> DbResult<()>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    query!(
+        "INSERT INTO bindings (voice_channel, guild_id, output_channel) VALUES ($1, $2, $3)
+        ON CONFLICT (voice_channel) DO UPDATE SET output_channel = $3",
+        binding.voice_channel.0 as i64,
+        binding.guild_id.0 as i64,
+        binding.output_channel.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Unbinds a voice chat in a guild, along with it being the guild's default bind. Returns
+/// `false` if it wasn't bound there.
+pub async fn remove_binding(
+    db: &PgPool,
+    guild_id: GuildId,
+    voice_channel: ChannelId,
+) -> DbResult<bool> {
+    let mut tx = db.begin().await?;
+
+    let r = query!(
+        "DELETE FROM bindings WHERE voice_channel = $1 AND guild_id = $2",
+        voice_channel.0 as i64,
+        guild_id.0 as i64
+    )
+    .execute(&mut tx)
+    .await?;
+    if r.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query!(
+        "UPDATE guilds SET default_bind = NULL WHERE default_bind = $1",
+        voice_channel.0 as i64
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Forgets a binding that can't be used anymore, along with the webhook of its output channel.
+pub async fn forget_binding(db: &PgPool, binding: &Binding) -> DbResult<()> {
+    query!(
+        "DELETE FROM bindings WHERE voice_channel = $1",
+        binding.voice_channel.0 as i64
+    )
+    .execute(db)
+    .await?;
+    query!(
+        "UPDATE guilds SET default_bind = NULL WHERE default_bind = $1",
+        binding.voice_channel.0 as i64
+    )
+    .execute(db)
+    .await?;
+    query!(
+        "DELETE FROM channels WHERE channel_id = $1",
+        binding.output_channel.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
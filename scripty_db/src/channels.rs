@@ -0,0 +1,152 @@
+use crate::{premium_from_db, DbResult};
+use serenity::model::id::{ChannelId, GuildId, WebhookId};
+use sqlx::{query, Executor, PgPool, Postgres};
+
+/// The webhook transcriptions are posted to a channel with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputWebhook {
+    pub id: WebhookId,
+    pub token: String,
+}
+
+/// A channel transcriptions are sent to, as stored in the `channels` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputChannel {
+    pub channel_id: ChannelId,
+    pub webhook_id: Option<WebhookId>,
+    pub webhook_token: Option<String>,
+}
+
+impl OutputChannel {
+    /// The channel's webhook, if both halves of it are stored.
+    pub fn webhook(&self) -> Option<OutputWebhook> {
+        match (self.webhook_id, &self.webhook_token) {
+            (Some(id), Some(token)) => Some(OutputWebhook {
+                id,
+                token: token.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Gets a channel's webhook, or `None` if it doesn't have a row.
+pub async fn fetch_output_channel(
+    db: &PgPool,
+    channel_id: ChannelId,
+) -> DbResult<Option<OutputChannel>> {
+    Ok(query!(
+        "SELECT webhook_token, webhook_id FROM channels WHERE channel_id = $1",
+        channel_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|r| OutputChannel {
+        channel_id,
+        webhook_id: r.webhook_id.map(|w| WebhookId(w as u64)),
+        webhook_token: r.webhook_token,
+    }))
+}
+
+/// Every channel a guild's bindings or `setup` send transcriptions to.
+pub async fn guild_output_channels(db: &PgPool, guild_id: GuildId) -> DbResult<Vec<OutputChannel>> {
+    Ok(query!(
+        "SELECT channel_id, webhook_id, webhook_token FROM channels WHERE channel_id IN (
+          SELECT output_channel FROM bindings WHERE guild_id = $1
+          UNION SELECT output_channel FROM guilds WHERE guild_id = $1
+        )
+        ORDER BY channel_id",
+        guild_id.0 as i64
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| OutputChannel {
+        channel_id: ChannelId(r.channel_id as u64),
+        webhook_id: r.webhook_id.map(|w| WebhookId(w as u64)),
+        webhook_token: r.webhook_token,
+    })
+    .collect())
+}
+
+/// Sets the webhook transcriptions are posted to a channel with.
+pub async fn set_output_webhook<'e, E>(
+    db: E,
+    channel_id: ChannelId,
+    webhook: &OutputWebhook,
+) -> DbResult<()>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    query!(
+        "INSERT INTO channels (channel_id, webhook_token, webhook_id)
+        VALUES($1, $2, $3) ON CONFLICT (channel_id) DO UPDATE SET webhook_token = $2, webhook_id = $3;",
+        channel_id.0 as i64,
+        webhook.token,
+        webhook.id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Forgets a channel's webhook. Returns `false` if it didn't have one.
+pub async fn delete_output_channel(db: &PgPool, channel_id: ChannelId) -> DbResult<bool> {
+    let r = query!(
+        "DELETE FROM channels WHERE channel_id = $1",
+        channel_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Gets the premium level of the guild a channel is in, if audio posted in the channel gets
+/// transcribed.
+pub async fn fetch_attachment_channel_premium(
+    db: &PgPool,
+    channel_id: ChannelId,
+) -> DbResult<Option<u8>> {
+    match query!(
+        "SELECT guilds.premium_level FROM attachment_channels
+        INNER JOIN guilds ON guilds.guild_id = attachment_channels.guild_id
+        WHERE attachment_channels.channel_id = $1",
+        channel_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await?
+    {
+        Some(r) => premium_from_db("premium_level", r.premium_level).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Turns transcribing audio posted in a channel on or off. Returns `false` if it was being
+/// turned on in a guild that was never set up.
+pub async fn set_attachment_channel(
+    db: &PgPool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    enabled: bool,
+) -> DbResult<bool> {
+    if enabled {
+        let r = query!(
+            "INSERT INTO attachment_channels (channel_id, guild_id)
+            SELECT $1, guild_id FROM guilds WHERE guild_id = $2
+            ON CONFLICT (channel_id) DO UPDATE SET guild_id = EXCLUDED.guild_id",
+            channel_id.0 as i64,
+            guild_id.0 as i64
+        )
+        .execute(db)
+        .await?;
+        Ok(r.rows_affected() != 0)
+    } else {
+        query!(
+            "DELETE FROM attachment_channels WHERE channel_id = $1",
+            channel_id.0 as i64
+        )
+        .execute(db)
+        .await?;
+        Ok(true)
+    }
+}
@@ -1,10 +1,11 @@
-use crate::PG_POOL;
+use crate::{migrate, PG_POOL};
 use scripty_config::{BotConfig, DatabaseConnection};
 use sqlx::postgres::PgConnectOptions;
-use sqlx::{query, PgPool, Pool, Postgres};
+use sqlx::{PgPool, Pool, Postgres};
 use std::path::PathBuf;
 
-pub async fn set_db() -> Pool<Postgres> {
+/// Connects to the database in the config, without touching its schema.
+pub async fn connect_db() -> Pool<Postgres> {
     let mut db_conn_options = PgConnectOptions::new();
 
     let config = BotConfig::get().expect("Couldn't get BOT_CONFIG to get the database file");
@@ -14,7 +15,7 @@ pub async fn set_db() -> Pool<Postgres> {
         DatabaseConnection::UnixSocket(path) => db_conn_options.socket(PathBuf::from(path)),
     };
 
-    let db = PgPool::connect_with(
+    PgPool::connect_with(
         db_conn_options
             .username(db_user)
             .database(db_db)
@@ -23,61 +24,19 @@ pub async fn set_db() -> Pool<Postgres> {
             .statement_cache_capacity(1000_usize),
     )
     .await
-    .expect("Couldn't connect to DB");
-
-    query!(
-        "CREATE TABLE IF NOT EXISTS prefixes (
-        guild_id BIGINT PRIMARY KEY,
-        prefix TEXT
-    )",
-    )
-    .execute(&db)
-    .await
-    .expect("Couldn't create the prefix table.");
-
-    query!(
-        "CREATE TABLE IF NOT EXISTS guilds (
-        guild_id BIGINT PRIMARY KEY,
-        default_bind BIGINT,
-        output_channel BIGINT,
-        premium_level SMALLINT NOT NULL
-    )",
-    )
-    .execute(&db)
-    .await
-    .expect("Couldn't create the guild table.");
-
-    query!(
-        "CREATE TABLE IF NOT EXISTS users (
-        user_id BIGINT PRIMARY KEY,
-        premium_level SMALLINT,
-        premium_count SMALLINT
-    )",
-    )
-    .execute(&db)
-    .await
-    .expect("Couldn't create the users table.");
+    .expect("Couldn't connect to DB")
+}
 
-    query!(
-        "CREATE TABLE IF NOT EXISTS channels (
-        channel_id BIGINT PRIMARY KEY,
-        webhook_token TEXT,
-        webhook_id BIGINT
-    )",
-    )
-    .execute(&db)
-    .await
-    .expect("Couldn't create the channel table.");
+/// Connects to the database, brings it up to date with `migrations/`, and puts it in `PG_POOL`.
+///
+/// Panics if the database has migrations this build doesn't know about, since that means it
+/// was migrated by a newer version of the bot.
+pub async fn set_db() -> Pool<Postgres> {
+    let db = connect_db().await;
 
-    query!(
-        "CREATE TABLE IF NOT EXISTS api_keys (
-           api_key TEXT NOT NULL,
-           user_id BIGINT
-         )"
-    )
-    .execute(&db)
-    .await
-    .expect("Couldn't create the API keys table");
+    if let Err(e) = migrate(&db).await {
+        panic!("Couldn't migrate the database: {}", e);
+    }
 
     PG_POOL
         .set(db.clone())
@@ -0,0 +1,41 @@
+use std::{convert::TryFrom, fmt};
+
+/// Everything that can go wrong reading from or writing to the database.
+#[derive(Debug)]
+pub enum DbError {
+    /// The query failed, or the database couldn't be reached.
+    Query(sqlx::Error),
+    /// A row held something its type can't, like a negative premium level.
+    Invalid(String),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Query(e) => write!(f, "DB returned a error: {}", e),
+            Self::Invalid(e) => write!(f, "DB has a invalid value: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Query(e) => Some(e),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Query(e)
+    }
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+/// Reads a premium level or count, which are stored as `SMALLINT`s.
+pub(crate) fn premium_from_db(column: &str, value: i16) -> DbResult<u8> {
+    u8::try_from(value).map_err(|_| DbError::Invalid(format!("{} is {}", column, value)))
+}
@@ -0,0 +1,222 @@
+use crate::{premium_from_db, set_binding, set_output_webhook, Binding, DbResult, OutputWebhook};
+use serenity::model::id::{ChannelId, GuildId};
+use sqlx::{query, PgPool};
+
+/// A guild's settings, as stored in the `guilds` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuildConfig {
+    pub guild_id: GuildId,
+    /// The voice chat picked in `setup`.
+    pub default_bind: Option<ChannelId>,
+    /// The channel picked in `setup`.
+    pub output_channel: Option<ChannelId>,
+    pub premium_level: u8,
+    /// Whether every voice session gets its own thread.
+    pub session_threads: bool,
+    /// The name of the format transcripts are uploaded in at the end of sessions, if they are.
+    pub transcript_format: Option<String>,
+    /// The hash of the guild's live feed token, if it has one.
+    pub live_token_hash: Option<String>,
+}
+
+/// Changes to a guild's settings. Fields left as `None` aren't changed.
+#[derive(Default)]
+pub struct GuildConfigUpdate {
+    pub default_bind: Option<Option<ChannelId>>,
+    pub output_channel: Option<Option<ChannelId>>,
+    pub premium_level: Option<u8>,
+    pub session_threads: Option<bool>,
+    pub transcript_format: Option<Option<String>>,
+}
+
+/// Gets a guild's settings, or `None` if it was never set up.
+pub async fn fetch_guild_config(db: &PgPool, guild_id: GuildId) -> DbResult<Option<GuildConfig>> {
+    let r = match query!(
+        "SELECT guild_id, default_bind, output_channel, premium_level, session_threads,
+          transcript_format, live_token_hash
+        FROM guilds WHERE guild_id = $1",
+        guild_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    Ok(Some(GuildConfig {
+        guild_id: GuildId(r.guild_id as u64),
+        default_bind: r.default_bind.map(|c| ChannelId(c as u64)),
+        output_channel: r.output_channel.map(|c| ChannelId(c as u64)),
+        premium_level: premium_from_db("premium_level", r.premium_level)?,
+        session_threads: r.session_threads,
+        transcript_format: r.transcript_format,
+        live_token_hash: r.live_token_hash,
+    }))
+}
+
+/// Gets a guild's premium level, or 0 if it was never set up.
+pub async fn fetch_guild_premium(db: &PgPool, guild_id: GuildId) -> DbResult<u8> {
+    match query!(
+        "SELECT premium_level FROM guilds WHERE guild_id = $1",
+        guild_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await?
+    {
+        Some(r) => premium_from_db("premium_level", r.premium_level),
+        None => Ok(0),
+    }
+}
+
+/// Adds a guild with no premium, unless it's already there.
+pub async fn ensure_guild(db: &PgPool, guild_id: GuildId) -> DbResult<()> {
+    query!(
+        "INSERT INTO guilds (guild_id, premium_level) VALUES ($1, 0)
+        ON CONFLICT (guild_id) DO NOTHING",
+        guild_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Sets a guild up the way the `setup` command does: `voice_channel` becomes its default bind,
+/// transcribed to `output_channel` with `webhook`, replacing whatever default bind it had. A
+/// guild that's set up again keeps its premium level.
+pub async fn setup_guild(
+    db: &PgPool,
+    guild_id: GuildId,
+    voice_channel: ChannelId,
+    output_channel: ChannelId,
+    webhook: &OutputWebhook,
+) -> DbResult<()> {
+    let mut tx = db.begin().await?;
+
+    query!(
+        "DELETE FROM bindings WHERE voice_channel =
+          (SELECT default_bind FROM guilds WHERE guild_id = $1)",
+        guild_id.0 as i64
+    )
+    .execute(&mut tx)
+    .await?;
+
+    query!(
+        "INSERT INTO guilds
+          (guild_id, default_bind, output_channel, premium_level)
+        VALUES ($1, $2, $3, $4)
+          ON CONFLICT (guild_id) DO UPDATE
+            SET default_bind = $2, output_channel = $3;",
+        guild_id.0 as i64,
+        voice_channel.0 as i64,
+        output_channel.0 as i64,
+        0 as i16
+    )
+    .execute(&mut tx)
+    .await?;
+
+    set_output_webhook(&mut tx, output_channel, webhook).await?;
+    set_binding(
+        &mut tx,
+        &Binding {
+            voice_channel,
+            guild_id,
+            output_channel,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Sets a guild's premium level. Returns `false` if the guild was never set up.
+pub async fn set_guild_premium(
+    db: &PgPool,
+    guild_id: GuildId,
+    premium_level: u8,
+) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE guilds SET premium_level = $1 WHERE guild_id = $2",
+        i16::from(premium_level),
+        guild_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Turns threads for voice sessions on or off. Returns `false` if the guild was never set up.
+pub async fn set_session_threads(db: &PgPool, guild_id: GuildId, enabled: bool) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE guilds SET session_threads = $1 WHERE guild_id = $2",
+        enabled,
+        guild_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Sets the name of the format transcripts are uploaded in, or stops uploading them with
+/// `None`. Returns `false` if the guild was never set up.
+pub async fn set_transcript_format(
+    db: &PgPool,
+    guild_id: GuildId,
+    format: Option<&str>,
+) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE guilds SET transcript_format = $1 WHERE guild_id = $2",
+        format,
+        guild_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Sets the hash of a guild's live feed token, or turns the live feed off with `None`. Returns
+/// `false` if the guild was never set up.
+pub async fn set_live_token_hash(
+    db: &PgPool,
+    guild_id: GuildId,
+    hash: Option<&str>,
+) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE guilds SET live_token_hash = $1 WHERE guild_id = $2",
+        hash,
+        guild_id.0 as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Applies `update` to a guild's settings. Returns `false` if the guild was never set up.
+pub async fn update_guild_config(
+    db: &PgPool,
+    guild_id: GuildId,
+    update: GuildConfigUpdate,
+) -> DbResult<bool> {
+    let r = query!(
+        "UPDATE guilds SET
+          default_bind = CASE WHEN $2 THEN $3 ELSE default_bind END,
+          output_channel = CASE WHEN $4 THEN $5 ELSE output_channel END,
+          premium_level = COALESCE($6, premium_level),
+          session_threads = COALESCE($7, session_threads),
+          transcript_format = CASE WHEN $8 THEN $9 ELSE transcript_format END
+        WHERE guild_id = $1",
+        guild_id.0 as i64,
+        update.default_bind.is_some(),
+        update.default_bind.flatten().map(|c| c.0 as i64),
+        update.output_channel.is_some(),
+        update.output_channel.flatten().map(|c| c.0 as i64),
+        update.premium_level.map(i16::from),
+        update.session_threads,
+        update.transcript_format.is_some(),
+        update.transcript_format.flatten()
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
@@ -0,0 +1,262 @@
+use crate::{DbError, DbResult};
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::Value;
+use sqlx::{query, PgPool};
+
+/// Every job ID starts with this.
+pub const JOB_ID_PREFIX: &str = "job_";
+
+/// The most jobs one user can have queued or running at once.
+pub const MAX_PENDING_JOBS: i64 = 5;
+
+fn random_chars(count: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(count)
+        .map(char::from)
+        .collect()
+}
+
+/// Generates a new job ID. These are random so they can't be guessed, even though only the
+/// job's owner can look at it.
+pub fn generate_job_id() -> String {
+    format!("{}{}", JOB_ID_PREFIX, random_chars(24))
+}
+
+/// Generates the secret a job's callbacks are signed with.
+pub fn generate_callback_secret() -> String {
+    random_chars(40)
+}
+
+/// Where a transcription job is at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting for a worker.
+    Queued,
+    Running,
+    /// Finished, with a result.
+    Done,
+    /// Finished, with an error.
+    Failed,
+}
+
+impl JobStatus {
+    /// The name this status is stored under in the DB.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "queued" => Some(Self::Queued),
+            "running" => Some(Self::Running),
+            "done" => Some(Self::Done),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+
+    /// Whether a job with this status won't change anymore.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Done | Self::Failed)
+    }
+}
+
+/// A transcription job, as stored in the `transcription_jobs` table, without its audio.
+pub struct JobRecord {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub duration_ms: u64,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// What the job produced, once it's done.
+    pub result: Option<Value>,
+    /// What went wrong, if it failed.
+    pub error: Option<String>,
+}
+
+/// A job to be queued by `insert_job`.
+pub struct NewJobRecord<'a> {
+    pub job_id: &'a str,
+    /// The API key the job was queued with.
+    pub key_id: i64,
+    pub user_id: u64,
+    /// Mono 16 bit PCM at `sample_rate`.
+    pub audio: &'a [i16],
+    pub sample_rate: u32,
+    pub duration_ms: u64,
+    pub callback_url: Option<&'a str>,
+    pub callback_secret: Option<&'a str>,
+}
+
+/// A job a worker claimed with `claim_job`, with everything it needs to run it.
+pub struct ClaimedJob {
+    pub job_id: String,
+    pub key_id: i64,
+    pub user_id: u64,
+    /// Mono 16 bit PCM at `sample_rate`.
+    pub audio: Vec<i16>,
+    pub sample_rate: u32,
+    pub duration_ms: u64,
+    pub callback_url: Option<String>,
+    pub callback_secret: Option<String>,
+}
+
+/// Looks up `job_id`, if it belongs to `user_id`.
+pub async fn fetch_job(db: &PgPool, job_id: &str, user_id: u64) -> DbResult<Option<JobRecord>> {
+    let r = match query!(
+        "SELECT job_id, status, duration_ms, created_at, started_at, finished_at, result, error
+        FROM transcription_jobs WHERE job_id = $1 AND user_id = $2",
+        job_id,
+        user_id as i64
+    )
+    .fetch_optional(db)
+    .await?
+    {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    let status = JobStatus::from_name(&r.status)
+        .ok_or_else(|| DbError::Invalid(format!("job {} has status {}", r.job_id, r.status)))?;
+    Ok(Some(JobRecord {
+        job_id: r.job_id,
+        status,
+        duration_ms: r.duration_ms as u64,
+        created_at: r.created_at,
+        started_at: r.started_at,
+        finished_at: r.finished_at,
+        result: r.result,
+        error: r.error,
+    }))
+}
+
+/// How many of `user_id`'s jobs are queued or running.
+pub async fn count_pending_jobs(db: &PgPool, user_id: u64) -> DbResult<i64> {
+    query!(
+        r#"SELECT count(*) AS "count!" FROM transcription_jobs
+        WHERE user_id = $1 AND status IN ('queued', 'running')"#,
+        user_id as i64
+    )
+    .fetch_one(db)
+    .await
+    .map(|r| r.count)
+    .map_err(Into::into)
+}
+
+/// Queues up `job`.
+pub async fn insert_job(db: &PgPool, job: &NewJobRecord<'_>) -> DbResult<()> {
+    let audio: Vec<u8> = job.audio.iter().flat_map(|s| s.to_le_bytes()).collect();
+    query!(
+        "INSERT INTO transcription_jobs
+          (job_id, key_id, user_id, audio, sample_rate, duration_ms, callback_url, callback_secret)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        job.job_id,
+        job.key_id,
+        job.user_id as i64,
+        audio,
+        job.sample_rate as i32,
+        job.duration_ms as i64,
+        job.callback_url,
+        job.callback_secret
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Deletes `job_id` if it belongs to `user_id`. Returns `false` if it doesn't, or doesn't exist.
+pub async fn delete_job(db: &PgPool, job_id: &str, user_id: u64) -> DbResult<bool> {
+    let r = query!(
+        "DELETE FROM transcription_jobs WHERE job_id = $1 AND user_id = $2",
+        job_id,
+        user_id as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() != 0)
+}
+
+/// Puts every running job back in the queue. Only call this before any workers have started,
+/// when the running ones must have been cut off by a restart.
+pub async fn requeue_running_jobs(db: &PgPool) -> DbResult<()> {
+    query!(
+        "UPDATE transcription_jobs SET status = 'queued', started_at = NULL
+        WHERE status = 'running'"
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Deletes every job that finished more than `retention_hours` ago.
+pub async fn delete_expired_jobs(db: &PgPool, retention_hours: u32) -> DbResult<()> {
+    query!(
+        "DELETE FROM transcription_jobs
+        WHERE finished_at < now() - make_interval(hours => $1)",
+        retention_hours as i32
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Marks the oldest queued job as running and returns it, if there is one.
+pub async fn claim_job(db: &PgPool) -> DbResult<Option<ClaimedJob>> {
+    let r = query!(
+        "UPDATE transcription_jobs SET status = 'running', started_at = now()
+        WHERE job_id = (
+          SELECT job_id FROM transcription_jobs WHERE status = 'queued'
+          ORDER BY created_at LIMIT 1 FOR UPDATE SKIP LOCKED
+        )
+        RETURNING job_id, key_id, user_id, audio, sample_rate, duration_ms, callback_url,
+          callback_secret"
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(r.map(|r| ClaimedJob {
+        job_id: r.job_id,
+        key_id: r.key_id,
+        user_id: r.user_id as u64,
+        audio: r
+            .audio
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect(),
+        sample_rate: r.sample_rate as u32,
+        duration_ms: r.duration_ms as u64,
+        callback_url: r.callback_url,
+        callback_secret: r.callback_secret,
+    }))
+}
+
+/// Saves how `job_id` ended, dropping its audio.
+pub async fn finish_job(
+    db: &PgPool,
+    job_id: &str,
+    status: JobStatus,
+    result: Option<Value>,
+    error: Option<&str>,
+) -> DbResult<()> {
+    query!(
+        "UPDATE transcription_jobs
+        SET status = $2, result = $3, error = $4, audio = NULL, finished_at = now()
+        WHERE job_id = $1",
+        job_id,
+        status.name(),
+        result,
+        error
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
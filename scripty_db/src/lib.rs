@@ -1,7 +1,33 @@
 #![feature(once_cell)]
 
+mod api_keys;
+mod bindings;
+mod channels;
 mod connect;
+mod error;
+mod guilds;
+mod jobs;
+mod migrate;
+mod prefixes;
+mod premium;
+mod sessions;
+mod sponsors;
+mod usage;
+mod users;
+pub use api_keys::*;
+pub use bindings::*;
+pub use channels::*;
 pub use connect::*;
+pub use error::*;
+pub use guilds::*;
+pub use jobs::*;
+pub use migrate::*;
+pub use prefixes::*;
+pub use premium::*;
+pub use sessions::*;
+pub use sponsors::*;
+pub use usage::*;
+pub use users::*;
 
 use serenity::prelude::TypeMapKey;
 use sqlx::{Pool, Postgres};
@@ -10,7 +36,7 @@ use std::lazy::SyncOnceCell as OnceCell;
 /// This OnceCell contains a PostgreSQL pool that you can use anywhere in the bot.
 /// If it isn't populated yet, `self::set_db` has not been called yet. You're too early.
 /// You should not populate this manually, and rather rely on `self::set_db` to do so for you,
-/// as it handles also creating the tables if need be.
+/// as it also migrates the database if need be.
 pub static PG_POOL: OnceCell<Pool<Postgres>> = OnceCell::new();
 
 /// A wrapper around a `Pool<Postgres>`, designed to be inserted into the serenity client's
@@ -0,0 +1,134 @@
+//! Versioned schema migrations, from the `migrations/` directory.
+//!
+//! Applied migrations are tracked in sqlx's `_sqlx_migrations` table. Every migration can be run
+//! on a database that already has what it adds, so databases from before that table existed
+//! just have all of them run once.
+use sqlx::{migrate::Migrator, query, PgPool, Row};
+use std::fmt;
+
+/// Every migration in `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// Where a migration stands in a database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but the file has changed since.
+    Modified,
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Applied => "applied",
+            Self::Pending => "pending",
+            Self::Modified => "modified since it was applied",
+        })
+    }
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// Where a database's schema stands compared to `MIGRATOR`.
+pub struct SchemaStatus {
+    /// Whether the database has a `_sqlx_migrations` table yet.
+    pub tracked: bool,
+    /// Every migration this build knows about, oldest first.
+    pub migrations: Vec<MigrationStatus>,
+    /// Versions applied to the database that this build doesn't know about.
+    pub unknown: Vec<i64>,
+}
+
+async fn table_exists(db: &PgPool, table: &str) -> Result<bool, sqlx::Error> {
+    query("SELECT to_regclass($1) IS NOT NULL")
+        .bind(table)
+        .fetch_one(db)
+        .await
+        .map(|r| r.get(0))
+}
+
+/// Compares the database's applied migrations with the ones this build knows about.
+pub async fn schema_status(db: &PgPool) -> Result<SchemaStatus, sqlx::Error> {
+    // `_sqlx_migrations` belongs to sqlx, so it isn't in the offline query data
+    let tracked = table_exists(db, "_sqlx_migrations").await?;
+    let applied: Vec<(i64, Vec<u8>)> = if tracked {
+        query("SELECT version, checksum FROM _sqlx_migrations WHERE success ORDER BY version")
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|r| (r.get(0), r.get(1)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let migrations = MIGRATOR
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            state: match applied.iter().find(|(v, _)| *v == m.version) {
+                None => MigrationState::Pending,
+                Some((_, checksum)) if checksum[..] == m.checksum[..] => MigrationState::Applied,
+                Some(_) => MigrationState::Modified,
+            },
+        })
+        .collect();
+    let unknown = applied
+        .iter()
+        .map(|(v, _)| *v)
+        .filter(|v| !MIGRATOR.iter().any(|m| m.version == *v))
+        .collect();
+
+    Ok(SchemaStatus {
+        tracked,
+        migrations,
+        unknown,
+    })
+}
+
+/// Applies every pending migration.
+///
+/// Fails without changing anything if the database has migrations this build doesn't know
+/// about, or if one that was applied has been changed since.
+pub async fn migrate(db: &PgPool) -> Result<(), String> {
+    let status = schema_status(db).await.map_err(|e| e.to_string())?;
+
+    if !status.unknown.is_empty() {
+        return Err(format!(
+            "the database has migrations this version doesn't know about ({}), so it was \
+            migrated by a newer version. Refusing to start on a downgrade",
+            status
+                .unknown
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if let Some(m) = status
+        .migrations
+        .iter()
+        .find(|m| m.state == MigrationState::Modified)
+    {
+        return Err(format!(
+            "migration {} ({}) was changed after it was applied",
+            m.version, m.description
+        ));
+    }
+
+    let pending = status
+        .migrations
+        .iter()
+        .filter(|m| m.state == MigrationState::Pending)
+        .count();
+    if pending != 0 {
+        tracing::info!("Applying {} migrations", pending);
+    }
+    MIGRATOR.run(db).await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,40 @@
+use crate::DbResult;
+use serenity::model::id::GuildId;
+use sqlx::{query, PgPool};
+
+/// Gets the prefix `guild_id` picked, or `None` if it never picked one.
+pub async fn fetch_prefix(db: &PgPool, guild_id: GuildId) -> DbResult<Option<String>> {
+    query!(
+        "SELECT
+           prefix
+         FROM
+           prefixes
+         WHERE
+           guild_id = $1",
+        guild_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await
+    .map(|r| r.and_then(|r| r.prefix))
+    .map_err(Into::into)
+}
+
+/// Sets the prefix used in `guild_id`. An empty prefix means none is needed.
+pub async fn set_prefix(db: &PgPool, guild_id: GuildId, prefix: &str) -> DbResult<()> {
+    query!(
+        "INSERT INTO prefixes
+                 (guild_id, prefix)
+             VALUES
+                 ($1, $2)
+             ON CONFLICT
+                 (guild_id)
+             DO UPDATE SET
+                 prefix = $2;",
+        guild_id.0 as i64,
+        prefix,
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+    .map_err(Into::into)
+}
@@ -0,0 +1,95 @@
+/// Get the maximum number of voice channels a guild can bind at once.
+///
+/// `premium_level` is the guild's premium level, as stored in the `guilds` table.
+pub fn max_bindings(premium_level: u8) -> usize {
+    match premium_level {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        3 => 8,
+        4 => 16,
+        _ => usize::MAX,
+    }
+}
+
+/// Get the longest audio, in seconds, a user can send to the speech to text API in one request.
+///
+/// `premium_level` is the user's premium level, as stored in the `users` table. Users without
+/// premium can't use the API at all.
+pub fn max_api_audio_secs(premium_level: u8) -> u64 {
+    match premium_level {
+        0 => 0,
+        1 => 60,
+        2 => 300,
+        3 => 900,
+        4 => 1800,
+        _ => 3600,
+    }
+}
+
+/// Get the longest audio, in seconds, a user can send to the speech to text API as one job.
+///
+/// `premium_level` is the user's premium level, as stored in the `users` table. Jobs are for
+/// recordings too long for `max_api_audio_secs`, so these are a lot longer.
+pub fn max_job_audio_secs(premium_level: u8) -> u64 {
+    match premium_level {
+        0 => 0,
+        1 => 3600,
+        2 => 7200,
+        3 => 10800,
+        4 => 14400,
+        _ => 21600,
+    }
+}
+
+/// Get the longest audio file, in seconds, that gets transcribed when it's posted in a server.
+///
+/// `premium_level` is the guild's premium level, as stored in the `guilds` table.
+pub fn max_attachment_audio_secs(premium_level: u8) -> u64 {
+    match premium_level {
+        0 => 60,
+        1 => 180,
+        2 => 300,
+        3 => 600,
+        4 => 900,
+        _ => 1800,
+    }
+}
+
+/// Get the premium level and premium count a GitHub sponsor gets for sponsoring
+/// `monthly_dollars` a month. Anything under the cheapest tier gets nothing.
+pub fn sponsor_premium(monthly_dollars: u32) -> (u8, u8) {
+    match monthly_dollars {
+        0..=4 => (0, 0),
+        5..=9 => (1, 1),
+        10..=24 => (2, 2),
+        25..=49 => (3, 3),
+        50..=99 => (4, 5),
+        _ => (5, 10),
+    }
+}
+
+/// The token bucket each of a user's API keys is rate limited with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApiRateLimit {
+    /// How many requests can be made at once, when the bucket is full.
+    pub burst: u32,
+    /// How many requests are added back to the bucket every minute.
+    pub per_minute: u32,
+}
+
+/// Get the rate limit of a user's API keys.
+///
+/// `premium_level` is the user's premium level, as stored in the `users` table. Users who lost
+/// premium can still read stats with their old keys, just slowly.
+pub fn api_rate_limit(premium_level: u8) -> ApiRateLimit {
+    let (burst, per_minute) = match premium_level {
+        0 => (5, 1),
+        1 => (10, 5),
+        2 => (20, 10),
+        3 => (40, 20),
+        4 => (80, 40),
+        _ => (160, 80),
+    };
+    ApiRateLimit { burst, per_minute }
+}
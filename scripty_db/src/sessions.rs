@@ -0,0 +1,49 @@
+use crate::DbResult;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use sqlx::{query, PgPool};
+
+/// Records a voice session starting in `voice_channel`, and returns its ID.
+pub async fn start_session(
+    db: &PgPool,
+    guild_id: GuildId,
+    voice_channel: ChannelId,
+    output_channel: ChannelId,
+    thread_id: Option<ChannelId>,
+) -> DbResult<i64> {
+    query!(
+        "INSERT INTO sessions (guild_id, voice_channel, output_channel, thread_id)
+        VALUES ($1, $2, $3, $4) RETURNING session_id",
+        guild_id.0 as i64,
+        voice_channel.0 as i64,
+        output_channel.0 as i64,
+        thread_id.map(|c| c.0 as i64)
+    )
+    .fetch_one(db)
+    .await
+    .map(|r| r.session_id)
+    .map_err(Into::into)
+}
+
+/// Marks a session as ended, along with who took part in it and how much was transcribed.
+pub async fn finish_session(
+    db: &PgPool,
+    session_id: i64,
+    participants: impl Iterator<Item = UserId>,
+    utterances: usize,
+    ms_transcribed: u64,
+) -> DbResult<()> {
+    let participants: Vec<i64> = participants.map(|u| u.0 as i64).collect();
+
+    query!(
+        "UPDATE sessions SET ended_at = now(), participants = $2, utterances = $3,
+        ms_transcribed = $4 WHERE session_id = $1",
+        session_id,
+        &participants[..],
+        utterances as i32,
+        ms_transcribed as i64
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+    .map_err(Into::into)
+}
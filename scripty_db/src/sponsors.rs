@@ -0,0 +1,123 @@
+use crate::{set_user_premium, sponsor_premium, DbResult};
+use serenity::model::id::UserId;
+use sqlx::{query, Executor, Pool, Postgres};
+
+/// A GitHub sponsor, as stored in the `sponsors` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sponsor {
+    /// Always lowercase.
+    pub github_login: String,
+    /// The Discord user who linked this login with `link_github`, if anyone has.
+    pub user_id: Option<u64>,
+    pub monthly_dollars: u32,
+    pub active: bool,
+}
+
+impl Sponsor {
+    /// The premium level and premium count this sponsor should have right now.
+    pub fn premium(&self) -> (u8, u8) {
+        if self.active {
+            sponsor_premium(self.monthly_dollars)
+        } else {
+            (0, 0)
+        }
+    }
+}
+
+/// Marks the webhook delivery `delivery_id` as seen, returning whether it wasn't already.
+pub async fn record_delivery<'e, E>(db: E, delivery_id: &str) -> DbResult<bool>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let r = query!(
+        "INSERT INTO sponsor_deliveries (delivery_id) VALUES ($1)
+        ON CONFLICT (delivery_id) DO NOTHING",
+        delivery_id
+    )
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() == 1)
+}
+
+/// Records what GitHub said a sponsor is now paying, returning the sponsor after.
+///
+/// This overwrites rather than adds to what was there, so recording the same change twice is
+/// harmless.
+pub async fn record_sponsorship<'e, E>(
+    db: E,
+    github_login: &str,
+    monthly_dollars: u32,
+    active: bool,
+) -> DbResult<Sponsor>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    query!(
+        "INSERT INTO sponsors (github_login, monthly_dollars, active) VALUES (lower($1), $2, $3)
+        ON CONFLICT (github_login) DO UPDATE
+          SET monthly_dollars = $2, active = $3, updated_at = now()
+        RETURNING github_login, user_id, monthly_dollars, active",
+        github_login,
+        monthly_dollars as i32,
+        active
+    )
+    .fetch_one(db)
+    .await
+    .map(|r| Sponsor {
+        github_login: r.github_login,
+        user_id: r.user_id.map(|u| u as u64),
+        monthly_dollars: r.monthly_dollars as u32,
+        active: r.active,
+    })
+    .map_err(Into::into)
+}
+
+/// Links a GitHub login to a Discord user, unlinking whatever login they had before, and gives
+/// them the premium the login's sponsorship comes with.
+///
+/// Returns `None` if someone else already linked the login.
+pub async fn link_sponsor(
+    db: &Pool<Postgres>,
+    github_login: &str,
+    user_id: u64,
+) -> DbResult<Option<Sponsor>> {
+    let mut tx = db.begin().await?;
+
+    query!(
+        "UPDATE sponsors SET user_id = NULL, updated_at = now()
+        WHERE user_id = $1 AND github_login <> lower($2)",
+        user_id as i64,
+        github_login
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let sponsor = match query!(
+        "INSERT INTO sponsors (github_login, user_id) VALUES (lower($1), $2)
+        ON CONFLICT (github_login) DO UPDATE SET user_id = $2, updated_at = now()
+          WHERE sponsors.user_id IS NULL OR sponsors.user_id = $2
+        RETURNING github_login, user_id, monthly_dollars, active",
+        github_login,
+        user_id as i64
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    {
+        Some(r) => Sponsor {
+            github_login: r.github_login,
+            user_id: r.user_id.map(|u| u as u64),
+            monthly_dollars: r.monthly_dollars as u32,
+            active: r.active,
+        },
+        None => return Ok(None),
+    };
+
+    // linking a login that isn't sponsoring yet shouldn't take away premium from elsewhere
+    if sponsor.active {
+        let (level, count) = sponsor.premium();
+        set_user_premium(&mut tx, UserId(user_id), level, count).await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(sponsor))
+}
@@ -0,0 +1,143 @@
+use crate::DbResult;
+use serenity::model::id::{GuildId, UserId};
+use sqlx::{query, Pool, Postgres};
+
+/// Milliseconds of audio a guild can have transcribed each month, or `None` if there's no limit.
+///
+/// `premium_level` is the guild's premium level, as stored in the `guilds` table.
+pub fn monthly_quota_ms(premium_level: u8) -> Option<u64> {
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    match premium_level {
+        0 => Some(10 * HOUR),
+        1 => Some(50 * HOUR),
+        2 => Some(150 * HOUR),
+        3 => Some(500 * HOUR),
+        4 => Some(1500 * HOUR),
+        _ => None,
+    }
+}
+
+/// Notices sent to a guild as it uses up its monthly quota. Each one is sent at most once a month.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsageNotice {
+    /// 80% of the quota has been used.
+    Warning,
+    /// The whole quota has been used, and transcription is paused until next month.
+    Paused,
+}
+
+/// How many milliseconds of audio `guild_id` has had transcribed this month.
+pub async fn monthly_usage(db: &Pool<Postgres>, guild_id: GuildId) -> DbResult<u64> {
+    query!(
+        "SELECT ms_transcribed FROM guild_usage
+        WHERE guild_id = $1 AND month = date_trunc('month', now())::date",
+        guild_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await
+    .map(|r| r.map_or(0, |r| r.ms_transcribed as u64))
+    .map_err(Into::into)
+}
+
+/// Adds `ms` milliseconds to what `guild_id` has had transcribed this month, and returns the new
+/// total.
+pub async fn add_usage(db: &Pool<Postgres>, guild_id: GuildId, ms: u64) -> DbResult<u64> {
+    query!(
+        "INSERT INTO guild_usage (guild_id, month, ms_transcribed)
+        VALUES ($1, date_trunc('month', now())::date, $2)
+        ON CONFLICT (guild_id, month) DO UPDATE
+          SET ms_transcribed = guild_usage.ms_transcribed + $2
+        RETURNING ms_transcribed",
+        guild_id.0 as i64,
+        ms as i64
+    )
+    .fetch_one(db)
+    .await
+    .map(|r| r.ms_transcribed as u64)
+    .map_err(Into::into)
+}
+
+/// Marks `notice` as sent to `guild_id` this month. Returns `true` if it wasn't already, meaning
+/// the caller should send it.
+pub async fn claim_usage_notice(
+    db: &Pool<Postgres>,
+    guild_id: GuildId,
+    notice: UsageNotice,
+) -> DbResult<bool> {
+    let result = match notice {
+        UsageNotice::Warning => {
+            query!(
+                "UPDATE guild_usage SET warned = true
+                WHERE guild_id = $1 AND month = date_trunc('month', now())::date AND NOT warned",
+                guild_id.0 as i64
+            )
+            .execute(db)
+            .await?
+        }
+        UsageNotice::Paused => {
+            query!(
+                "UPDATE guild_usage SET paused = true
+                WHERE guild_id = $1 AND month = date_trunc('month', now())::date AND NOT paused",
+                guild_id.0 as i64
+            )
+            .execute(db)
+            .await?
+        }
+    };
+    Ok(result.rows_affected() != 0)
+}
+
+/// Adds `requests` requests and `audio_ms` milliseconds of transcribed audio to what the API key
+/// `key_id` has used this month.
+pub async fn add_api_key_usage(
+    db: &Pool<Postgres>,
+    key_id: i64,
+    requests: u64,
+    audio_ms: u64,
+) -> DbResult<()> {
+    query!(
+        "INSERT INTO api_key_usage (key_id, month, requests, audio_ms)
+        VALUES ($1, date_trunc('month', now())::date, $2, $3)
+        ON CONFLICT (key_id, month) DO UPDATE
+          SET requests = api_key_usage.requests + $2, audio_ms = api_key_usage.audio_ms + $3",
+        key_id,
+        requests as i64,
+        audio_ms as i64
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+    .map_err(Into::into)
+}
+
+/// How much one of a user's API keys has been used this month.
+pub struct ApiKeyUsage {
+    /// The start of the key, as shown to its owner.
+    pub prefix: String,
+    pub requests: u64,
+    pub audio_ms: u64,
+}
+
+/// How much each of a user's unrevoked API keys has been used this month, oldest key first.
+pub async fn api_key_usage(db: &Pool<Postgres>, user_id: UserId) -> DbResult<Vec<ApiKeyUsage>> {
+    Ok(query!(
+        r#"SELECT api_keys.key_prefix,
+          COALESCE(api_key_usage.requests, 0) AS "requests!",
+          COALESCE(api_key_usage.audio_ms, 0) AS "audio_ms!"
+        FROM api_keys LEFT JOIN api_key_usage ON api_key_usage.key_id = api_keys.key_id
+          AND api_key_usage.month = date_trunc('month', now())::date
+        WHERE api_keys.user_id = $1 AND api_keys.revoked_at IS NULL
+        ORDER BY api_keys.created_at"#,
+        user_id.0 as i64
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|r| ApiKeyUsage {
+        prefix: r.key_prefix,
+        requests: r.requests as u64,
+        audio_ms: r.audio_ms as u64,
+    })
+    .collect())
+}
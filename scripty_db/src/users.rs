@@ -0,0 +1,40 @@
+use crate::{premium_from_db, DbResult};
+use serenity::model::id::UserId;
+use sqlx::{query, Executor, PgPool, Postgres};
+
+/// Gets a user's premium level, or 0 if they don't have one.
+pub async fn fetch_user_premium(db: &PgPool, user_id: UserId) -> DbResult<u8> {
+    match query!(
+        "SELECT premium_level FROM users WHERE user_id = $1",
+        user_id.0 as i64
+    )
+    .fetch_optional(db)
+    .await?
+    .and_then(|r| r.premium_level)
+    {
+        Some(l) => premium_from_db("premium_level", l),
+        None => Ok(0),
+    }
+}
+
+/// Sets a user's premium level, and how many guilds they can give premium to.
+pub async fn set_user_premium<'e, E>(
+    db: E,
+    user_id: UserId,
+    premium_level: u8,
+    premium_count: u8,
+) -> DbResult<()>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    query!(
+        "INSERT INTO users (user_id, premium_level, premium_count) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE SET premium_level = $2, premium_count = $3",
+        user_id.0 as i64,
+        i16::from(premium_level),
+        i16::from(premium_count)
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::METRICS;
+use crate::{heartbeat, register_task, METRICS};
 use std::ops::Add;
 use std::time::Duration;
 use systemstat::{
@@ -7,12 +7,15 @@ use systemstat::{
 use tokio::time;
 
 const ONE_SECOND: Duration = Duration::from_secs(1);
+const UPDATE_INTERVAL: Duration = Duration::from_secs(5);
 
 pub fn spawn_updater_task() {
+    register_task("metrics_updater", UPDATE_INTERVAL);
     tokio::spawn(async move {
         loop {
             updater_task().await;
-            time::sleep(Duration::from_secs(5)).await;
+            heartbeat("metrics_updater", UPDATE_INTERVAL);
+            time::sleep(UPDATE_INTERVAL).await;
         }
     });
 }
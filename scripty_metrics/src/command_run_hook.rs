@@ -1,33 +1,91 @@
 use crate::METRICS;
-use serenity::framework::standard::macros::hook;
-use serenity::model::prelude::Message;
+use dashmap::DashMap;
+use serenity::framework::standard::{macros::hook, Command, CommandGroup, CommandResult};
+use serenity::model::prelude::{Message, MessageId};
 use serenity::prelude::Context;
-use tracing::warn;
+use std::{fmt, lazy::SyncLazy, time::Instant};
+
+/// When each running command started, by the ID of the message that ran it.
+static COMMAND_STARTS: SyncLazy<DashMap<MessageId, Instant>> = SyncLazy::new(DashMap::new);
+
+/// A command failed because of something the user did, like giving a channel that doesn't
+/// exist, rather than because of a bug or an outage.
+///
+/// Return this (boxed, `?` does that) from a command to have it counted as a `user_error`
+/// instead of an `internal_error`.
+#[derive(Debug)]
+pub struct UserError(pub String);
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UserError {}
+
+/// Adds every command in `groups`, their subcommands and their subgroups to the command metrics,
+/// so they show up at zero before anyone runs them.
+pub fn register_commands(groups: &[&'static CommandGroup]) {
+    fn register_command(command: &'static Command) {
+        let metrics = unsafe { METRICS.get().unwrap_unchecked() };
+        if let Some(name) = command.options.names.first() {
+            metrics.commands.with_label_values(&[name]);
+            metrics.command_duration.with_label_values(&[name]);
+            for result in ["success", "user_error", "internal_error"] {
+                metrics.command_results.with_label_values(&[name, result]);
+            }
+        }
+        for sub_command in command.options.sub_commands {
+            register_command(sub_command);
+        }
+    }
+
+    for group in groups {
+        for command in group.options.commands {
+            register_command(command);
+        }
+        register_commands(group.options.sub_groups);
+    }
+}
 
 #[hook]
-pub async fn before_hook(_: &Context, _: &Message, cmd_name: &str) -> bool {
+pub async fn before_hook(_: &Context, msg: &Message, cmd_name: &str) -> bool {
     let metrics = match METRICS.get() {
         Some(m) => m,
         None => return true,
     };
 
-    match cmd_name {
-        "info" => metrics.commands.info.inc(),
-        "prefix" => metrics.commands.prefix.inc(),
-        "donate" => metrics.commands.donate.inc(),
-        "ping" => metrics.commands.ping.inc(),
-        "status" => metrics.commands.status.inc(),
-        "credits" => metrics.commands.credits.inc(),
-        "stats" => metrics.commands.stats.inc(),
-        "join" => metrics.commands.join.inc(),
-        "setup" => metrics.commands.setup.inc(),
-        "rejoin_all" => metrics.commands.rejoin_all.inc(),
-        "shutdown" => metrics.commands.shutdown.inc(),
-        "add_premium" => metrics.commands.add_premium.inc(),
-        "eval" => metrics.commands.eval.inc(),
-        x => warn!("unknown command found: {}", x),
-    };
+    COMMAND_STARTS.insert(msg.id, Instant::now());
+    metrics.commands.with_label_values(&[cmd_name]).inc();
     metrics.total_commands.inc();
 
     true
 }
+
+#[hook]
+pub async fn after_hook(_: &Context, msg: &Message, cmd_name: &str, result: CommandResult) {
+    let metrics = match METRICS.get() {
+        Some(m) => m,
+        None => return,
+    };
+
+    if let Some((_, start)) = COMMAND_STARTS.remove(&msg.id) {
+        metrics
+            .command_duration
+            .with_label_values(&[cmd_name])
+            .observe(start.elapsed().as_secs_f64());
+    }
+    let outcome = match result {
+        Ok(_) => "success",
+        Err(e) if e.is::<UserError>() => "user_error",
+        Err(e) => {
+            tracing::warn!("command {} failed: {}", cmd_name, e);
+            "internal_error"
+        }
+    };
+    metrics
+        .command_results
+        .with_label_values(&[cmd_name, outcome])
+        .inc();
+}
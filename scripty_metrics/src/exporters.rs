@@ -0,0 +1,481 @@
+//! Pushing metrics to monitoring stacks that don't scrape Prometheus' `/metrics` route.
+use crate::{heartbeat, register_task, METRICS};
+use prometheus::proto::{MetricFamily, MetricType};
+use scripty_config::MetricsExporterConfig;
+use serenity::async_trait;
+use std::{collections::HashMap, fmt::Write, sync::Mutex, time::Duration};
+use tokio::{net::UdpSocket, time};
+use tracing::{info, warn};
+
+/// Keeps StatsD datagrams under the smallest MTU they're likely to cross.
+const MAX_DATAGRAM_SIZE: usize = 1400;
+
+/// Something metrics can be pushed to.
+#[async_trait]
+pub trait Exporter: Send + Sync {
+    /// Pushes the current value of every metric in `families`.
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String>;
+}
+
+/// The value of one metric, with one set of labels.
+enum SampleValue {
+    Counter(f64),
+    Gauge(f64),
+    Histogram { count: u64, sum: f64 },
+}
+
+struct Sample {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: SampleValue,
+}
+
+/// Flattens `families` into one sample per metric and label set. Summaries and untyped metrics
+/// are skipped, scripty doesn't have any.
+fn samples(families: &[MetricFamily]) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for family in families {
+        for metric in family.get_metric() {
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => SampleValue::Counter(metric.get_counter().get_value()),
+                MetricType::GAUGE => SampleValue::Gauge(metric.get_gauge().get_value()),
+                MetricType::HISTOGRAM => SampleValue::Histogram {
+                    count: metric.get_histogram().get_sample_count(),
+                    sum: metric.get_histogram().get_sample_sum(),
+                },
+                _ => continue,
+            };
+            samples.push(Sample {
+                name: family.get_name().to_string(),
+                labels: metric
+                    .get_label()
+                    .iter()
+                    .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                    .collect(),
+                value,
+            });
+        }
+    }
+    samples
+}
+
+/// Pushes metrics to a StatsD server over UDP.
+///
+/// StatsD has no labels, so label values are added onto the name: `commands_used` with a
+/// `command_name` of `help` becomes `<prefix>.commands_used.help`. Prometheus counters only ever
+/// go up, so they're sent as the difference since the last push. Histograms are sent as a
+/// `.count` counter and a `.sum` gauge.
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+    /// Every counter's value as of the last push, or `None` before the first one.
+    last_values: Mutex<Option<HashMap<String, f64>>>,
+}
+
+impl StatsdExporter {
+    /// Creates a exporter sending to the StatsD server at `address`.
+    pub async fn new(address: &str, prefix: String) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(address).await?;
+        Ok(Self {
+            socket,
+            prefix,
+            last_values: Mutex::new(None),
+        })
+    }
+
+    fn name(&self, sample: &Sample) -> String {
+        let mut name = format!("{}.{}", self.prefix, sample.name);
+        for (_, value) in sample.labels.iter() {
+            name.push('.');
+            // `:` and `|` would end the name early
+            name.extend(value.chars().map(|c| match c {
+                ':' | '|' | '@' | ' ' => '_',
+                c => c,
+            }));
+        }
+        name
+    }
+
+    /// Turns `families` into StatsD lines.
+    ///
+    /// The first push only remembers where counters start: they're restored from `metrics.json`
+    /// at startup, so their whole value would be counted again after every restart. After that,
+    /// a counter that wasn't there last push counts up from 0.
+    pub fn lines(&self, families: &[MetricFamily]) -> Vec<String> {
+        let mut last_values = self
+            .last_values
+            .lock()
+            .expect("thread panicked while holding last values lock");
+        let baseline = last_values.is_none();
+        let last_values = last_values.get_or_insert_with(HashMap::new);
+        // how much `name` went up since the last push
+        let mut increase = |name: &str, value: f64| {
+            let last = last_values.insert(name.to_string(), value).unwrap_or(0.0);
+            if baseline {
+                0.0
+            } else if value < last {
+                // a counter that went down was reset, so all of it is new
+                value
+            } else {
+                value - last
+            }
+        };
+
+        let mut lines = Vec::new();
+        for sample in samples(families) {
+            let name = self.name(&sample);
+            match sample.value {
+                SampleValue::Counter(v) => {
+                    let delta = increase(&name, v);
+                    if delta > 0.0 {
+                        lines.push(format!("{}:{}|c", name, delta));
+                    }
+                }
+                SampleValue::Gauge(v) => lines.push(format!("{}:{}|g", name, v)),
+                SampleValue::Histogram { count, sum } => {
+                    let count_name = format!("{}.count", name);
+                    let delta = increase(&count_name, count as f64);
+                    if delta > 0.0 {
+                        lines.push(format!("{}:{}|c", count_name, delta));
+                    }
+                    lines.push(format!("{}.sum:{}|g", name, sum));
+                }
+            }
+        }
+        lines
+    }
+}
+
+#[async_trait]
+impl Exporter for StatsdExporter {
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String> {
+        let mut datagram = String::new();
+        for line in self.lines(families) {
+            if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+                self.socket
+                    .send(datagram.as_bytes())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&line);
+        }
+        if !datagram.is_empty() {
+            self.socket
+                .send(datagram.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Pushes metrics to InfluxDB's HTTP write API, in line protocol.
+///
+/// Each metric is its own measurement, with its labels as tags. Counters and gauges have a
+/// single `value` field, histograms a `count` and a `sum`. Points are timestamped by InfluxDB
+/// when it receives them.
+pub struct InfluxExporter {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+/// Escapes commas, spaces and equals signs, which mean something in measurements, tag keys and
+/// tag values.
+fn escape_influx(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl InfluxExporter {
+    /// Creates a exporter writing to `url`, authenticating with `token` if there is one. Writes
+    /// give up after `timeout`.
+    pub fn new(url: String, token: Option<String>, timeout: Duration) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("failed to build the InfluxDB client"),
+            url,
+            token,
+        }
+    }
+
+    /// Turns `families` into line protocol, one point per line.
+    pub fn body(families: &[MetricFamily]) -> String {
+        let mut body = String::new();
+        // writing to a String never fails, so the results below are ignored
+        for sample in samples(families) {
+            body.push_str(&escape_influx(&sample.name));
+            for (key, value) in sample.labels.iter() {
+                let _ = write!(body, ",{}={}", escape_influx(key), escape_influx(value));
+            }
+            let _ = match sample.value {
+                SampleValue::Counter(v) | SampleValue::Gauge(v) => writeln!(body, " value={}", v),
+                SampleValue::Histogram { count, sum } => {
+                    writeln!(body, " count={}i,sum={}", count, sum)
+                }
+            };
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl Exporter for InfluxExporter {
+    async fn export(&self, families: &[MetricFamily]) -> Result<(), String> {
+        let mut request = self.client.post(&self.url).body(Self::body(families));
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "InfluxDB returned {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ))
+        }
+    }
+}
+
+/// Starts pushing metrics to every exporter in `configs`, each on its own interval.
+///
+/// Exporters that can't be set up are logged and skipped.
+pub async fn spawn_exporters(configs: &[MetricsExporterConfig]) {
+    for config in configs {
+        let interval = match config {
+            MetricsExporterConfig::Statsd { interval, .. }
+            | MetricsExporterConfig::Influx { interval, .. } => *interval,
+        };
+        let period = Duration::from_secs(interval.max(1));
+        // a push has to be done well before the next one is due
+        let timeout = period / 2;
+        let exporter: Box<dyn Exporter> = match config {
+            MetricsExporterConfig::Statsd {
+                address, prefix, ..
+            } => match StatsdExporter::new(address, prefix.clone()).await {
+                Ok(e) => {
+                    info!("pushing metrics to StatsD at {}", address);
+                    Box::new(e)
+                }
+                Err(e) => {
+                    warn!("couldn't set up the StatsD exporter for {}: {}", address, e);
+                    continue;
+                }
+            },
+            MetricsExporterConfig::Influx { url, token, .. } => {
+                info!("pushing metrics to InfluxDB at {}", url);
+                Box::new(InfluxExporter::new(url.clone(), token.clone(), timeout))
+            }
+        };
+
+        let name = match config {
+            MetricsExporterConfig::Statsd { address, .. } => {
+                format!("statsd_exporter({})", address)
+            }
+            MetricsExporterConfig::Influx { url, .. } => format!("influx_exporter({})", url),
+        };
+        register_task(&name, period);
+        tokio::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                interval.tick().await;
+                let families = unsafe { METRICS.get().unwrap_unchecked() }
+                    .registry
+                    .gather();
+                // a sink that hangs mustn't stop the heartbeat, or the bot would be restarted
+                // because its metrics are down
+                match time::timeout(timeout, exporter.export(&families)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("failed to push metrics: {}", e),
+                    Err(_) => warn!("pushing metrics timed out after {:?}", timeout),
+                }
+                heartbeat(&name, period);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Gauge, Histogram, HistogramOpts, IntCounterVec, Opts, Registry};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    struct TestMetrics {
+        registry: Registry,
+        commands: IntCounterVec,
+        guilds: Gauge,
+        latency: Histogram,
+    }
+
+    fn test_metrics() -> TestMetrics {
+        let registry = Registry::new();
+        let commands =
+            IntCounterVec::new(Opts::new("commands_used", "help"), &["command_name"]).unwrap();
+        let guilds = Gauge::new("guilds", "help").unwrap();
+        let latency = Histogram::with_opts(HistogramOpts::new("latency", "help")).unwrap();
+        registry.register(Box::new(commands.clone())).unwrap();
+        registry.register(Box::new(guilds.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        TestMetrics {
+            registry,
+            commands,
+            guilds,
+            latency,
+        }
+    }
+
+    async fn recv_lines(socket: &UdpSocket) -> Vec<String> {
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        let len = time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .expect("no datagram arrived")
+            .unwrap();
+        String::from_utf8(buf[..len].to_vec())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn statsd_sends_counter_deltas_after_the_first_push() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let exporter = StatsdExporter::new(&server.local_addr().unwrap().to_string(), "s".into())
+            .await
+            .unwrap();
+        let m = test_metrics();
+        m.commands.with_label_values(&["help"]).inc_by(5);
+        m.guilds.set(12.0);
+        m.latency.observe(0.5);
+
+        // counters restored from metrics.json shouldn't be counted again
+        exporter.export(&m.registry.gather()).await.unwrap();
+        assert_eq!(
+            recv_lines(&server).await,
+            ["s.guilds:12|g", "s.latency.sum:0.5|g"]
+        );
+
+        m.commands.with_label_values(&["help"]).inc_by(3);
+        m.commands.with_label_values(&["ping"]).inc();
+        m.latency.observe(1.0);
+        exporter.export(&m.registry.gather()).await.unwrap();
+        assert_eq!(
+            recv_lines(&server).await,
+            [
+                "s.commands_used.help:3|c",
+                "s.commands_used.ping:1|c",
+                "s.guilds:12|g",
+                "s.latency.count:1|c",
+                "s.latency.sum:1.5|g",
+            ]
+        );
+
+        // only what `ping` went up by since the last push is sent
+        m.commands.with_label_values(&["ping"]).inc_by(2);
+        exporter.export(&m.registry.gather()).await.unwrap();
+        assert_eq!(
+            recv_lines(&server).await,
+            [
+                "s.commands_used.ping:2|c",
+                "s.guilds:12|g",
+                "s.latency.sum:1.5|g",
+            ]
+        );
+    }
+
+    /// Accepts one HTTP request, answers it with `status`, and returns the request.
+    async fn serve_once(listener: TcpListener, status: &'static str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let len = stream.read(&mut buf).await.unwrap();
+            assert_ne!(len, 0, "connection closed before the request was read");
+            request.extend_from_slice(&buf[..len]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|l| {
+                        let (name, value) = l.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        stream
+            .write_all(format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status).as_bytes())
+            .await
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn influx_posts_line_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v2/write", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(listener, "204 No Content"));
+
+        let m = test_metrics();
+        m.commands.with_label_values(&["help me"]).inc_by(2);
+        m.guilds.set(3.0);
+        m.latency.observe(0.25);
+        m.latency.observe(0.5);
+        InfluxExporter::new(url, Some("secret".into()), Duration::from_secs(5))
+            .export(&m.registry.gather())
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /api/v2/write HTTP/1.1"));
+        assert!(head
+            .lines()
+            .any(|l| l.eq_ignore_ascii_case("authorization: Token secret")));
+        assert_eq!(
+            body,
+            "commands_used,command_name=help\\ me value=2\n\
+             guilds value=3\n\
+             latency count=2i,sum=0.75\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn influx_errors_are_returned() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/write", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(listener, "400 Bad Request"));
+
+        let m = test_metrics();
+        m.guilds.set(1.0);
+        let result = InfluxExporter::new(url, None, Duration::from_secs(5))
+            .export(&m.registry.gather())
+            .await;
+        server.await.unwrap();
+        assert!(result.unwrap_err().contains("400"));
+    }
+}
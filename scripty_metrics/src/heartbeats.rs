@@ -0,0 +1,98 @@
+//! Keeps track of whether background tasks are still running.
+use dashmap::DashMap;
+use std::{
+    lazy::SyncLazy,
+    time::{Duration, Instant},
+};
+
+/// A task is considered stuck once it's gone this many of its intervals without a heartbeat.
+const STALE_INTERVALS: u32 = 3;
+
+struct Heartbeat {
+    last: Instant,
+    interval: Duration,
+}
+
+static HEARTBEATS: SyncLazy<DashMap<String, Heartbeat>> = SyncLazy::new(DashMap::new);
+
+/// Starts keeping track of the background task `name`, which runs every `interval`. Call this
+/// when spawning the task, so one that never sends its first heartbeat goes stale like one that
+/// stopped sending them, rather than never showing up at all.
+pub fn register_task(name: &str, interval: Duration) {
+    HEARTBEATS.entry(name.to_string()).or_insert(Heartbeat {
+        last: Instant::now(),
+        interval,
+    });
+}
+
+/// Records that the background task `name`, which runs every `interval`, just ran.
+pub fn heartbeat(name: &str, interval: Duration) {
+    HEARTBEATS.insert(
+        name.to_string(),
+        Heartbeat {
+            last: Instant::now(),
+            interval,
+        },
+    );
+}
+
+/// How a background task was doing as of its last heartbeat.
+pub struct TaskStatus {
+    pub name: String,
+    /// Time since the task last ran, or was registered if it hasn't yet.
+    pub age: Duration,
+    pub interval: Duration,
+    /// Whether the task has missed enough heartbeats that it's probably stuck or dead.
+    pub stale: bool,
+}
+
+/// Every background task that's been registered or sent a heartbeat, sorted by name.
+pub fn task_statuses() -> Vec<TaskStatus> {
+    let mut statuses: Vec<TaskStatus> = HEARTBEATS
+        .iter()
+        .map(|h| {
+            let age = h.last.elapsed();
+            TaskStatus {
+                name: h.key().clone(),
+                age,
+                interval: h.interval,
+                stale: age > h.interval * STALE_INTERVALS,
+            }
+        })
+        .collect();
+    statuses.sort_by(|a, b| a.name.cmp(&b.name));
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(name: &str) -> TaskStatus {
+        task_statuses()
+            .into_iter()
+            .find(|t| t.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn registered_tasks_go_stale_without_a_heartbeat() {
+        register_task("never_runs", Duration::from_millis(1));
+        assert_eq!(status("never_runs").interval, Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(status("never_runs").stale);
+    }
+
+    #[test]
+    fn heartbeats_keep_tasks_fresh() {
+        register_task("runs", Duration::from_secs(60));
+        assert!(!status("runs").stale);
+        heartbeat("runs", Duration::from_secs(60));
+        assert!(!status("runs").stale);
+
+        // registering a task again doesn't undo its heartbeats
+        heartbeat("runs_fast", Duration::from_millis(1));
+        register_task("runs_fast", Duration::from_secs(60));
+        assert_eq!(status("runs_fast").interval, Duration::from_millis(1));
+    }
+}
@@ -3,19 +3,24 @@
 
 mod background_updater;
 mod command_run_hook;
+mod exporters;
+mod heartbeats;
+mod persist;
 
 pub use background_updater::spawn_updater_task;
-pub use command_run_hook::before_hook;
+pub use command_run_hook::{after_hook, before_hook, register_commands, UserError};
+pub use exporters::*;
+pub use heartbeats::{heartbeat, register_task, task_statuses, TaskStatus};
+pub use persist::spawn_saver_task;
 
 /// Code used from sushiibot
 /// https://raw.githubusercontent.com/sushiibot/sushii-2/888fbcdaecc0838e5c3735a5aac677a2d327ef10/src/model/metrics.rs
 use chrono::{naive::NaiveDateTime, offset::Utc};
 use prometheus::{
-    Encoder, Gauge, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
-    TextEncoder,
+    exponential_buckets, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
 };
 use prometheus_static_metric::make_static_metric;
-use serde::{Deserialize, Serialize};
 use serenity::{async_trait, model::prelude::*, prelude::*};
 use std::lazy::SyncOnceCell as OnceCell;
 use std::sync::Arc;
@@ -137,22 +142,6 @@ make_static_metric! {
         unknown,
     }
 
-    pub label_enum CommandsUsed {
-        info,
-        prefix,
-        donate,
-        ping,
-        status,
-        credits,
-        stats,
-        join,
-        setup,
-        rejoin_all,
-        shutdown,
-        add_premium,
-        eval
-    }
-
     pub struct MessageCounterVec: IntCounter {
         "user_type" => UserType,
     }
@@ -184,10 +173,6 @@ make_static_metric! {
     pub struct LoadAvgStatsVec: Gauge {
         "load_avg" => LoadAvgStats,
     }
-
-    pub struct CommandsUsedVec: IntCounter {
-        "command_name" => CommandsUsed,
-    }
 }
 
 pub static METRICS: OnceCell<Arc<Metrics>> = OnceCell::new();
@@ -202,20 +187,6 @@ pub fn serialize_metrics() -> Vec<u8> {
     buffer
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct MetricsJson {
-    messages: Messages,
-    ms_transcribed: u64,
-    total_events: u64,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct Messages {
-    user: u64,
-    other_bot: u64,
-    own: u64,
-}
-
 pub struct Metrics {
     pub registry: Registry,
     pub start_time: NaiveDateTime,
@@ -224,8 +195,18 @@ pub struct Metrics {
     pub guilds: IntGauge,
     pub members: IntGauge,
     pub ms_transcribed: IntCounter,
+    pub ms_received: IntCounter,
+    pub quota_rejections: IntCounter,
     pub total_events: IntCounter,
-    pub avg_audio_process_time: IntGauge,
+    pub packet_process_time: Histogram,
+    pub inference_time: Histogram,
+    pub transcription_latency: Histogram,
+    pub real_time_factor: Histogram,
+    pub voice_calls: IntGauge,
+    pub active_speakers: IntGaugeVec,
+    pub waitlisted_speakers: IntGaugeVec,
+    pub audio_buffer_bytes: IntGauge,
+    pub failed_webhooks: IntCounter,
     pub cpu_usage: CpuUsageVec,
     pub mem_usage: MemoryUsageVec,
     pub block_stats: BlockStatsVec,
@@ -234,7 +215,13 @@ pub struct Metrics {
     pub load_avg_stats: LoadAvgStatsVec,
     pub cpu_temp: Gauge,
     pub total_commands: IntCounter,
-    pub commands: CommandsUsedVec,
+    pub commands: IntCounterVec,
+    pub command_duration: HistogramVec,
+    pub command_results: IntCounterVec,
+    /// Every counter that's saved to disk, see `persist.rs`.
+    persisted_counters: Vec<IntCounter>,
+    /// Every counter vec that's saved to disk, see `persist.rs`.
+    persisted_counter_vecs: Vec<IntCounterVec>,
 }
 
 #[allow(clippy::new_without_default)]
@@ -245,12 +232,12 @@ impl Metrics {
         let messages_vec =
             IntCounterVec::new(Opts::new("messages", "Received messages"), &["user_type"]).unwrap();
         let messages_static_vec = MessageCounterVec::from(&messages_vec);
-        registry.register(Box::new(messages_vec)).unwrap();
+        registry.register(Box::new(messages_vec.clone())).unwrap();
 
         let events_vec =
             IntCounterVec::new(Opts::new("events", "Gateway events"), &["event_type"]).unwrap();
         let events_static_vec = EventCounterVec::from(&events_vec);
-        registry.register(Box::new(events_vec)).unwrap();
+        registry.register(Box::new(events_vec.clone())).unwrap();
 
         let guilds_gauge = IntGauge::new("guilds", "Current guilds").unwrap();
         registry.register(Box::new(guilds_gauge.clone())).unwrap();
@@ -262,15 +249,112 @@ impl Metrics {
             IntCounter::new("audio_transcribed", "Milliseconds of audio transcribed").unwrap();
         registry.register(Box::new(ms_transcribed.clone())).unwrap();
 
+        let ms_received = IntCounter::new(
+            "audio_received",
+            "Milliseconds of audio received from voice chats. Includes bots.",
+        )
+        .unwrap();
+        registry.register(Box::new(ms_received.clone())).unwrap();
+
+        let quota_rejections = IntCounter::new(
+            "quota_rejections",
+            "Utterances not transcribed because their guild used up its monthly quota",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(quota_rejections.clone()))
+            .unwrap();
+
         let events = IntCounter::new("total_events", "Total gateway events").unwrap();
         registry.register(Box::new(events.clone())).unwrap();
 
-        let audio_process = IntGauge::new(
-            "avg_audio_process_time",
-            "Average time to process one audio packet. Includes bots.",
+        // 10µs up to about 0.3s
+        let packet_process_time = Histogram::with_opts(
+            HistogramOpts::new(
+                "packet_process_time",
+                "Seconds taken to handle one audio packet. Includes bots.",
+            )
+            .buckets(exponential_buckets(0.000_01, 2.0, 16).unwrap()),
+        )
+        .unwrap();
+        registry
+            .register(Box::new(packet_process_time.clone()))
+            .unwrap();
+
+        // 50ms up to about 50s
+        let inference_time = Histogram::with_opts(
+            HistogramOpts::new(
+                "inference_time",
+                "Seconds taken to run speech-to-text on one utterance",
+            )
+            .buckets(exponential_buckets(0.05, 2.0, 11).unwrap()),
+        )
+        .unwrap();
+        registry.register(Box::new(inference_time.clone())).unwrap();
+
+        let transcription_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "transcription_latency",
+                "Seconds from someone stopping talking to their transcription being posted",
+            )
+            .buckets(exponential_buckets(0.05, 2.0, 11).unwrap()),
+        )
+        .unwrap();
+        registry
+            .register(Box::new(transcription_latency.clone()))
+            .unwrap();
+
+        // above 1 means speech-to-text is slower than people talk, and falling behind
+        let real_time_factor = Histogram::with_opts(
+            HistogramOpts::new(
+                "real_time_factor",
+                "Time taken to transcribe an utterance divided by how long the utterance is",
+            )
+            .buckets(vec![0.05, 0.1, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 5.0, 10.0]),
+        )
+        .unwrap();
+        registry
+            .register(Box::new(real_time_factor.clone()))
+            .unwrap();
+
+        let voice_calls = IntGauge::new("voice_calls", "Connected voice calls").unwrap();
+        registry.register(Box::new(voice_calls.clone())).unwrap();
+
+        let active_speakers = IntGaugeVec::new(
+            Opts::new("active_speakers", "Users being transcribed"),
+            &["premium_level"],
         )
         .unwrap();
-        registry.register(Box::new(audio_process.clone())).unwrap();
+        registry
+            .register(Box::new(active_speakers.clone()))
+            .unwrap();
+
+        let waitlisted_speakers = IntGaugeVec::new(
+            Opts::new(
+                "waitlisted_speakers",
+                "Users waiting for a spot because their voice chat is over its user limit",
+            ),
+            &["premium_level"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(waitlisted_speakers.clone()))
+            .unwrap();
+
+        let audio_buffer_bytes = IntGauge::new(
+            "audio_buffer_bytes",
+            "Bytes of audio buffered waiting for people to stop talking",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(audio_buffer_bytes.clone()))
+            .unwrap();
+
+        let failed_webhooks =
+            IntCounter::new("failed_webhooks", "Transcriptions that failed to post").unwrap();
+        registry
+            .register(Box::new(failed_webhooks.clone()))
+            .unwrap();
 
         let cpu_usage = GaugeVec::new(Opts::new("cpu_usage", "CPU usage"), &["cpu_type"]).unwrap();
         let cpu_usage_static = CpuUsageVec::from(&cpu_usage);
@@ -315,9 +399,43 @@ impl Metrics {
             &["command_name"],
         )
         .unwrap();
-        let commands_used_static = CommandsUsedVec::from(&commands_used);
         registry.register(Box::new(commands_used.clone())).unwrap();
 
+        // 10ms up to about 40s
+        let command_duration = HistogramVec::new(
+            HistogramOpts::new("command_duration", "Seconds taken to run a command")
+                .buckets(exponential_buckets(0.01, 2.0, 13).unwrap()),
+            &["command_name"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(command_duration.clone()))
+            .unwrap();
+
+        let command_results = IntCounterVec::new(
+            Opts::new("command_results", "How commands finished"),
+            &["command_name", "result"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(command_results.clone()))
+            .unwrap();
+
+        let persisted_counters = vec![
+            ms_transcribed.clone(),
+            ms_received.clone(),
+            quota_rejections.clone(),
+            events.clone(),
+            failed_webhooks.clone(),
+            total_commands_used.clone(),
+        ];
+        let persisted_counter_vecs = vec![
+            messages_vec,
+            events_vec,
+            commands_used.clone(),
+            command_results.clone(),
+        ];
+
         Self {
             registry,
             start_time: Utc::now().naive_utc(),
@@ -326,8 +444,18 @@ impl Metrics {
             guilds: guilds_gauge,
             members: members_gauge,
             ms_transcribed,
+            ms_received,
+            quota_rejections,
             total_events: events,
-            avg_audio_process_time: audio_process,
+            packet_process_time,
+            inference_time,
+            transcription_latency,
+            real_time_factor,
+            voice_calls,
+            active_speakers,
+            waitlisted_speakers,
+            audio_buffer_bytes,
+            failed_webhooks,
             cpu_usage: cpu_usage_static,
             mem_usage: mem_usage_static,
             block_stats: block_stats_static,
@@ -336,49 +464,13 @@ impl Metrics {
             load_avg_stats: load_avg_static,
             cpu_temp,
             total_commands: total_commands_used,
-            commands: commands_used_static,
+            commands: commands_used,
+            command_duration,
+            command_results,
+            persisted_counters,
+            persisted_counter_vecs,
         }
     }
-
-    /// Load metrics from disk, from a file called `metrics.json`
-    /// # Panics
-    /// This function panics if the metrics file cannot be parsed as JSON. This could happen if it's empty.
-    pub async fn load_metrics(&self) {
-        let buf = match tokio::fs::read("metrics.json").await {
-            Ok(f) => f,
-            Err(_) => return,
-        };
-        let d = serde_json::from_slice::<MetricsJson>(&buf[..])
-            .expect("failed to parse metrics file as JSON");
-        self.messages.user.inc_by(d.messages.user);
-        self.messages.other_bot.inc_by(d.messages.other_bot);
-        self.messages.own.inc_by(d.messages.own);
-        self.ms_transcribed.inc_by(d.ms_transcribed);
-        self.total_events.inc_by(d.total_events);
-    }
-
-    /// Save metrics to disk.
-    /// # Panics
-    /// This function panics if
-    /// * the metrics could not be serialized as JSON
-    /// * something happened while writing to disk
-    pub async fn save_metrics(&self) {
-        let r = MetricsJson {
-            messages: Messages {
-                user: self.messages.user.get(),
-                other_bot: self.messages.other_bot.get(),
-                own: self.messages.own.get(),
-            },
-            ms_transcribed: self.ms_transcribed.get(),
-            total_events: self.total_events.get(),
-        };
-        tokio::fs::write(
-            "metrics.json",
-            serde_json::to_vec(&r).expect("failed to serialize JSON"),
-        )
-        .await
-        .expect("failed to write metrics to disk");
-    }
 }
 
 #[async_trait]
@@ -0,0 +1,159 @@
+//! Saving counters to disk, so they keep counting up across restarts instead of starting from
+//! zero every time.
+//!
+//! Gauges and histograms aren't saved: gauges get set to the current value as soon as the bot
+//! is running again, and histograms only really matter for the last few minutes.
+use crate::{heartbeat, register_task, Metrics, METRICS};
+use prometheus::core::Collector;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+use tokio::time;
+use tracing::{debug, warn};
+
+const METRICS_FILE: &str = "metrics.json";
+const METRICS_TMP_FILE: &str = "metrics.json.tmp";
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A snapshot of every persisted counter.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MetricsJson {
+    /// Plain counters, by their full name.
+    #[serde(default)]
+    counters: BTreeMap<String, u64>,
+    /// Counter vecs, by their full name, then by their label values joined with commas.
+    #[serde(default)]
+    counter_vecs: BTreeMap<String, BTreeMap<String, u64>>,
+
+    // only found in files written before everything was saved
+    #[serde(default, skip_serializing)]
+    messages: Option<Messages>,
+    #[serde(default, skip_serializing)]
+    ms_transcribed: Option<u64>,
+    #[serde(default, skip_serializing)]
+    total_events: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Messages {
+    user: u64,
+    other_bot: u64,
+    own: u64,
+}
+
+/// Saves metrics to disk every minute, that way a crash loses at most a minute of counts.
+pub fn spawn_saver_task() {
+    register_task("metrics_saver", SAVE_INTERVAL);
+    tokio::spawn(async move {
+        let mut interval = time::interval(SAVE_INTERVAL);
+        // the first tick completes immediately, and there's nothing new to save yet
+        interval.tick().await;
+        heartbeat("metrics_saver", SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            unsafe { METRICS.get().unwrap_unchecked() }
+                .save_metrics()
+                .await;
+            heartbeat("metrics_saver", SAVE_INTERVAL);
+        }
+    });
+}
+
+/// The full name of the one metric `c` collects.
+fn name_of(c: &impl Collector) -> String {
+    c.desc()
+        .first()
+        .map_or_else(String::new, |d| d.fq_name.clone())
+}
+
+impl Metrics {
+    /// Load metrics from disk, from a file called `metrics.json`.
+    ///
+    /// A missing, empty or corrupt file is logged and otherwise ignored, so the bot starts
+    /// counting from zero rather than not starting at all.
+    pub async fn load_metrics(&self) {
+        let buf = match tokio::fs::read(METRICS_FILE).await {
+            Ok(f) if f.is_empty() => {
+                warn!("{} is empty, starting metrics from zero", METRICS_FILE);
+                return;
+            }
+            Ok(f) => f,
+            Err(_) => return,
+        };
+        let d = match serde_json::from_slice::<MetricsJson>(&buf[..]) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!(
+                    "failed to parse {}, starting metrics from zero: {}",
+                    METRICS_FILE, e
+                );
+                return;
+            }
+        };
+
+        for counter in self.persisted_counters.iter() {
+            if let Some(v) = d.counters.get(&name_of(counter)) {
+                counter.inc_by(*v);
+            }
+        }
+        for vec in self.persisted_counter_vecs.iter() {
+            if let Some(values) = d.counter_vecs.get(&name_of(vec)) {
+                for (labels, v) in values {
+                    let labels: Vec<&str> = labels.split(',').collect();
+                    // a label was added or removed since this was saved
+                    if let Ok(counter) = vec.get_metric_with_label_values(&labels) {
+                        counter.inc_by(*v);
+                    }
+                }
+            }
+        }
+
+        if let Some(messages) = d.messages {
+            self.messages.user.inc_by(messages.user);
+            self.messages.other_bot.inc_by(messages.other_bot);
+            self.messages.own.inc_by(messages.own);
+        }
+        if let Some(ms_transcribed) = d.ms_transcribed {
+            self.ms_transcribed.inc_by(ms_transcribed);
+        }
+        if let Some(total_events) = d.total_events {
+            self.total_events.inc_by(total_events);
+        }
+    }
+
+    /// Save metrics to disk.
+    ///
+    /// The snapshot is written to a temporary file first and then moved over `metrics.json`, so
+    /// a crash halfway through a save can't leave a half written file behind. Errors are logged.
+    pub async fn save_metrics(&self) {
+        let mut d = MetricsJson::default();
+        for counter in self.persisted_counters.iter() {
+            d.counters.insert(name_of(counter), counter.get());
+        }
+        for vec in self.persisted_counter_vecs.iter() {
+            let values = d.counter_vecs.entry(name_of(vec)).or_default();
+            for family in vec.collect() {
+                for metric in family.get_metric() {
+                    let labels: Vec<&str> =
+                        metric.get_label().iter().map(|l| l.get_value()).collect();
+                    values.insert(labels.join(","), metric.get_counter().get_value() as u64);
+                }
+            }
+        }
+
+        let buf = match serde_json::to_vec(&d) {
+            Ok(b) => b,
+            Err(e) => {
+                warn!("failed to serialize metrics: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::write(METRICS_TMP_FILE, buf).await {
+            warn!("failed to write metrics to {}: {}", METRICS_TMP_FILE, e);
+            return;
+        }
+        match tokio::fs::rename(METRICS_TMP_FILE, METRICS_FILE).await {
+            Ok(_) => debug!("saved metrics to {}", METRICS_FILE),
+            Err(e) => warn!("failed to move metrics to {}: {}", METRICS_FILE, e),
+        }
+    }
+}
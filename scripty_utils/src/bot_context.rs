@@ -0,0 +1,6 @@
+use serenity::client::Context;
+use std::{lazy::SyncOnceCell as OnceCell, sync::Arc};
+
+/// The bot's context, set once its cache is ready. Lets things that don't get one handed to
+/// them, like the webserver, look at the bot.
+pub static BOT_CONTEXT: OnceCell<Arc<Context>> = OnceCell::new();
@@ -1,7 +1,6 @@
-use scripty_db::PgPoolKey;
+use scripty_db::{fetch_prefix, PgPoolKey};
 use serenity::client::Context;
-use serenity::model::prelude::{ChannelId, MessageId};
-use sqlx::query;
+use serenity::model::prelude::{ChannelId, GuildId, MessageId};
 use std::sync::Arc;
 use std::time::SystemTime;
 
@@ -44,12 +43,7 @@ pub async fn do_stats_update(ctx: Arc<Context>) {
         let mut v: u128 = 0;
         if let Some(db) = ctx.data.read().await.get::<PgPoolKey>() {
             let start = SystemTime::now();
-            let _ = query!(
-                "SELECT prefix FROM prefixes WHERE guild_id = $1",
-                675390855716274216_i64
-            )
-            .fetch_optional(db)
-            .await;
+            let _ = fetch_prefix(db, GuildId(675390855716274216)).await;
             v = start
                 .elapsed()
                 .expect("System clock rolled back!")
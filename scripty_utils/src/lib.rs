@@ -1,6 +1,7 @@
 #![feature(once_cell)]
 #![feature(option_result_unwrap_unchecked)]
 
+mod bot_context;
 mod bot_info;
 mod context_types;
 mod do_stats_update;
@@ -11,6 +12,7 @@ mod start_time;
 mod update_status;
 mod ws_latency;
 
+pub use bot_context::*;
 pub use bot_info::*;
 pub use context_types::*;
 pub use do_stats_update::*;
@@ -0,0 +1,367 @@
+//! An API for running the bot without going through Discord.
+//!
+//! Every route needs the config's `admin_token` as `Authorization: Bearer <token>`, and 404s if
+//! there isn't one. Changes to a guild's settings only reach voice chats the bot is already in
+//! once it rejoins them, which `POST /v1/admin/guilds/<guild_id>/rejoin` does.
+use crate::ApiError;
+use rocket::{
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest},
+    serde::json::Json,
+    Request,
+};
+use schemars::JsonSchema;
+use scripty_audio::{
+    connected_channel, leave_guild, rejoin_guild, voice_contexts, TranscriptFormat,
+};
+use scripty_config::BotConfig;
+use scripty_db::{
+    delete_output_channel, fetch_guild_config, guild_bindings, guild_output_channels, hash_api_key,
+    set_output_webhook, set_user_premium, update_guild_config, DbError, GuildConfigUpdate,
+    OutputWebhook, PG_POOL,
+};
+use scripty_utils::BOT_CONTEXT;
+use serde::{Deserialize, Deserializer, Serialize};
+use serenity::{
+    client::Context,
+    model::id::{ChannelId, GuildId, UserId, WebhookId},
+};
+use sqlx::PgPool;
+use std::{convert::TryFrom, sync::Arc};
+
+/// Proof a request was made with the admin token.
+pub struct AdminToken;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let expected = match BotConfig::get().and_then(|c| c.admin_token()) {
+            Some(t) => t,
+            None => return Outcome::Failure((Status::NotFound, ())),
+        };
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        // comparing hashes keeps how long this takes from giving away how much of a guess was right
+        match given {
+            Some(t) if hash_api_key(t) == hash_api_key(expected) => Outcome::Success(AdminToken),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+fn bot_context() -> Result<&'static Arc<Context>, ApiError> {
+    BOT_CONTEXT
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))
+}
+
+fn db() -> Result<&'static PgPool, ApiError> {
+    PG_POOL
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))
+}
+
+fn db_error(e: DbError) -> ApiError {
+    tracing::warn!("admin API query failed: {}", e);
+    ApiError::new(Status::InternalServerError, "database error")
+}
+
+/// Deserializes a field that can be left out, set to `null`, or set to a value, into `None`,
+/// `Some(None)` and `Some(Some(value))` respectively. Use it with `#[serde(default)]`.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// A voice connection held by the bot or one of its workers.
+#[derive(Serialize, JsonSchema)]
+pub struct ActiveCall {
+    pub guild_id: u64,
+    pub voice_channel: u64,
+    /// The user ID of the bot account holding the connection.
+    pub bot_user_id: u64,
+}
+
+async fn guild_calls(ctx: &Context, guild_id: GuildId) -> Vec<ActiveCall> {
+    let mut calls = Vec::new();
+    for c in voice_contexts(ctx, guild_id).await {
+        if let Some(voice_channel) = connected_channel(&c, guild_id).await {
+            calls.push(ActiveCall {
+                guild_id: guild_id.0,
+                voice_channel,
+                bot_user_id: c.cache.current_user_id().await.0,
+            });
+        }
+    }
+    calls
+}
+
+/// Lists every voice connection in every guild.
+#[rocket::get("/v1/admin/calls")]
+pub async fn admin_calls(_admin: AdminToken) -> Result<Json<Vec<ActiveCall>>, ApiError> {
+    let ctx = bot_context()?;
+    let mut calls = Vec::new();
+    for guild_id in ctx.cache.guilds().await {
+        calls.extend(guild_calls(ctx, guild_id).await);
+    }
+    Ok(Json(calls))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LeaveResult {
+    /// How many voice chats were left.
+    pub left: usize,
+}
+
+/// Leaves every voice chat in a guild. Its bindings are kept, so the next auto join brings the
+/// bot back unless they're removed.
+#[rocket::post("/v1/admin/guilds/<guild_id>/leave")]
+pub async fn admin_leave(_admin: AdminToken, guild_id: u64) -> Result<Json<LeaveResult>, ApiError> {
+    let ctx = bot_context()?;
+    let left = leave_guild(ctx, GuildId(guild_id))
+        .await
+        .map_err(|e| ApiError::new(Status::InternalServerError, e))?;
+    Ok(Json(LeaveResult { left }))
+}
+
+/// Rejoins every voice chat bound in a guild, returning the guild's voice connections after.
+#[rocket::post("/v1/admin/guilds/<guild_id>/rejoin")]
+pub async fn admin_rejoin(
+    _admin: AdminToken,
+    guild_id: u64,
+) -> Result<Json<Vec<ActiveCall>>, ApiError> {
+    let ctx = bot_context()?;
+    rejoin_guild(Arc::clone(ctx), GuildId(guild_id)).await;
+    Ok(Json(guild_calls(ctx, GuildId(guild_id)).await))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AdminBinding {
+    pub voice_channel: u64,
+    pub output_channel: u64,
+}
+
+/// A row of the `channels` table. The webhook token is a secret, so only whether it's set is
+/// shown.
+#[derive(Serialize, JsonSchema)]
+pub struct AdminChannel {
+    pub channel_id: u64,
+    pub webhook_id: Option<u64>,
+    pub has_webhook_token: bool,
+}
+
+/// A guild's row in the `guilds` table, with its bindings and the `channels` rows of the
+/// channels it outputs to.
+#[derive(Serialize, JsonSchema)]
+pub struct AdminGuild {
+    pub guild_id: u64,
+    pub default_bind: Option<u64>,
+    pub output_channel: Option<u64>,
+    pub premium_level: i16,
+    pub session_threads: bool,
+    pub transcript_format: Option<String>,
+    /// Whether the guild has a live feed token.
+    pub live_feed: bool,
+    pub bindings: Vec<AdminBinding>,
+    pub channels: Vec<AdminChannel>,
+}
+
+async fn fetch_guild(db: &PgPool, guild_id: u64) -> Result<AdminGuild, ApiError> {
+    let guild_id = GuildId(guild_id);
+    let g = fetch_guild_config(db, guild_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| ApiError::new(Status::NotFound, "that guild isn't set up"))?;
+
+    let bindings = guild_bindings(db, guild_id)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|b| AdminBinding {
+            voice_channel: b.voice_channel.0,
+            output_channel: b.output_channel.0,
+        })
+        .collect();
+
+    let channels = guild_output_channels(db, guild_id)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|c| AdminChannel {
+            channel_id: c.channel_id.0,
+            webhook_id: c.webhook_id.map(|w| w.0),
+            has_webhook_token: c.webhook_token.is_some(),
+        })
+        .collect();
+
+    Ok(AdminGuild {
+        guild_id: g.guild_id.0,
+        default_bind: g.default_bind.map(|c| c.0),
+        output_channel: g.output_channel.map(|c| c.0),
+        premium_level: g.premium_level.into(),
+        session_threads: g.session_threads,
+        transcript_format: g.transcript_format,
+        live_feed: g.live_token_hash.is_some(),
+        bindings,
+        channels,
+    })
+}
+
+/// Shows a guild's settings.
+#[rocket::get("/v1/admin/guilds/<guild_id>")]
+pub async fn admin_guild(_admin: AdminToken, guild_id: u64) -> Result<Json<AdminGuild>, ApiError> {
+    Ok(Json(fetch_guild(db()?, guild_id).await?))
+}
+
+/// Changes to a guild's row. Fields that are left out aren't changed, and nullable ones can be
+/// cleared with `null`.
+#[derive(Deserialize, JsonSchema)]
+pub struct GuildUpdate {
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_bind: Option<Option<u64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub output_channel: Option<Option<u64>>,
+    pub premium_level: Option<i16>,
+    pub session_threads: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub transcript_format: Option<Option<String>>,
+}
+
+/// Edits a guild's settings, returning them after.
+#[rocket::patch("/v1/admin/guilds/<guild_id>", data = "<update>")]
+pub async fn admin_update_guild(
+    _admin: AdminToken,
+    guild_id: u64,
+    update: Json<GuildUpdate>,
+) -> Result<Json<AdminGuild>, ApiError> {
+    let update = update.into_inner();
+    let premium_level = update
+        .premium_level
+        .map(u8::try_from)
+        .transpose()
+        .map_err(|_| {
+            ApiError::new(
+                Status::UnprocessableEntity,
+                "premium_level has to be between 0 and 255",
+            )
+        })?;
+    // store the canonical name, like the `transcript_format` command does
+    let transcript_format = match update.transcript_format {
+        Some(Some(f)) => match TranscriptFormat::from_name(&f) {
+            Some(f) => Some(Some(f.name().to_string())),
+            None => {
+                return Err(ApiError::new(
+                    Status::UnprocessableEntity,
+                    format!("`{}` isn't a transcript format", f),
+                ))
+            }
+        },
+        other => other,
+    };
+
+    let db = db()?;
+    let found = update_guild_config(
+        db,
+        GuildId(guild_id),
+        GuildConfigUpdate {
+            default_bind: update.default_bind.map(|c| c.map(ChannelId)),
+            output_channel: update.output_channel.map(|c| c.map(ChannelId)),
+            premium_level,
+            session_threads: update.session_threads,
+            transcript_format,
+        },
+    )
+    .await
+    .map_err(db_error)?;
+    if !found {
+        return Err(ApiError::new(Status::NotFound, "that guild isn't set up"));
+    }
+
+    Ok(Json(fetch_guild(db, guild_id).await?))
+}
+
+/// A channel's webhook, as stored in the `channels` table.
+#[derive(Deserialize, JsonSchema)]
+pub struct ChannelWebhook {
+    pub webhook_id: u64,
+    pub webhook_token: String,
+}
+
+/// Sets the webhook a channel's transcriptions are posted with.
+#[rocket::put("/v1/admin/channels/<channel_id>", data = "<webhook>")]
+pub async fn admin_set_channel(
+    _admin: AdminToken,
+    channel_id: u64,
+    webhook: Json<ChannelWebhook>,
+) -> Result<Status, ApiError> {
+    let webhook = webhook.into_inner();
+    set_output_webhook(
+        db()?,
+        ChannelId(channel_id),
+        &OutputWebhook {
+            id: WebhookId(webhook.webhook_id),
+            token: webhook.webhook_token,
+        },
+    )
+    .await
+    .map_err(db_error)?;
+    Ok(Status::NoContent)
+}
+
+/// Forgets a channel's webhook.
+#[rocket::delete("/v1/admin/channels/<channel_id>")]
+pub async fn admin_delete_channel(_admin: AdminToken, channel_id: u64) -> Result<Status, ApiError> {
+    let deleted = delete_output_channel(db()?, ChannelId(channel_id))
+        .await
+        .map_err(db_error)?;
+    if !deleted {
+        Err(ApiError::new(
+            Status::NotFound,
+            "that channel has no webhook",
+        ))
+    } else {
+        Ok(Status::NoContent)
+    }
+}
+
+/// A user's premium, as stored in the `users` table.
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct UserPremium {
+    pub premium_level: i16,
+    /// How many guilds the user can give premium to.
+    pub premium_count: i16,
+}
+
+/// Sets a user's premium level, which decides their API limits.
+#[rocket::put("/v1/admin/users/<user_id>/premium", data = "<premium>")]
+pub async fn admin_set_user_premium(
+    _admin: AdminToken,
+    user_id: u64,
+    premium: Json<UserPremium>,
+) -> Result<Json<UserPremium>, ApiError> {
+    let (level, count) = match (
+        u8::try_from(premium.premium_level),
+        u8::try_from(premium.premium_count),
+    ) {
+        (Ok(l), Ok(c)) => (l, c),
+        _ => {
+            return Err(ApiError::new(
+                Status::UnprocessableEntity,
+                "premium_level and premium_count have to be between 0 and 255",
+            ))
+        }
+    };
+    set_user_premium(db()?, UserId(user_id), level, count)
+        .await
+        .map_err(db_error)?;
+    Ok(premium)
+}
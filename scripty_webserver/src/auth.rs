@@ -0,0 +1,154 @@
+use crate::{take_request, ApiError, RateLimitStatus};
+use rocket::{
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest},
+    Request,
+};
+use scripty_db::{
+    api_rate_limit, authorize_api_key, hash_api_key, touch_api_key, ApiScope, KeyAuthorization,
+    PG_POOL,
+};
+
+/// A valid, unrevoked API key, along with who it belongs to.
+///
+/// Keys are read from the `Authorization` header as `Bearer <key>`, or from a `token` header.
+/// Every request made with a key takes one out of its rate limit, and is turned away with a 429
+/// once that runs out.
+pub struct ApiKey {
+    pub key_id: i64,
+    pub user_id: u64,
+    /// The premium level of the key's owner, which decides their limits.
+    pub premium_level: u8,
+    /// What this key is allowed to do.
+    pub scopes: Vec<ApiScope>,
+    /// Where this key's rate limit stood after this request.
+    pub rate_limit: RateLimitStatus,
+}
+
+impl ApiKey {
+    /// Looks up `key`, and takes one request out of its rate limit.
+    pub async fn authenticate(key: &str) -> Result<Self, (Status, ApiKeyError)> {
+        UnchargedKey::lookup(key).await?.charge()
+    }
+
+    /// Fails with a 403 unless this key has `scope`.
+    pub fn require(&self, scope: ApiScope) -> Result<(), ApiError> {
+        require_scope(&self.scopes, scope)
+    }
+}
+
+fn require_scope(scopes: &[ApiScope], scope: ApiScope) -> Result<(), ApiError> {
+    if scopes.contains(&scope) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            Status::Forbidden,
+            format!("this API key doesn't have the `{}` scope", scope.name()),
+        ))
+    }
+}
+
+/// A valid, unrevoked API key that hasn't had anything taken out of its rate limit yet.
+///
+/// Connections that outlive a single request, like streams, hold on to one of these and `charge`
+/// it for each thing they do.
+pub struct UnchargedKey(KeyAuthorization);
+
+impl UnchargedKey {
+    /// Looks up `key`.
+    pub async fn lookup(key: &str) -> Result<Self, (Status, ApiKeyError)> {
+        let db = PG_POOL
+            .get()
+            .ok_or((Status::ServiceUnavailable, ApiKeyError::Database))?;
+
+        // only hashes are stored, so look the key up by its hash
+        let key = match authorize_api_key(db, &hash_api_key(key)).await {
+            Ok(Some(k)) => k,
+            Ok(None) => return Err((Status::Unauthorized, ApiKeyError::Invalid)),
+            Err(e) => {
+                tracing::warn!("failed to look up API key: {}", e);
+                return Err((Status::ServiceUnavailable, ApiKeyError::Database));
+            }
+        };
+
+        let key_id = key.key_id;
+        tokio::spawn(async move {
+            if let Err(e) = touch_api_key(db, key_id).await {
+                tracing::warn!("failed to update last use of API key {}: {}", key_id, e);
+            }
+        });
+        Ok(Self(key))
+    }
+
+    pub fn key_id(&self) -> i64 {
+        self.0.key_id
+    }
+
+    /// The premium level of the key's owner, which decides their limits.
+    pub fn premium_level(&self) -> u8 {
+        self.0.premium_level
+    }
+
+    /// Fails with a 403 unless this key has `scope`.
+    pub fn require(&self, scope: ApiScope) -> Result<(), ApiError> {
+        require_scope(&self.0.scopes, scope)
+    }
+
+    /// Takes one request out of the key's rate limit.
+    pub fn charge(&self) -> Result<ApiKey, (Status, ApiKeyError)> {
+        let key = &self.0;
+        let rate_limit = take_request(
+            key.key_id,
+            api_rate_limit(key.premium_level),
+            key.saved_rate_limit,
+        )
+        .map_err(|s| (Status::TooManyRequests, ApiKeyError::RateLimited(s)))?;
+
+        Ok(ApiKey {
+            key_id: key.key_id,
+            user_id: key.user_id.0,
+            premium_level: key.premium_level,
+            scopes: key.scopes.clone(),
+            rate_limit,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ApiKeyError {
+    Missing,
+    Invalid,
+    Database,
+    RateLimited(RateLimitStatus),
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ApiKeyError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let key = match request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .or_else(|| request.headers().get_one("token"))
+        {
+            Some(k) => k,
+            None => return Outcome::Failure((Status::Unauthorized, ApiKeyError::Missing)),
+        };
+
+        match ApiKey::authenticate(key).await {
+            Ok(key) => {
+                request.local_cache(|| Some(key.rate_limit));
+                Outcome::Success(key)
+            }
+            Err((status, error)) => {
+                if let ApiKeyError::RateLimited(rate_limit) = error {
+                    request.local_cache(|| Some(rate_limit));
+                }
+                Outcome::Failure((status, error))
+            }
+        }
+    }
+}
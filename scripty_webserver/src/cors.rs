@@ -0,0 +1,58 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{Header, Method, Status},
+    Request, Response,
+};
+use scripty_config::BotConfig;
+use std::io::Cursor;
+
+/// Lets browsers on the config's `cors_origins` call the API, answering their preflight
+/// requests too.
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let origin = match request.headers().get_one("Origin") {
+            Some(o) => o,
+            None => return,
+        };
+        let allowed = BotConfig::get().map_or(false, |c| c.web().allows_origin(origin));
+        if !allowed {
+            return;
+        }
+
+        response.set_header(Header::new(
+            "Access-Control-Allow-Origin",
+            origin.to_string(),
+        ));
+        response.set_header(Header::new("Vary", "Origin"));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            "X-RateLimit-Limit, X-RateLimit-Remaining, X-RateLimit-Reset, Retry-After",
+        ));
+
+        // there are no OPTIONS routes, so preflight requests make it here as a 404
+        if request.method() == Method::Options && response.status() == Status::NotFound {
+            response.set_status(Status::NoContent);
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                "GET, POST, PUT, PATCH, DELETE",
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type, token",
+            ));
+            response.set_header(Header::new("Access-Control-Max-Age", "86400"));
+            response.remove_header("Content-Type");
+            response.set_sized_body(0, Cursor::new(""));
+        }
+    }
+}
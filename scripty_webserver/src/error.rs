@@ -0,0 +1,67 @@
+use rocket::{
+    http::Status,
+    response::{self, Responder},
+    serde::json::Json,
+    Request,
+};
+use schemars::JsonSchema;
+use scripty_audio_utils::DecodeError;
+use serde::Serialize;
+
+/// The body of every error response.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+}
+
+/// An error from the API, sent as `{"error": "<message>"}` with `status`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        (
+            self.status,
+            Json(ErrorBody {
+                error: self.message,
+            }),
+        )
+            .respond_to(request)
+    }
+}
+
+impl From<DecodeError> for ApiError {
+    fn from(e: DecodeError) -> Self {
+        let status = match e {
+            DecodeError::Unsupported(_) => Status::UnsupportedMediaType,
+            DecodeError::Corrupt(_) => Status::UnprocessableEntity,
+            DecodeError::TooLong { .. } => Status::PayloadTooLarge,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+/// Turns every error Rocket itself returns, like a request guard failing, into a JSON one.
+#[rocket::catch(default)]
+pub fn default_catcher(status: Status, request: &Request) -> ApiError {
+    let message = match status.code {
+        401 if request.uri().path().starts_with("/v1/admin") => "a valid admin token is required",
+        401 if request.uri().path() == "/metrics" => "a valid metrics token is required",
+        401 => "a valid API key is required",
+        429 => "this API key is being rate limited, see the Retry-After header",
+        _ => status.reason().unwrap_or("something went wrong"),
+    };
+    ApiError::new(status, message)
+}
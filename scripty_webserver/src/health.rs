@@ -0,0 +1,196 @@
+//! Health checks for whatever's running the bot.
+//!
+//! `/healthz` fails when a background task has stopped checking in, meaning something is wedged
+//! and the bot should be restarted. `/readyz` fails until everything the bot needs to do its job
+//! is up: the database, the model, and every shard. Both return the same detailed report.
+use rocket::{http::Status, serde::json::Json};
+use schemars::JsonSchema;
+use scripty_audio_utils::model_loaded;
+use scripty_db::PG_POOL;
+use scripty_metrics::{task_statuses, METRICS};
+use scripty_utils::{ShardManagerWrapper, BOT_CONTEXT};
+use serde::Serialize;
+use serenity::{client::Context, gateway::ConnectionStage};
+use sqlx::query;
+use std::time::{Duration, Instant};
+
+/// How long to wait on the database before calling it down.
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, JsonSchema)]
+pub struct DatabaseHealth {
+    pub ok: bool,
+    /// How long a trivial query took, in milliseconds.
+    pub latency_ms: Option<f64>,
+    /// Open connections in the pool, counting idle ones.
+    pub connections: u32,
+    pub idle_connections: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ShardHealth {
+    pub id: u64,
+    pub stage: String,
+    pub connected: bool,
+    /// The latency of the shard's last heartbeat, in milliseconds.
+    pub latency_ms: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TaskHealth {
+    pub name: String,
+    /// Seconds since the task last ran, or was started if it hasn't yet.
+    pub age_secs: f64,
+    pub interval_secs: f64,
+    pub stale: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct HealthReport {
+    /// Whether `/healthz` passes.
+    pub live: bool,
+    /// Whether `/readyz` passes.
+    pub ready: bool,
+    pub uptime_secs: Option<i64>,
+    /// Whether the bot's cache is ready, which is when its background tasks start.
+    pub bot_ready: bool,
+    pub model_loaded: bool,
+    pub database: DatabaseHealth,
+    pub shards: Vec<ShardHealth>,
+    pub songbird_calls: Option<usize>,
+    pub tasks: Vec<TaskHealth>,
+}
+
+async fn database_health() -> DatabaseHealth {
+    let db = match PG_POOL.get() {
+        Some(db) => db,
+        None => {
+            return DatabaseHealth {
+                ok: false,
+                latency_ms: None,
+                connections: 0,
+                idle_connections: 0,
+                error: Some("not connected yet".to_string()),
+            }
+        }
+    };
+
+    let start = Instant::now();
+    let result = tokio::time::timeout(DB_TIMEOUT, query!("SELECT 1 AS ping").fetch_one(db)).await;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let error = match result {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("timed out after {}s", DB_TIMEOUT.as_secs())),
+    };
+
+    DatabaseHealth {
+        ok: error.is_none(),
+        latency_ms: error.is_none().then(|| latency_ms),
+        connections: db.size(),
+        idle_connections: db.num_idle(),
+        error,
+    }
+}
+
+async fn shard_health(ctx: &Context) -> Vec<ShardHealth> {
+    let data = ctx.data.read().await;
+    let manager = match data.get::<ShardManagerWrapper>() {
+        Some(m) => m,
+        None => return Vec::new(),
+    };
+    let manager = manager.read().await;
+    let manager = manager.lock().await;
+    let runners = manager.runners.lock().await;
+
+    let mut shards: Vec<ShardHealth> = runners
+        .iter()
+        .map(|(id, runner)| ShardHealth {
+            id: id.0,
+            stage: format!("{:?}", runner.stage),
+            connected: runner.stage == ConnectionStage::Connected,
+            latency_ms: runner.latency.map(|l| l.as_secs_f64() * 1000.0),
+        })
+        .collect();
+    shards.sort_by_key(|s| s.id);
+    shards
+}
+
+async fn songbird_calls(ctx: &Context) -> Option<usize> {
+    let manager = songbird::get(ctx).await?;
+    Some(
+        ctx.cache
+            .guilds()
+            .await
+            .into_iter()
+            .filter(|g| manager.get(*g).is_some())
+            .count(),
+    )
+}
+
+async fn health_report() -> HealthReport {
+    let ctx = BOT_CONTEXT.get();
+    let (shards, songbird_calls) = match ctx {
+        Some(ctx) => (shard_health(ctx).await, songbird_calls(ctx).await),
+        None => (Vec::new(), None),
+    };
+    let database = database_health().await;
+    let model_loaded = model_loaded();
+
+    let tasks: Vec<TaskHealth> = task_statuses()
+        .into_iter()
+        .map(|t| TaskHealth {
+            name: t.name,
+            age_secs: t.age.as_secs_f64(),
+            interval_secs: t.interval.as_secs_f64(),
+            stale: t.stale,
+        })
+        .collect();
+
+    let live = tasks.iter().all(|t| !t.stale);
+    let ready = live
+        && ctx.is_some()
+        && database.ok
+        && model_loaded
+        && !shards.is_empty()
+        && shards.iter().all(|s| s.connected);
+
+    HealthReport {
+        live,
+        ready,
+        uptime_secs: METRICS
+            .get()
+            .map(|m| (chrono::Utc::now().naive_utc() - m.start_time).num_seconds()),
+        bot_ready: ctx.is_some(),
+        model_loaded,
+        database,
+        shards,
+        songbird_calls,
+        tasks,
+    }
+}
+
+/// Liveness: 503 if a background task has stopped running, otherwise 200.
+#[rocket::get("/healthz")]
+pub async fn healthz() -> (Status, Json<HealthReport>) {
+    let report = health_report().await;
+    let status = if report.live {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(report))
+}
+
+/// Readiness: 503 until the database, model and every shard are up, otherwise 200.
+#[rocket::get("/readyz")]
+pub async fn readyz() -> (Status, Json<HealthReport>) {
+    let report = health_report().await;
+    let status = if report.ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(report))
+}
@@ -0,0 +1,602 @@
+//! Transcription jobs, for recordings too long to transcribe within one request.
+//!
+//! Uploading audio to `/v1/jobs` queues it up in the DB and returns a job ID straight away.
+//! `spawn_job_workers` picks queued jobs up, at most `job_workers` at a time, and transcribes
+//! them in chunks with the same model as the rest of the API. Once a job finishes its owner can
+//! poll `/v1/jobs/<job_id>` for the result, or have it POSTed to a callback URL, signed with the
+//! secret they got when uploading. Finished jobs are deleted after `job_retention_hours`.
+use crate::{decode_body, record_audio, ApiError, ApiKey, Token};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use rocket::{
+    data::{Data, ToByteUnit},
+    http::Status,
+    serde::json::Json,
+};
+use schemars::JsonSchema;
+use scripty_audio_utils::{model, run_stt_mono, SAMPLE_RATE};
+use scripty_config::BotConfig;
+use scripty_db::{
+    claim_job, count_pending_jobs, delete_expired_jobs, fetch_job as fetch_job_record, finish_job,
+    generate_callback_secret, generate_job_id, insert_job, max_job_audio_secs,
+    requeue_running_jobs, ApiScope, ClaimedJob, DbError, JobStatus, NewJobRecord, MAX_PENDING_JOBS,
+    PG_POOL,
+};
+use scripty_metrics::{heartbeat, register_task};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::{
+    lazy::SyncLazy,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    net::lookup_host,
+    sync::{Notify, Semaphore},
+    time,
+};
+
+/// How often to check for queued jobs, in case a notification was missed.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How much audio is transcribed at once. The model gets slow and inaccurate on long audio.
+const CHUNK_MS: u64 = 30_000;
+
+/// The biggest upload a job takes, whatever the owner's premium level. Hours of uncompressed
+/// audio would be several gigabytes held in memory, so long recordings have to be compressed.
+const MAX_UPLOAD_BYTES: u64 = 512 * 1024 * 1024;
+
+/// How many times to try delivering a callback, and how long to wait before each retry.
+const CALLBACK_RETRIES: [Duration; 3] = [
+    Duration::from_secs(0),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+/// Woken up whenever a job is queued or finishes, so workers don't wait for the next poll.
+static JOBS_CHANGED: SyncLazy<Notify> = SyncLazy::new(Notify::new);
+
+/// A client for one callback that connects to `addr` whatever `host` resolves to by the time
+/// it's sent, so the address `check_callback_url` vetted is the one that gets the request.
+fn callback_client(host: &str, addr: SocketAddr) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        // a redirect could point anywhere, including places `check_callback_url` refuses
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(host, addr)
+        .build()
+}
+
+fn db() -> Result<&'static PgPool, ApiError> {
+    PG_POOL
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))
+}
+
+fn db_error(e: DbError) -> ApiError {
+    tracing::warn!("transcription job query failed: {}", e);
+    ApiError::new(Status::InternalServerError, "database error")
+}
+
+/// Whether `ip` is somewhere a callback shouldn't be able to reach: loopback, private,
+/// link-local, or otherwise not on the public internet.
+fn is_internal_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_ipv4(ip),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if let [0, 0, 0, 0, 0, 0xffff, hi, lo] = segments {
+                return is_internal_ipv4(Ipv4Addr::new(
+                    (hi >> 8) as u8,
+                    hi as u8,
+                    (lo >> 8) as u8,
+                    lo as u8,
+                ));
+            }
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || segments[0] & 0xfe00 == 0xfc00
+                // link-local, fe80::/10
+                || segments[0] & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "this network", 0.0.0.0/8
+        || a == 0
+        // carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && b & 0xc0 == 64)
+}
+
+/// Checks that `url` is an http or https URL that only resolves to public addresses, so jobs
+/// can't be used to poke at the bot's own network. Returns the host and one of the addresses it
+/// resolved to, for `callback_client`.
+async fn check_callback_url(url: &str) -> Result<(String, SocketAddr), &'static str> {
+    let url = reqwest::Url::parse(url).map_err(|_| "callback_url isn't a valid URL")?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err("callback_url has to be an http or https URL");
+    }
+    let host = url.host_str().ok_or("callback_url has to have a host")?;
+    let port = url
+        .port_or_known_default()
+        .ok_or("callback_url has to have a port")?;
+
+    // IPv6 hosts come bracketed, which `lookup_host` doesn't understand
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = lookup_host((host, port))
+        .await
+        .map_err(|_| "callback_url's host couldn't be resolved")?
+        .collect();
+    if addrs.iter().any(|a| is_internal_ip(a.ip())) {
+        return Err("callback_url can't point at a private or local address");
+    }
+    match addrs.first() {
+        Some(addr) => Ok((host.to_string(), *addr)),
+        None => Err("callback_url's host couldn't be resolved"),
+    }
+}
+
+/// A transcription job, as returned by `/v1/jobs/<job_id>` and sent to callbacks.
+#[derive(Serialize, JsonSchema)]
+pub struct Job {
+    pub job_id: String,
+    /// One of `queued`, `running`, `done` or `failed`.
+    pub status: String,
+    /// How long the audio is, in milliseconds.
+    pub duration_ms: u64,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When the job will be deleted, once it's finished.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The `JobResult`, once the job is done.
+    #[schemars(with = "Option<JobResult>")]
+    pub result: Option<Value>,
+    /// What went wrong, if the job failed.
+    pub error: Option<String>,
+}
+
+/// One chunk of a job's audio, transcribed.
+#[derive(Serialize, JsonSchema)]
+pub struct Segment {
+    /// When this segment starts, in milliseconds from the start of the audio.
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub transcript: String,
+    pub confidence: f64,
+    /// Every token in `transcript`, timed from the start of the audio.
+    pub tokens: Vec<Token>,
+}
+
+/// What a job produced.
+#[derive(Serialize, JsonSchema)]
+pub struct JobResult {
+    /// Every segment's transcript, joined together.
+    pub transcript: String,
+    pub segments: Vec<Segment>,
+}
+
+/// Looks up `job_id`, if it belongs to `user_id`.
+async fn fetch_job(db: &PgPool, job_id: &str, user_id: u64) -> Result<Option<Job>, DbError> {
+    let retention = ChronoDuration::hours(i64::from(
+        unsafe { BotConfig::get().unwrap_unchecked() }.job_retention_hours(),
+    ));
+    Ok(fetch_job_record(db, job_id, user_id).await?.map(|r| Job {
+        job_id: r.job_id,
+        status: r.status.name().to_string(),
+        duration_ms: r.duration_ms,
+        created_at: r.created_at,
+        started_at: r.started_at,
+        finished_at: r.finished_at,
+        expires_at: r.finished_at.map(|f| f + retention),
+        result: r.result,
+        error: r.error,
+    }))
+}
+
+/// What's returned when a job is queued.
+#[derive(Serialize, JsonSchema)]
+pub struct NewJob {
+    pub job_id: String,
+    pub status: String,
+    pub duration_ms: u64,
+    /// What the callback is signed with, if there is one. This is the only time it's shown.
+    pub callback_secret: Option<String>,
+}
+
+/// Queues up the audio file in the request body to be transcribed. It can be in any format
+/// `/v1/transcribe` takes, and at most 512 MiB.
+///
+/// If `callback_url` is given, the finished job is POSTed to it with an
+/// `X-Scripty-Signature: sha256=<hex HMAC-SHA256 of the body>` header, keyed with the returned
+/// `callback_secret`.
+#[rocket::post("/v1/jobs?<callback_url>", data = "<audio>")]
+pub async fn create_job(
+    key: ApiKey,
+    callback_url: Option<String>,
+    audio: Data<'_>,
+) -> Result<(Status, Json<NewJob>), ApiError> {
+    key.require(ApiScope::Transcribe)?;
+
+    let max_secs = max_job_audio_secs(key.premium_level);
+    if max_secs == 0 {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "the speech to text API is only for premium subscribers",
+        ));
+    }
+    if let Some(url) = callback_url.as_deref() {
+        check_callback_url(url)
+            .await
+            .map_err(|e| ApiError::new(Status::UnprocessableEntity, e))?;
+    }
+
+    let db = db()?;
+    let pending = count_pending_jobs(db, key.user_id)
+        .await
+        .map_err(db_error)?;
+    if pending >= MAX_PENDING_JOBS {
+        return Err(ApiError::new(
+            Status::TooManyRequests,
+            format!(
+                "you can only have {} jobs waiting at once, wait for one to finish",
+                MAX_PENDING_JOBS
+            ),
+        ));
+    }
+
+    // 48kHz stereo is about as big as anyone will send, plus some room for the header
+    let limit = (max_secs * 48_000 * 2 * 2 + 4096).min(MAX_UPLOAD_BYTES);
+    let body = match audio.open(limit.bytes()).into_bytes().await {
+        Ok(b) if b.is_complete() => b.into_inner(),
+        Ok(_) if limit == MAX_UPLOAD_BYTES => {
+            return Err(ApiError::new(
+                Status::PayloadTooLarge,
+                format!(
+                    "uploads can be at most {} MiB, compress the audio (e.g. as Opus or MP3) first",
+                    MAX_UPLOAD_BYTES / 1024 / 1024
+                ),
+            ))
+        }
+        Ok(_) => {
+            return Err(ApiError::new(
+                Status::PayloadTooLarge,
+                format!("audio can be at most {} seconds long", max_secs),
+            ))
+        }
+        Err(e) => return Err(ApiError::new(Status::BadRequest, e.to_string())),
+    };
+
+    let decoded = decode_body(body, max_secs).await?;
+    let duration_ms = decoded.duration_ms();
+
+    let job_id = generate_job_id();
+    let callback_secret = callback_url.as_ref().map(|_| generate_callback_secret());
+    insert_job(
+        db,
+        &NewJobRecord {
+            job_id: &job_id,
+            key_id: key.key_id,
+            user_id: key.user_id,
+            audio: &decoded.samples,
+            sample_rate: SAMPLE_RATE,
+            duration_ms,
+            callback_url: callback_url.as_deref(),
+            callback_secret: callback_secret.as_deref(),
+        },
+    )
+    .await
+    .map_err(db_error)?;
+    JOBS_CHANGED.notify_one();
+
+    Ok((
+        Status::Accepted,
+        Json(NewJob {
+            job_id,
+            status: JobStatus::Queued.name().to_string(),
+            duration_ms,
+            callback_secret,
+        }),
+    ))
+}
+
+/// Gets one of your jobs.
+#[rocket::get("/v1/jobs/<job_id>")]
+pub async fn get_job(key: ApiKey, job_id: &str) -> Result<Json<Job>, ApiError> {
+    key.require(ApiScope::Transcribe)?;
+
+    fetch_job(db()?, job_id, key.user_id)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or_else(|| ApiError::new(Status::NotFound, "there's no job with that ID"))
+}
+
+/// Deletes one of your jobs, cancelling it if it hasn't finished.
+#[rocket::delete("/v1/jobs/<job_id>")]
+pub async fn delete_job(key: ApiKey, job_id: &str) -> Result<Status, ApiError> {
+    key.require(ApiScope::Transcribe)?;
+
+    if scripty_db::delete_job(db()?, job_id, key.user_id)
+        .await
+        .map_err(db_error)?
+    {
+        Ok(Status::NoContent)
+    } else {
+        Err(ApiError::new(
+            Status::NotFound,
+            "there's no job with that ID",
+        ))
+    }
+}
+
+/// Starts the workers that run queued jobs, and deletes expired ones.
+pub fn spawn_job_workers() {
+    register_task("transcription_jobs", POLL_INTERVAL);
+    tokio::spawn(async move {
+        let config = unsafe { BotConfig::get().unwrap_unchecked() };
+        let workers = Arc::new(Semaphore::new(config.job_workers().max(1)));
+        let mut interval = time::interval(POLL_INTERVAL);
+        let mut requeued = false;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = JOBS_CHANGED.notified() => {}
+            }
+            heartbeat("transcription_jobs", POLL_INTERVAL);
+            let db = match PG_POOL.get() {
+                Some(db) => db,
+                None => continue,
+            };
+
+            // nothing can be running yet, so anything marked as running was cut off by a restart
+            if !requeued {
+                match requeue_running_jobs(db).await {
+                    Ok(_) => requeued = true,
+                    Err(e) => {
+                        tracing::warn!("failed to requeue interrupted jobs: {}", e);
+                        continue;
+                    }
+                }
+            }
+
+            if let Err(e) = delete_expired_jobs(db, config.job_retention_hours()).await {
+                tracing::warn!("failed to delete expired jobs: {}", e);
+            }
+
+            while let Ok(permit) = Arc::clone(&workers).try_acquire_owned() {
+                match claim_job(db).await {
+                    Ok(Some(job)) => {
+                        tokio::spawn(async move {
+                            run_job(db, job).await;
+                            drop(permit);
+                            // there might be more waiting for this worker
+                            JOBS_CHANGED.notify_one();
+                        });
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::warn!("failed to claim a transcription job: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Transcribes `audio` a chunk at a time.
+async fn transcribe_chunks(audio: Vec<i16>, sample_rate: u32) -> Result<JobResult, String> {
+    let chunk_samples = (sample_rate as u64 * CHUNK_MS / 1000).max(1) as usize;
+    let mut segments = Vec::with_capacity(audio.len() / chunk_samples + 1);
+
+    for (i, chunk) in audio.chunks(chunk_samples).enumerate() {
+        let start_ms = i as u64 * CHUNK_MS;
+        let duration_ms = chunk.len() as u64 * 1000 / sample_rate.max(1) as u64;
+        let metadata = run_stt_mono(chunk.to_vec(), sample_rate, 1, model().await)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let best = match metadata.transcripts().first() {
+            Some(t) => t,
+            None => continue,
+        };
+        let tokens: Vec<Token> = best
+            .tokens()
+            .iter()
+            .filter_map(|token| {
+                Some(Token {
+                    text: token.text().ok()?.to_string(),
+                    start_ms: start_ms as u32 + token.timestep() * 20,
+                })
+            })
+            .collect();
+        let transcript: String = tokens.iter().map(|t| t.text.as_str()).collect();
+        if transcript.trim().is_empty() {
+            continue;
+        }
+
+        segments.push(Segment {
+            start_ms,
+            duration_ms,
+            transcript,
+            confidence: best.confidence(),
+            tokens,
+        });
+    }
+
+    Ok(JobResult {
+        transcript: segments
+            .iter()
+            .map(|s| s.transcript.trim())
+            .collect::<Vec<_>>()
+            .join(" "),
+        segments,
+    })
+}
+
+async fn run_job(db: &PgPool, job: ClaimedJob) {
+    let (status, result, error) = match transcribe_chunks(job.audio, job.sample_rate).await {
+        Ok(result) => {
+            record_audio(job.key_id, job.duration_ms);
+            match serde_json::to_value(result) {
+                Ok(v) => (JobStatus::Done, Some(v), None),
+                Err(e) => (JobStatus::Failed, None, Some(e.to_string())),
+            }
+        }
+        Err(e) => {
+            tracing::warn!("transcription job {} failed: {}", job.job_id, e);
+            (
+                JobStatus::Failed,
+                None,
+                Some(format!("speech to text failed: {}", e)),
+            )
+        }
+    };
+
+    if let Err(e) = finish_job(db, &job.job_id, status, result, error.as_deref()).await {
+        tracing::warn!("failed to save transcription job {}: {}", job.job_id, e);
+        return;
+    }
+
+    if let (Some(url), Some(secret)) = (job.callback_url, job.callback_secret) {
+        send_callback(db, &job.job_id, job.user_id, &url, &secret).await;
+    }
+}
+
+/// POSTs the finished job to its callback URL, retrying a few times if that fails.
+async fn send_callback(db: &PgPool, job_id: &str, user_id: u64, url: &str, secret: &str) {
+    let body = match fetch_job(db, job_id, user_id).await {
+        Ok(Some(job)) => match serde_json::to_vec(&job) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::warn!("failed to serialize job {}: {}", job_id, e);
+                return;
+            }
+        },
+        // deleted while it was running
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("failed to fetch job {} for its callback: {}", job_id, e);
+            return;
+        }
+    };
+
+    // the host's DNS could have changed to point somewhere internal since the job was queued,
+    // and could change again between retries, so every attempt goes to the address checked here
+    let client = match check_callback_url(url).await {
+        Ok((host, addr)) => match callback_client(&host, addr) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!(
+                    "failed to build the callback client for job {}: {}",
+                    job_id,
+                    e
+                );
+                return;
+            }
+        },
+        Err(e) => {
+            tracing::info!("not sending the callback for job {}: {}", job_id, e);
+            return;
+        }
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(&body);
+    let signature = format!("sha256={:x}", mac.finalize().into_bytes());
+
+    for delay in CALLBACK_RETRIES.iter() {
+        time::sleep(*delay).await;
+        match client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Scripty-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await
+        {
+            Ok(r) if r.status().is_success() => return,
+            Ok(r) => tracing::info!("callback for job {} returned {}", job_id, r.status()),
+            Err(e) => tracing::info!("callback for job {} failed: {}", job_id, e),
+        }
+    }
+    tracing::info!("giving up on the callback for job {}", job_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_refused() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(
+                is_internal_ip(ip.parse().unwrap()),
+                "{} should be internal",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        for ip in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(
+                !is_internal_ip(ip.parse().unwrap()),
+                "{} should be public",
+                ip
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn callback_urls_are_checked() {
+        assert!(check_callback_url("ftp://1.1.1.1/").await.is_err());
+        assert!(check_callback_url("not a url").await.is_err());
+        assert!(check_callback_url("http://127.0.0.1:8080/hook")
+            .await
+            .is_err());
+        assert!(check_callback_url("http://[::1]/hook").await.is_err());
+        assert!(check_callback_url("http://localhost/hook").await.is_err());
+        assert_eq!(
+            check_callback_url("https://1.1.1.1:8443/hook").await,
+            Ok(("1.1.1.1".to_string(), "1.1.1.1:8443".parse().unwrap()))
+        );
+    }
+}
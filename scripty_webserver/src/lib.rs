@@ -1,6 +1,33 @@
 #![feature(once_cell)]
 #![feature(option_result_unwrap_unchecked)]
+#![feature(slice_as_chunks)]
 
+mod admin;
+mod auth;
+mod cors;
+mod error;
+mod health;
+mod jobs;
+mod live;
+mod openapi;
+mod rate_limit;
 mod server;
+mod sponsors;
+mod stats;
+mod stream;
+mod stt;
 
+pub use admin::*;
+pub use auth::*;
+pub use cors::*;
+pub use error::*;
+pub use health::*;
+pub use jobs::*;
+pub use live::*;
+pub use openapi::*;
+pub use rate_limit::*;
 pub use server::*;
+pub use sponsors::*;
+pub use stats::*;
+pub use stream::*;
+pub use stt::*;
@@ -0,0 +1,72 @@
+use crate::ApiError;
+use rocket::{
+    http::Status,
+    response::{
+        content::Html,
+        stream::{Event, EventStream},
+    },
+    tokio::{select, sync::broadcast::error::RecvError},
+    Shutdown,
+};
+use scripty_audio::subscribe_live_feed;
+use scripty_db::{fetch_guild_config, hash_api_key, PG_POOL};
+use serenity::model::id::GuildId;
+
+/// Checks `token` is the current live feed token of `guild_id`.
+async fn check_live_token(guild_id: u64, token: Option<&str>) -> Result<(), ApiError> {
+    let invalid = || ApiError::new(Status::Unauthorized, "a valid live feed token is required");
+    let token = token.ok_or_else(invalid)?;
+    let db = PG_POOL
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))?;
+
+    let hash = fetch_guild_config(db, GuildId(guild_id))
+        .await
+        .map_err(|e| {
+            tracing::warn!("failed to look up live feed token: {}", e);
+            ApiError::new(Status::ServiceUnavailable, "couldn't check your token")
+        })?
+        .and_then(|g| g.live_token_hash);
+
+    match hash {
+        Some(hash) if hash == hash_api_key(token) => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+/// Streams everything transcribed in a guild as Server-Sent Events, one `utterance` event each.
+///
+/// The token is a query parameter rather than a header, since `EventSource` can't set headers.
+#[rocket::get("/v1/live/<guild_id>?<token>")]
+pub async fn live_feed(
+    guild_id: u64,
+    token: Option<&str>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], ApiError> {
+    check_live_token(guild_id, token).await?;
+
+    let mut feed = subscribe_live_feed(GuildId(guild_id));
+    Ok(EventStream! {
+        loop {
+            let utterance = select! {
+                u = feed.recv() => match u {
+                    Ok(u) => u,
+                    // a slow client missing a few lines is better than it falling further behind
+                    Err(RecvError::Lagged(_)) => continue,
+                    // the token changed
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            yield Event::json(&utterance).event("utterance");
+        }
+    })
+}
+
+/// A caption overlay showing a guild's live feed, meant to be added to OBS as a browser source.
+///
+/// The page takes the same `guild` and `token` the live feed does, as query parameters.
+#[rocket::get("/overlay")]
+pub fn overlay() -> Html<&'static str> {
+    Html(include_str!("../static/overlay.html"))
+}
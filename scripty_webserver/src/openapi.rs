@@ -0,0 +1,601 @@
+//! The OpenAPI 3 description of the web API, served at `/openapi.json`.
+//!
+//! Paths and methods are read from the routes the server actually mounts, and the schemas are
+//! generated from the types the handlers take and return. What's left, like which guard a
+//! route has, is written down in `OPERATIONS`, and `check_operations` makes sure that and the
+//! routes agree.
+use crate::{
+    routes, ActiveCall, AdminGuild, ChannelWebhook, ErrorBody, GuildUpdate, HealthReport, Job,
+    LeaveResult, NewJob, SponsorWebhookResult, SponsorshipEvent, Stats, Transcription, UserPremium,
+};
+use rocket::{http::Method, serde::json::Json, Route};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+use std::lazy::SyncLazy;
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+/// What a request or response body contains.
+enum Content {
+    Json(SchemaFn),
+    /// An audio file in any format `decode_audio` takes.
+    Audio,
+    Text,
+    Html,
+    EventStream,
+}
+
+/// What a route needs to be called.
+enum Auth {
+    None,
+    ApiKey,
+    Admin,
+    /// The `metrics_token` from the `[web]` config, if there is one.
+    Metrics,
+}
+
+/// A path or query parameter.
+struct Param {
+    name: &'static str,
+    /// The JSON type of the parameter, like `integer`.
+    kind: &'static str,
+    description: &'static str,
+}
+
+/// Everything about a route that can't be read off of it.
+struct Operation {
+    method: Method,
+    /// The route's URI as it's written in its attribute.
+    uri: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    auth: Auth,
+    params: &'static [Param],
+    body: Option<Content>,
+    responses: &'static [(u16, &'static str, Option<Content>)],
+}
+
+const GUILD_ID: Param = Param {
+    name: "guild_id",
+    kind: "integer",
+    description: "The guild's ID.",
+};
+
+const JOB_ID: Param = Param {
+    name: "job_id",
+    kind: "string",
+    description: "The job's ID, starting with `job_`.",
+};
+
+static OPERATIONS: &[Operation] = &[
+    Operation {
+        method: Method::Get,
+        uri: "/metrics",
+        tag: "meta",
+        summary: "Prometheus metrics",
+        auth: Auth::Metrics,
+        params: &[],
+        body: None,
+        responses: &[(
+            200,
+            "The metrics, in Prometheus' text format.",
+            Some(Content::Text),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/",
+        tag: "meta",
+        summary: "Nothing to see here",
+        auth: Auth::None,
+        params: &[],
+        body: None,
+        responses: &[(
+            200,
+            "A note saying there's nothing here.",
+            Some(Content::Text),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/openapi.json",
+        tag: "meta",
+        summary: "This document",
+        auth: Auth::None,
+        params: &[],
+        body: None,
+        responses: &[(200, "The OpenAPI document.", None)],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/healthz",
+        tag: "health",
+        summary: "Liveness check, failing when a background task has stopped running",
+        auth: Auth::None,
+        params: &[],
+        body: None,
+        responses: &[
+            (200, "Alive.", Some(Content::Json(schema::<HealthReport>))),
+            (
+                503,
+                "Something is wedged.",
+                Some(Content::Json(schema::<HealthReport>)),
+            ),
+        ],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/readyz",
+        tag: "health",
+        summary: "Readiness check, failing until the database, model and every shard are up",
+        auth: Auth::None,
+        params: &[],
+        body: None,
+        responses: &[
+            (200, "Ready.", Some(Content::Json(schema::<HealthReport>))),
+            (
+                503,
+                "Not ready yet.",
+                Some(Content::Json(schema::<HealthReport>)),
+            ),
+        ],
+    },
+    Operation {
+        method: Method::Post,
+        uri: "/v1/transcribe",
+        tag: "transcription",
+        summary: "Transcribe an audio file",
+        auth: Auth::ApiKey,
+        params: &[],
+        body: Some(Content::Audio),
+        responses: &[(
+            200,
+            "The transcript.",
+            Some(Content::Json(schema::<Transcription>)),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/v1/stats",
+        tag: "stats",
+        summary: "The bot's current stats",
+        auth: Auth::ApiKey,
+        params: &[],
+        body: None,
+        responses: &[(200, "The stats.", Some(Content::Json(schema::<Stats>)))],
+    },
+    Operation {
+        method: Method::Post,
+        uri: "/v1/jobs?<callback_url>",
+        tag: "jobs",
+        summary: "Queue an audio file too long for `/v1/transcribe`",
+        auth: Auth::ApiKey,
+        params: &[Param {
+            name: "callback_url",
+            kind: "string",
+            description: "Where to POST the job once it finishes. The body is signed with the \
+            returned `callback_secret`, in the `X-Scripty-Signature` header.",
+        }],
+        body: Some(Content::Audio),
+        responses: &[(
+            202,
+            "The job was queued.",
+            Some(Content::Json(schema::<NewJob>)),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/v1/jobs/<job_id>",
+        tag: "jobs",
+        summary: "Check on a job",
+        auth: Auth::ApiKey,
+        params: &[JOB_ID],
+        body: None,
+        responses: &[(200, "The job.", Some(Content::Json(schema::<Job>)))],
+    },
+    Operation {
+        method: Method::Delete,
+        uri: "/v1/jobs/<job_id>",
+        tag: "jobs",
+        summary: "Cancel or delete a job",
+        auth: Auth::ApiKey,
+        params: &[JOB_ID],
+        body: None,
+        responses: &[(204, "The job is gone.", None)],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/v1/live/<guild_id>?<token>",
+        tag: "live",
+        summary: "Everything transcribed in a guild, as it's transcribed",
+        auth: Auth::None,
+        params: &[
+            GUILD_ID,
+            Param {
+                name: "token",
+                kind: "string",
+                description: "The guild's live feed token, from the `live_feed` command.",
+            },
+        ],
+        body: None,
+        responses: &[(
+            200,
+            "Server-Sent Events, with one `utterance` event per line transcribed.",
+            Some(Content::EventStream),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/overlay",
+        tag: "live",
+        summary: "A caption overlay for a guild's live feed, reading `guild` and `token` from \
+        its query string in the browser",
+        auth: Auth::None,
+        params: &[],
+        body: None,
+        responses: &[(200, "The overlay page.", Some(Content::Html))],
+    },
+    Operation {
+        method: Method::Post,
+        uri: "/v1/webhooks/sponsors",
+        tag: "webhooks",
+        summary: "GitHub Sponsors webhook, signed in `X-Hub-Signature-256` with the config's \
+        `sponsor_webhook_secret`",
+        auth: Auth::None,
+        params: &[],
+        body: Some(Content::Json(schema::<SponsorshipEvent>)),
+        responses: &[(
+            200,
+            "What was done with the delivery.",
+            Some(Content::Json(schema::<SponsorWebhookResult>)),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/v1/admin/calls",
+        tag: "admin",
+        summary: "Every voice call the bot is in",
+        auth: Auth::Admin,
+        params: &[],
+        body: None,
+        responses: &[(
+            200,
+            "The calls.",
+            Some(Content::Json(schema::<Vec<ActiveCall>>)),
+        )],
+    },
+    Operation {
+        method: Method::Post,
+        uri: "/v1/admin/guilds/<guild_id>/leave",
+        tag: "admin",
+        summary: "Leave every voice call in a guild",
+        auth: Auth::Admin,
+        params: &[GUILD_ID],
+        body: None,
+        responses: &[(
+            200,
+            "How many calls were left.",
+            Some(Content::Json(schema::<LeaveResult>)),
+        )],
+    },
+    Operation {
+        method: Method::Post,
+        uri: "/v1/admin/guilds/<guild_id>/rejoin",
+        tag: "admin",
+        summary: "Rejoin a guild's bound voice chats",
+        auth: Auth::Admin,
+        params: &[GUILD_ID],
+        body: None,
+        responses: &[(
+            200,
+            "The guild's voice calls, after rejoining.",
+            Some(Content::Json(schema::<Vec<ActiveCall>>)),
+        )],
+    },
+    Operation {
+        method: Method::Get,
+        uri: "/v1/admin/guilds/<guild_id>",
+        tag: "admin",
+        summary: "A guild's settings",
+        auth: Auth::Admin,
+        params: &[GUILD_ID],
+        body: None,
+        responses: &[(200, "The guild.", Some(Content::Json(schema::<AdminGuild>)))],
+    },
+    Operation {
+        method: Method::Patch,
+        uri: "/v1/admin/guilds/<guild_id>",
+        tag: "admin",
+        summary: "Edit a guild's settings",
+        auth: Auth::Admin,
+        params: &[GUILD_ID],
+        body: Some(Content::Json(schema::<GuildUpdate>)),
+        responses: &[(
+            200,
+            "The guild, after the edit.",
+            Some(Content::Json(schema::<AdminGuild>)),
+        )],
+    },
+    Operation {
+        method: Method::Put,
+        uri: "/v1/admin/channels/<channel_id>",
+        tag: "admin",
+        summary: "Set the webhook a channel's transcripts are sent through",
+        auth: Auth::Admin,
+        params: &[Param {
+            name: "channel_id",
+            kind: "integer",
+            description: "The channel's ID.",
+        }],
+        body: Some(Content::Json(schema::<ChannelWebhook>)),
+        responses: &[(204, "The webhook was set.", None)],
+    },
+    Operation {
+        method: Method::Delete,
+        uri: "/v1/admin/channels/<channel_id>",
+        tag: "admin",
+        summary: "Forget a channel's webhook",
+        auth: Auth::Admin,
+        params: &[Param {
+            name: "channel_id",
+            kind: "integer",
+            description: "The channel's ID.",
+        }],
+        body: None,
+        responses: &[(204, "The webhook was forgotten.", None)],
+    },
+    Operation {
+        method: Method::Put,
+        uri: "/v1/admin/users/<user_id>/premium",
+        tag: "admin",
+        summary: "Set a user's premium level",
+        auth: Auth::Admin,
+        params: &[Param {
+            name: "user_id",
+            kind: "integer",
+            description: "The user's ID.",
+        }],
+        body: Some(Content::Json(schema::<UserPremium>)),
+        responses: &[(
+            200,
+            "The user's premium.",
+            Some(Content::Json(schema::<UserPremium>)),
+        )],
+    },
+];
+
+/// Gets the parameter names in part of a route URI, like `job_id` in `/v1/jobs/<job_id>`.
+fn uri_params(part: &str) -> Vec<&str> {
+    part.split(|c| c == '/' || c == '&')
+        .filter_map(|seg| seg.strip_prefix('<')?.strip_suffix('>'))
+        .map(|name| name.trim_end_matches(".."))
+        .collect()
+}
+
+/// Splits a route URI into its path and the names of its path and query parameters.
+fn split_uri(uri: &str) -> (&str, Vec<&str>, Vec<&str>) {
+    let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+    (path, uri_params(path), uri_params(query))
+}
+
+/// The URI of a route, as it's written in its attribute.
+fn route_uri(route: &Route) -> String {
+    match route.uri.query() {
+        Some(query) => format!("{}?{}", route.uri.path(), query),
+        None => route.uri.path().to_string(),
+    }
+}
+
+/// Finds every difference between the mounted routes and `OPERATIONS`.
+pub(crate) fn check_operations(routes: &[Route]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for route in routes {
+        let uri = route_uri(route);
+        if !OPERATIONS
+            .iter()
+            .any(|op| op.method == route.method && op.uri == uri)
+        {
+            problems.push(format!("{} {} isn't documented", route.method, uri));
+        }
+    }
+
+    for op in OPERATIONS {
+        if !routes
+            .iter()
+            .any(|r| r.method == op.method && route_uri(r) == op.uri)
+        {
+            problems.push(format!("{} {} isn't a route", op.method, op.uri));
+        }
+
+        let (_, path_params, query_params) = split_uri(op.uri);
+        for name in path_params.iter().chain(&query_params) {
+            if !op.params.iter().any(|p| p.name == *name) {
+                problems.push(format!(
+                    "{} {} doesn't describe `{}`",
+                    op.method, op.uri, name
+                ));
+            }
+        }
+        for param in op.params {
+            if !path_params.contains(&param.name) && !query_params.contains(&param.name) {
+                problems.push(format!(
+                    "{} {} describes `{}`, which it doesn't take",
+                    op.method, op.uri, param.name
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+fn content(content: &Content, gen: &mut SchemaGenerator) -> Value {
+    match content {
+        Content::Json(schema) => json!({ "application/json": { "schema": schema(gen) } }),
+        Content::Audio => json!({
+            "audio/*": { "schema": { "type": "string", "format": "binary" } },
+            "video/webm": { "schema": { "type": "string", "format": "binary" } },
+        }),
+        Content::Text => json!({ "text/plain": { "schema": { "type": "string" } } }),
+        Content::Html => json!({ "text/html": { "schema": { "type": "string" } } }),
+        Content::EventStream => {
+            json!({ "text/event-stream": { "schema": { "type": "string" } } })
+        }
+    }
+}
+
+fn operation(op: &Operation, route: &Route, gen: &mut SchemaGenerator) -> Value {
+    let (_, path_params, _) = split_uri(op.uri);
+    let params: Vec<Value> = op
+        .params
+        .iter()
+        .map(|p| {
+            let path = path_params.contains(&p.name);
+            json!({
+                "name": p.name,
+                "in": if path { "path" } else { "query" },
+                "required": path,
+                "description": p.description,
+                "schema": { "type": p.kind },
+            })
+        })
+        .collect();
+
+    let mut responses = Map::new();
+    for (status, description, body) in op.responses {
+        let mut response = json!({ "description": description });
+        if let Some(body) = body {
+            response["content"] = content(body, gen);
+        }
+        responses.insert(status.to_string(), response);
+    }
+    if op.uri.starts_with("/v1/") {
+        responses.insert(
+            "default".to_string(),
+            json!({
+                "description": "Something went wrong.",
+                "content": content(&Content::Json(schema::<ErrorBody>), gen),
+            }),
+        );
+    }
+
+    let mut value = json!({
+        "tags": [op.tag],
+        "summary": op.summary,
+        "operationId": route.name.as_deref(),
+        "parameters": params,
+        "responses": responses,
+    });
+    if let Some(body) = &op.body {
+        value["requestBody"] = json!({ "required": true, "content": content(body, gen) });
+    }
+    match op.auth {
+        Auth::None => {}
+        Auth::ApiKey => value["security"] = json!([{ "apiKey": [] }]),
+        Auth::Admin => value["security"] = json!([{ "adminToken": [] }]),
+        // an empty requirement means the token is optional, which is up to the config
+        Auth::Metrics => value["security"] = json!([{ "metricsToken": [] }, {}]),
+    }
+    value
+}
+
+/// Builds the OpenAPI document for `routes`. Routes missing from `OPERATIONS` are left out.
+pub(crate) fn build_openapi(routes: &[Route]) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+
+    let mut paths = Map::new();
+    for route in routes {
+        let uri = route_uri(route);
+        let op = match OPERATIONS
+            .iter()
+            .find(|op| op.method == route.method && op.uri == uri)
+        {
+            Some(op) => op,
+            None => continue,
+        };
+
+        let (path, _, _) = split_uri(op.uri);
+        let path = path.replace('<', "{").replace('>', "}");
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[op.method.as_str().to_lowercase()] = operation(op, route, &mut gen);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Scripty",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Speech to text, stats, and administration for Scripty.",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+            "securitySchemes": {
+                "apiKey": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "An API key, from the `getkey` command.",
+                },
+                "adminToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "The `admin_token` from the bot's config.",
+                },
+                "metricsToken": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "The `metrics_token` from the `[web]` config, if it has one.",
+                },
+            },
+        },
+    })
+}
+
+static OPENAPI: SyncLazy<Value> = SyncLazy::new(|| build_openapi(&routes()));
+
+/// Returns this document.
+#[rocket::get("/openapi.json")]
+pub fn openapi_json() -> Json<&'static Value> {
+    Json(&OPENAPI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_match_routes() {
+        let problems = check_operations(&routes());
+        assert!(
+            problems.is_empty(),
+            "the OpenAPI document is out of date:\n{}",
+            problems.join("\n")
+        );
+    }
+
+    #[test]
+    fn every_route_is_in_the_document() {
+        let routes = routes();
+        let doc = build_openapi(&routes);
+        for route in &routes {
+            let uri = route_uri(route);
+            let (path, _, _) = split_uri(&uri);
+            let path = path.replace('<', "{").replace('>', "}");
+            assert!(
+                doc["paths"][&path][route.method.as_str().to_lowercase()].is_object(),
+                "{} {} is missing",
+                route.method,
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Token bucket rate limits and usage metering for API keys.
+//!
+//! Buckets and usage are kept in memory so requests don't wait on the DB, and saved every minute
+//! by `spawn_persist_task`. If the bot stops between saves, up to a minute of usage is lost.
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use dashmap::DashMap;
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    Request, Response,
+};
+use scripty_db::{add_api_key_usage, save_rate_limit, ApiRateLimit, PG_POOL};
+use scripty_metrics::{heartbeat, register_task};
+use std::{lazy::SyncLazy, time::Duration};
+use tokio::time;
+
+/// How often buckets and usage are saved to the DB.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Buckets that haven't been touched in this long are dropped from memory once saved. They're
+/// loaded back from the DB on the key's next request.
+const IDLE_BUCKET_HOURS: i64 = 1;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    /// Whether this bucket changed since it was last saved.
+    dirty: bool,
+}
+
+/// Usage that hasn't been saved to the DB yet.
+#[derive(Default)]
+struct PendingUsage {
+    requests: u64,
+    audio_ms: u64,
+}
+
+static BUCKETS: SyncLazy<DashMap<i64, Bucket>> = SyncLazy::new(DashMap::new);
+static PENDING_USAGE: SyncLazy<DashMap<i64, PendingUsage>> = SyncLazy::new(DashMap::new);
+
+/// Where a key's bucket stood after a request, sent back in `X-RateLimit-*` headers.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request will be allowed, if this one wasn't.
+    pub retry_after: Option<u64>,
+}
+
+/// Takes one request out of `key_id`'s bucket.
+///
+/// `saved` is the bucket as last saved in the `api_keys` table, used if it isn't in memory. Returns
+/// `Err` if the bucket is empty, in which case the request should be turned away.
+pub fn take_request(
+    key_id: i64,
+    limit: ApiRateLimit,
+    saved: Option<(f64, DateTime<Utc>)>,
+) -> Result<RateLimitStatus, RateLimitStatus> {
+    let now = Utc::now();
+    let burst = f64::from(limit.burst);
+    let per_sec = f64::from(limit.per_minute) / 60.0;
+
+    let mut bucket = BUCKETS.entry(key_id).or_insert_with(|| {
+        let (tokens, updated_at) = saved.unwrap_or((burst, now));
+        Bucket {
+            tokens,
+            updated_at,
+            dirty: false,
+        }
+    });
+    let elapsed = (now - bucket.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+    // capping here also shrinks the bucket of someone whose premium ran out
+    bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+    bucket.updated_at = now;
+    bucket.dirty = true;
+
+    let allowed = bucket.tokens >= 1.0;
+    if allowed {
+        bucket.tokens -= 1.0;
+    }
+    let secs_until = |tokens: f64| (tokens.max(0.0) / per_sec).ceil() as u64;
+    let status = RateLimitStatus {
+        limit: limit.burst,
+        remaining: bucket.tokens.floor() as u32,
+        reset_secs: secs_until(burst - bucket.tokens),
+        retry_after: if allowed {
+            None
+        } else {
+            Some(secs_until(1.0 - bucket.tokens))
+        },
+    };
+    drop(bucket);
+
+    if allowed {
+        PENDING_USAGE.entry(key_id).or_default().requests += 1;
+        Ok(status)
+    } else {
+        Err(status)
+    }
+}
+
+/// Records that `key_id` had `audio_ms` milliseconds of audio transcribed.
+pub fn record_audio(key_id: i64, audio_ms: u64) {
+    PENDING_USAGE.entry(key_id).or_default().audio_ms += audio_ms;
+}
+
+/// Saves buckets and usage every `PERSIST_INTERVAL`.
+pub fn spawn_persist_task() {
+    register_task("api_rate_limit_persist", PERSIST_INTERVAL);
+    tokio::spawn(async move {
+        let mut interval = time::interval(PERSIST_INTERVAL);
+        loop {
+            interval.tick().await;
+            persist().await;
+            heartbeat("api_rate_limit_persist", PERSIST_INTERVAL);
+        }
+    });
+}
+
+async fn persist() {
+    let db = match PG_POOL.get() {
+        Some(db) => db,
+        None => return,
+    };
+
+    let key_ids: Vec<i64> = PENDING_USAGE.iter().map(|u| *u.key()).collect();
+    for key_id in key_ids {
+        let usage = match PENDING_USAGE.remove(&key_id) {
+            Some((_, u)) => u,
+            None => continue,
+        };
+        if let Err(e) = add_api_key_usage(db, key_id, usage.requests, usage.audio_ms).await {
+            tracing::warn!("failed to save usage of API key {}: {}", key_id, e);
+            // try again next time
+            let mut pending = PENDING_USAGE.entry(key_id).or_default();
+            pending.requests += usage.requests;
+            pending.audio_ms += usage.audio_ms;
+        }
+    }
+
+    let mut dirty = Vec::new();
+    for mut bucket in BUCKETS.iter_mut() {
+        if bucket.dirty {
+            bucket.dirty = false;
+            dirty.push((*bucket.key(), bucket.tokens, bucket.updated_at));
+        }
+    }
+    for (key_id, tokens, updated_at) in dirty {
+        if let Err(e) = save_rate_limit(db, key_id, tokens, updated_at).await {
+            tracing::warn!("failed to save rate limit of API key {}: {}", key_id, e);
+            if let Some(mut bucket) = BUCKETS.get_mut(&key_id) {
+                bucket.dirty = true;
+            }
+        }
+    }
+
+    let idle_since = Utc::now() - ChronoDuration::hours(IDLE_BUCKET_HOURS);
+    BUCKETS.retain(|_, bucket| bucket.dirty || bucket.updated_at > idle_since);
+}
+
+/// Adds `X-RateLimit-*` headers to every response to a request made with an API key, and
+/// `Retry-After` if it was turned away.
+pub struct RateLimitHeaders;
+
+#[rocket::async_trait]
+impl Fairing for RateLimitHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limit headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let status = match request.local_cache(|| None::<RateLimitStatus>) {
+            Some(s) => s,
+            None => return,
+        };
+        response.set_header(Header::new("X-RateLimit-Limit", status.limit.to_string()));
+        response.set_header(Header::new(
+            "X-RateLimit-Remaining",
+            status.remaining.to_string(),
+        ));
+        response.set_header(Header::new(
+            "X-RateLimit-Reset",
+            status.reset_secs.to_string(),
+        ));
+        if let Some(retry_after) = status.retry_after {
+            response.set_header(Header::new("Retry-After", retry_after.to_string()));
+        }
+    }
+}
@@ -1,75 +1,116 @@
-use rocket::Shutdown;
+use crate::{
+    admin_calls, admin_delete_channel, admin_guild, admin_leave, admin_rejoin, admin_set_channel,
+    admin_set_user_premium, admin_update_guild, create_job, default_catcher, delete_job, get_job,
+    healthz, live_feed, openapi_json, overlay, readyz, serve_streams, spawn_job_workers,
+    spawn_persist_task, sponsor_webhook, stats, transcribe, Cors, RateLimitHeaders,
+};
+use rocket::{
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest},
+    Request, Route, Shutdown,
+};
+use scripty_audio_utils::model;
+use scripty_config::BotConfig;
+use scripty_db::hash_api_key;
 use scripty_metrics::serialize_metrics;
-use tokio::sync::oneshot::{self, Receiver};
-/*
-use rocket::http::Status;
-use rocket::outcome::Outcome;
-use rocket::{Request, request};
-use rocket::request::FromRequest;
-*/
 
-#[rocket::get("/metrics")]
-async fn metrics() -> Vec<u8> {
-    serialize_metrics()
-}
-
-/*
-TODO: actually implement authorization and a speech to text API
-
-struct Token(String);
-
-#[derive(Debug)]
-enum ApiTokenError {
-    Missing,
-    Invalid,
-}
+/// Proof a request was made with the `metrics_token` from the `[web]` config, if there is one.
+struct MetricsToken;
 
 #[rocket::async_trait]
-impl FromRequest<'r> for Token {
-    type Error = ApiTokenError;
+impl<'r> FromRequest<'r> for MetricsToken {
+    type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let token = request.headers().get_one("token");
-        match token {
-            Some(token) => {
-                Outcome::Success(Token(token.to_string()))
-            }
-            None => Outcome::Failure((Status::Unauthorized, ApiTokenError::Missing)),
+        let expected = match BotConfig::get().and_then(|c| c.web().metrics_token.as_deref()) {
+            Some(t) => t,
+            None => return Outcome::Success(MetricsToken),
+        };
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        match given {
+            Some(t) if hash_api_key(t) == hash_api_key(expected) => Outcome::Success(MetricsToken),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
         }
     }
 }
 
-#[rocket::post("/run-stt")]
-async fn run_stt(token: Token) -> (Status, String) {
-
-    (Status::InternalServerError, "Something went wrong!".to_string())
+#[rocket::get("/metrics")]
+async fn metrics(_token: MetricsToken) -> Vec<u8> {
+    serialize_metrics()
 }
-*/
 
 #[rocket::get("/")]
 async fn root() -> &'static str {
     "This server doesn't have any content. Go away. *waves you away*"
 }
 
-async fn _start(tx: oneshot::Sender<Shutdown>) {
+/// Every route the server has.
+pub fn routes() -> Vec<Route> {
     #[allow(clippy::nonstandard_macro_braces)] // originates in a macro, nothing i can do
-    let r = rocket::build()
-        .mount("/", rocket::routes![metrics])
-        .mount("/", rocket::routes![root])
-        .ignite()
-        .await
-        .expect("failed to ignite server");
+    let routes = [
+        rocket::routes![metrics, root, openapi_json],
+        rocket::routes![healthz, readyz],
+        rocket::routes![transcribe, stats],
+        rocket::routes![create_job, get_job, delete_job],
+        rocket::routes![live_feed, overlay],
+        rocket::routes![sponsor_webhook],
+        rocket::routes![
+            admin_calls,
+            admin_leave,
+            admin_rejoin,
+            admin_guild,
+            admin_update_guild,
+            admin_set_channel,
+            admin_delete_channel,
+            admin_set_user_premium
+        ],
+    ];
+    routes.concat()
+}
 
-    tx.send(r.shutdown())
-        .expect("receiver was dropped: don't do that!");
+/// Starts the web server, and everything behind the API. Whether it should be started at all is
+/// up to the caller, going by the `[web]` config.
+///
+/// Fails if the `[web]` config is invalid, without starting anything.
+pub async fn start() -> Result<Shutdown, String> {
+    let web = BotConfig::get()
+        .map(|c| c.web().clone())
+        .unwrap_or_default();
+    web.validate()?;
+    let mut figment = rocket::Config::figment()
+        .merge(("address", &web.address))
+        .merge(("port", web.port));
+    if let Some(tls) = &web.tls {
+        figment = figment
+            .merge(("tls.certs", &tls.certs))
+            .merge(("tls.key", &tls.key));
+    }
 
-    if let Err(e) = r.launch().await {
-        tracing::warn!("error while starting metrics server: {}", e)
-    };
-}
+    #[allow(clippy::nonstandard_macro_braces)] // originates in a macro, nothing i can do
+    let r = rocket::custom(figment)
+        .mount("/", routes())
+        .register("/", rocket::catchers![default_catcher])
+        .attach(RateLimitHeaders)
+        .attach(Cors)
+        .ignite()
+        .await
+        .map_err(|e| format!("failed to ignite server: {}", e))?;
+    let shutdown = r.shutdown();
 
-pub fn start() -> Receiver<Shutdown> {
-    let (tx, rx) = oneshot::channel();
-    tokio::spawn(_start(tx));
-    rx
+    tokio::spawn(async move {
+        if let Err(e) = r.launch().await {
+            tracing::warn!("error while starting metrics server: {}", e)
+        };
+    });
+    spawn_persist_task();
+    spawn_job_workers();
+    tokio::spawn(serve_streams());
+    // load the model now rather than on the first request, so `/readyz` can wait for it
+    tokio::spawn(model());
+    Ok(shutdown)
 }
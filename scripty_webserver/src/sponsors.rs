@@ -0,0 +1,498 @@
+//! The GitHub Sponsors webhook, which keeps sponsors' premium up to date without anyone having
+//! to step in.
+//!
+//! GitHub signs each delivery with the configured secret in `X-Hub-Signature-256`, and gives it
+//! an ID in `X-GitHub-Delivery`. Deliveries are only applied once, so GitHub redelivering one is
+//! harmless. Sponsors link their GitHub login to their Discord account with `link_github`, and
+//! until they do their sponsorship is only written down.
+use crate::ApiError;
+use hmac::{Hmac, Mac, NewMac};
+use rocket::{
+    data::{Data, ToByteUnit},
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest},
+    serde::json::Json,
+    Request,
+};
+use schemars::JsonSchema;
+use scripty_config::BotConfig;
+use scripty_db::{
+    record_delivery, record_sponsorship, set_user_premium, DbError, DbResult, Sponsor, PG_POOL,
+};
+use scripty_utils::BOT_CONTEXT;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+use sha2::Sha256;
+use sqlx::{Postgres, Transaction};
+
+/// The most a webhook body can be. Sponsorship events are a few kilobytes.
+const MAX_BODY_KB: u64 = 256;
+
+/// The headers GitHub sends with every webhook delivery.
+pub struct Delivery {
+    /// The delivery's ID, which stays the same when it's redelivered.
+    pub id: String,
+    /// What kind of event this is, like `sponsorship` or `ping`.
+    pub event: String,
+    /// The `X-Hub-Signature-256` header.
+    pub signature: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Delivery {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        match (
+            headers.get_one("X-GitHub-Delivery"),
+            headers.get_one("X-GitHub-Event"),
+            headers.get_one("X-Hub-Signature-256"),
+        ) {
+            (Some(id), Some(event), Some(signature)) => Outcome::Success(Delivery {
+                id: id.to_string(),
+                event: event.to_string(),
+                signature: signature.to_string(),
+            }),
+            _ => Outcome::Failure((Status::BadRequest, ())),
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Whether `signature`, an `X-Hub-Signature-256` header, is `body` signed with `secret`.
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let expected = match signature.strip_prefix("sha256=").and_then(decode_hex) {
+        Some(s) => s,
+        None => return false,
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    // `verify` compares in constant time
+    mac.verify(&expected).is_ok()
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SponsorAccount {
+    pub login: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct SponsorTier {
+    pub monthly_price_in_dollars: u32,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Sponsorship {
+    pub sponsor: SponsorAccount,
+    pub tier: SponsorTier,
+}
+
+/// The parts of a `sponsorship` event that matter here.
+#[derive(Deserialize, JsonSchema)]
+pub struct SponsorshipEvent {
+    /// `created`, `tier_changed`, `cancelled`, or something that's ignored, like `edited`.
+    pub action: String,
+    pub sponsorship: Sponsorship,
+}
+
+/// What a sponsorship event means for a sponsor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SponsorChange {
+    pub github_login: String,
+    pub monthly_dollars: u32,
+    pub active: bool,
+}
+
+impl SponsorshipEvent {
+    /// Works out what this event changes, if anything.
+    ///
+    /// The pending actions are ignored, since GitHub sends the real ones once they take effect.
+    pub fn change(&self) -> Option<SponsorChange> {
+        let active = match self.action.as_str() {
+            "created" | "tier_changed" => true,
+            "cancelled" => false,
+            _ => return None,
+        };
+        Some(SponsorChange {
+            github_login: self.sponsorship.sponsor.login.to_lowercase(),
+            monthly_dollars: self.sponsorship.tier.monthly_price_in_dollars,
+            active,
+        })
+    }
+}
+
+/// What the webhook did with a delivery.
+#[derive(Serialize, JsonSchema)]
+pub struct SponsorWebhookResult {
+    /// `applied`, `duplicate` or `ignored`.
+    pub outcome: &'static str,
+}
+
+/// What applying a delivery came to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The sponsor's new state, which their premium now matches.
+    Applied(Sponsor),
+    /// The delivery was already applied, so nothing changed.
+    Duplicate,
+    /// The delivery doesn't change anything.
+    Ignored,
+}
+
+impl DeliveryOutcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Applied(_) => "applied",
+            Self::Duplicate => "duplicate",
+            Self::Ignored => "ignored",
+        }
+    }
+}
+
+/// Where deliveries are applied to. In the webhook that's a DB transaction, so a delivery is
+/// either applied and marked as seen, or neither.
+#[rocket::async_trait]
+pub trait SponsorStore: Send {
+    /// Marks `delivery_id` as seen, returning whether it wasn't already.
+    async fn record_delivery(&mut self, delivery_id: &str) -> DbResult<bool>;
+    /// Records the sponsor's new sponsorship, returning the sponsor after.
+    async fn record_sponsorship(&mut self, change: &SponsorChange) -> DbResult<Sponsor>;
+    async fn set_user_premium(&mut self, user_id: u64, level: u8, count: u8) -> DbResult<()>;
+}
+
+#[rocket::async_trait]
+impl SponsorStore for Transaction<'_, Postgres> {
+    async fn record_delivery(&mut self, delivery_id: &str) -> DbResult<bool> {
+        record_delivery(&mut *self, delivery_id).await
+    }
+
+    async fn record_sponsorship(&mut self, change: &SponsorChange) -> DbResult<Sponsor> {
+        record_sponsorship(
+            &mut *self,
+            &change.github_login,
+            change.monthly_dollars,
+            change.active,
+        )
+        .await
+    }
+
+    async fn set_user_premium(&mut self, user_id: u64, level: u8, count: u8) -> DbResult<()> {
+        set_user_premium(&mut *self, UserId(user_id), level, count).await
+    }
+}
+
+/// Applies an already verified delivery of an `event` with `body` to `store`, once per
+/// `delivery_id`.
+pub async fn apply_delivery(
+    store: &mut impl SponsorStore,
+    delivery_id: &str,
+    event: &str,
+    body: &[u8],
+) -> Result<DeliveryOutcome, ApiError> {
+    // GitHub sends a `ping` when the webhook is set up
+    if event != "sponsorship" {
+        return Ok(DeliveryOutcome::Ignored);
+    }
+    let event: SponsorshipEvent = serde_json::from_slice(body)
+        .map_err(|e| ApiError::new(Status::UnprocessableEntity, e.to_string()))?;
+    let change = match event.change() {
+        Some(c) => c,
+        None => return Ok(DeliveryOutcome::Ignored),
+    };
+
+    if !store.record_delivery(delivery_id).await.map_err(db_error)? {
+        return Ok(DeliveryOutcome::Duplicate);
+    }
+    let sponsor = store.record_sponsorship(&change).await.map_err(db_error)?;
+    if let Some(user_id) = sponsor.user_id {
+        let (level, count) = sponsor.premium();
+        store
+            .set_user_premium(user_id, level, count)
+            .await
+            .map_err(db_error)?;
+    }
+    Ok(DeliveryOutcome::Applied(sponsor))
+}
+
+fn db_error(e: impl Into<DbError>) -> ApiError {
+    let e = e.into();
+    tracing::warn!("sponsor webhook query failed: {}", e);
+    ApiError::new(
+        Status::ServiceUnavailable,
+        "couldn't record the sponsorship",
+    )
+}
+
+/// Tells a sponsor what their premium is now.
+async fn notify_sponsor(sponsor: &Sponsor) {
+    let (user_id, ctx) = match (sponsor.user_id, BOT_CONTEXT.get()) {
+        (Some(u), Some(ctx)) => (UserId(u), ctx),
+        _ => return,
+    };
+    let (level, count) = sponsor.premium();
+    let content = if level == 0 {
+        "Your sponsorship has ended, so your premium has too. Thanks for supporting Scripty!"
+            .to_string()
+    } else {
+        format!(
+            "Thanks for sponsoring Scripty! You now have premium level {}, and can give \
+            premium to {} server{}.",
+            level,
+            count,
+            if count == 1 { "" } else { "s" }
+        )
+    };
+
+    let result = match user_id.create_dm_channel(&ctx.http).await {
+        Ok(channel) => channel.say(&ctx.http, content).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::info!("failed to DM sponsor {}: {}", sponsor.github_login, e);
+    }
+}
+
+/// Receives GitHub Sponsors webhooks, granting, changing and revoking premium to match.
+#[rocket::post("/v1/webhooks/sponsors", data = "<body>")]
+pub async fn sponsor_webhook(
+    delivery: Delivery,
+    body: Data<'_>,
+) -> Result<Json<SponsorWebhookResult>, ApiError> {
+    let secret = BotConfig::get()
+        .and_then(|c| c.sponsor_webhook_secret())
+        .ok_or_else(|| ApiError::new(Status::NotFound, "the sponsor webhook is turned off"))?;
+
+    let body = match body.open(MAX_BODY_KB.kibibytes()).into_bytes().await {
+        Ok(b) if b.is_complete() => b.into_inner(),
+        Ok(_) => return Err(ApiError::new(Status::PayloadTooLarge, "body is too large")),
+        Err(e) => return Err(ApiError::new(Status::BadRequest, e.to_string())),
+    };
+    if !verify_signature(secret, &body, &delivery.signature) {
+        return Err(ApiError::new(Status::Unauthorized, "bad signature"));
+    }
+
+    let db = PG_POOL
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))?;
+    let mut tx = db.begin().await.map_err(db_error)?;
+    let sponsor = match apply_delivery(&mut tx, &delivery.id, &delivery.event, &body).await? {
+        DeliveryOutcome::Applied(sponsor) => sponsor,
+        outcome => return Ok(result(outcome.name())),
+    };
+    tx.commit().await.map_err(db_error)?;
+
+    tracing::info!(
+        "sponsor {} is now at ${} a month (active: {})",
+        sponsor.github_login,
+        sponsor.monthly_dollars,
+        sponsor.active
+    );
+    notify_sponsor(&sponsor).await;
+    Ok(result("applied"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    /// The secret the recorded deliveries in `fixtures/sponsors` were signed with.
+    const SECRET: &str = "It's a Secret to Everybody";
+
+    fn recorded(name: &str) -> (&'static [u8], &'static str) {
+        match name {
+            "created" => (
+                include_bytes!("../fixtures/sponsors/created.json"),
+                include_str!("../fixtures/sponsors/created.sig").trim(),
+            ),
+            "tier_changed" => (
+                include_bytes!("../fixtures/sponsors/tier_changed.json"),
+                include_str!("../fixtures/sponsors/tier_changed.sig").trim(),
+            ),
+            "cancelled" => (
+                include_bytes!("../fixtures/sponsors/cancelled.json"),
+                include_str!("../fixtures/sponsors/cancelled.sig").trim(),
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn verifies_githubs_example() {
+        // from GitHub's docs on validating webhook deliveries
+        assert!(verify_signature(
+            SECRET,
+            b"Hello, World!",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+    }
+
+    #[test]
+    fn verifies_recorded_deliveries() {
+        for name in ["created", "tier_changed", "cancelled"] {
+            let (body, signature) = recorded(name);
+            assert!(verify_signature(SECRET, body, signature), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let (body, signature) = recorded("created");
+        let mut tampered = body.to_vec();
+        tampered[body.len() / 2] ^= 1;
+
+        assert!(!verify_signature(SECRET, &tampered, signature));
+        assert!(!verify_signature("not the secret", body, signature));
+        assert!(!verify_signature(SECRET, body, "sha256=abc"));
+        assert!(!verify_signature(
+            SECRET,
+            body,
+            signature.trim_start_matches("sha256=")
+        ));
+        assert!(!verify_signature(SECRET, body, ""));
+    }
+
+    fn change(name: &str) -> Option<SponsorChange> {
+        let (body, _) = recorded(name);
+        serde_json::from_slice::<SponsorshipEvent>(body)
+            .expect("recorded delivery doesn't parse")
+            .change()
+    }
+
+    #[test]
+    fn reads_recorded_changes() {
+        let login = "octocat".to_string();
+        assert_eq!(
+            change("created"),
+            Some(SponsorChange {
+                github_login: login.clone(),
+                monthly_dollars: 5,
+                active: true,
+            })
+        );
+        assert_eq!(
+            change("tier_changed"),
+            Some(SponsorChange {
+                github_login: login.clone(),
+                monthly_dollars: 25,
+                active: true,
+            })
+        );
+        assert_eq!(
+            change("cancelled"),
+            Some(SponsorChange {
+                github_login: login,
+                monthly_dollars: 25,
+                active: false,
+            })
+        );
+    }
+
+    /// Keeps everything in memory, with `octocat` already linked to user 1.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct MemoryStore {
+        deliveries: HashSet<String>,
+        sponsors: HashMap<String, Sponsor>,
+        premium: HashMap<u64, (u8, u8)>,
+    }
+
+    impl MemoryStore {
+        fn new() -> Self {
+            let octocat = Sponsor {
+                github_login: "octocat".to_string(),
+                user_id: Some(1),
+                monthly_dollars: 0,
+                active: false,
+            };
+            let mut sponsors = HashMap::new();
+            sponsors.insert(octocat.github_login.clone(), octocat);
+            Self {
+                deliveries: HashSet::new(),
+                sponsors,
+                premium: HashMap::new(),
+            }
+        }
+    }
+
+    #[rocket::async_trait]
+    impl SponsorStore for MemoryStore {
+        async fn record_delivery(&mut self, delivery_id: &str) -> DbResult<bool> {
+            Ok(self.deliveries.insert(delivery_id.to_string()))
+        }
+
+        async fn record_sponsorship(&mut self, change: &SponsorChange) -> DbResult<Sponsor> {
+            let sponsor = self
+                .sponsors
+                .entry(change.github_login.clone())
+                .or_insert_with(|| Sponsor {
+                    github_login: change.github_login.clone(),
+                    user_id: None,
+                    monthly_dollars: 0,
+                    active: false,
+                });
+            sponsor.monthly_dollars = change.monthly_dollars;
+            sponsor.active = change.active;
+            Ok(sponsor.clone())
+        }
+
+        async fn set_user_premium(&mut self, user_id: u64, level: u8, count: u8) -> DbResult<()> {
+            self.premium.insert(user_id, (level, count));
+            Ok(())
+        }
+    }
+
+    async fn deliver(store: &mut MemoryStore, id: &str, name: &str) -> &'static str {
+        let (body, _) = recorded(name);
+        apply_delivery(store, id, "sponsorship", body)
+            .await
+            .unwrap_or_else(|e| panic!("{} failed: {}", name, e.message))
+            .name()
+    }
+
+    #[tokio::test]
+    async fn redelivery_is_a_duplicate() {
+        let mut store = MemoryStore::new();
+
+        for (id, name) in [("1", "created"), ("2", "tier_changed"), ("3", "cancelled")] {
+            assert_eq!(deliver(&mut store, id, name).await, "applied", "{}", name);
+            let after = store.clone();
+            assert_eq!(store.premium[&1], store.sponsors["octocat"].premium());
+
+            assert_eq!(deliver(&mut store, id, name).await, "duplicate", "{}", name);
+            assert_eq!(store, after, "{} changed something the second time", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn old_redelivery_doesnt_undo_newer_changes() {
+        let mut store = MemoryStore::new();
+        deliver(&mut store, "1", "created").await;
+        deliver(&mut store, "2", "tier_changed").await;
+        let premium = store.premium[&1];
+
+        // GitHub redelivering the `created` event shouldn't put the sponsor back on $5
+        assert_eq!(deliver(&mut store, "1", "created").await, "duplicate");
+        assert_eq!(store.premium[&1], premium);
+        assert_eq!(store.sponsors["octocat"].monthly_dollars, 25);
+    }
+
+    #[tokio::test]
+    async fn pings_are_ignored() {
+        let mut store = MemoryStore::new();
+        let outcome = apply_delivery(&mut store, "1", "ping", b"{}").await;
+        assert_eq!(outcome.ok(), Some(DeliveryOutcome::Ignored));
+        assert!(store.deliveries.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{ApiError, ApiKey};
+use rocket::{http::Status, serde::json::Json};
+use schemars::JsonSchema;
+use scripty_db::ApiScope;
+use scripty_metrics::METRICS;
+use serde::Serialize;
+
+#[derive(Serialize, JsonSchema)]
+pub struct Stats {
+    pub guilds: i64,
+    pub members: i64,
+    /// How much audio has been transcribed, in milliseconds.
+    pub ms_transcribed: u64,
+    pub voice_calls: i64,
+    /// How long the bot has been up, in seconds.
+    pub uptime_secs: i64,
+}
+
+/// Returns the bot's current stats.
+#[rocket::get("/v1/stats")]
+pub async fn stats(key: ApiKey) -> Result<Json<Stats>, ApiError> {
+    key.require(ApiScope::Stats)?;
+
+    let metrics = METRICS
+        .get()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "the bot is still starting"))?;
+    Ok(Json(Stats {
+        guilds: metrics.guilds.get(),
+        members: metrics.members.get(),
+        ms_transcribed: metrics.ms_transcribed.get(),
+        voice_calls: metrics.voice_calls.get(),
+        uptime_secs: (chrono::Utc::now().naive_utc() - metrics.start_time).num_seconds(),
+    }))
+}
@@ -0,0 +1,397 @@
+//! Live transcription over WebSockets.
+//!
+//! Rocket can't upgrade connections to WebSockets yet, so this runs its own listener on the
+//! config's `stream_address`. Clients connect to `/v1/stream`, authenticating with the same
+//! headers as any other API request. Keys in URLs end up in logs, so there's no query parameter
+//! for them: browsers, which can't set headers on WebSockets, have to go through a backend.
+//!
+//! Connecting is free, but every utterance takes a request out of the key's rate limit once
+//! there's speech in it. That pays for its interim transcripts too, which get further apart as
+//! the utterance grows so together they never cost more than about three times the final one.
+//!
+//! Audio is sent as binary messages, either raw 16 bit little endian PCM made of whole samples
+//! (`?format=pcm`, with `sample_rate` and `channels`), or one Opus packet per message
+//! (`?format=opus`), decoded to 48kHz stereo just like songbird does for voice chats. Interim
+//! transcripts of the current utterance are sent back as it grows, and a final one once it ends:
+//! after a pause, when the client sends `{"type": "end"}`, or when it gets too long.
+use crate::{record_audio, ApiKeyError, Transcription, UnchargedKey, NUM_RESULTS};
+use audiopus::{coder::Decoder as OpusDecoder, Channels, SampleRate};
+use futures_util::{SinkExt, StreamExt};
+use scripty_audio_utils::{model, run_stt_mono, stereo_to_mono};
+use scripty_config::BotConfig;
+use scripty_db::{max_api_audio_secs, ApiScope};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+    WebSocketStream,
+};
+
+/// How much new audio there has to be before sending another interim transcript. Once the
+/// utterance gets longer, there has to be at least half as much new audio as the last interim
+/// transcript covered.
+const INTERIM_INTERVAL_MS: u64 = 1500;
+
+/// How long a pause ends an utterance.
+const SILENCE_MS: u64 = 800;
+
+/// Audio quieter than this (as the RMS of 16 bit samples) counts as a pause.
+const SILENCE_RMS: f64 = 300.0;
+
+/// The longest an utterance can get before it's ended anyway, if the key's owner can send audio
+/// that long at all.
+const MAX_UTTERANCE_MS: u64 = 30_000;
+
+/// The most samples one Opus packet can decode to: 120ms of 48kHz stereo.
+const MAX_OPUS_SAMPLES: usize = 5760 * 2;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    /// The current utterance so far. Later interim transcripts replace earlier ones.
+    Interim {
+        transcript: String,
+        duration_ms: u64,
+    },
+    /// A finished utterance, with the same fields as `/v1/transcribe` returns.
+    Final(Transcription),
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    /// Ends the current utterance.
+    End,
+}
+
+/// What the audio sent over a stream looks like.
+enum AudioFormat {
+    Pcm { sample_rate: u32, channels: u16 },
+    Opus(OpusDecoder),
+}
+
+impl AudioFormat {
+    /// Reads the format from a query string like `format=pcm&sample_rate=16000&channels=1`.
+    fn from_query(query: &str) -> Result<Self, String> {
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|p| p.split_once('='))
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v)
+        };
+        match param("format").unwrap_or("pcm") {
+            "pcm" => {
+                let sample_rate = match param("sample_rate") {
+                    Some(r) => r.parse().map_err(|_| "sample_rate must be a number")?,
+                    None => 16_000,
+                };
+                let channels = match param("channels") {
+                    Some(c) => c.parse().map_err(|_| "channels must be a number")?,
+                    None => 1,
+                };
+                if !(8_000..=48_000).contains(&sample_rate) {
+                    return Err("sample_rate must be between 8000 and 48000".to_string());
+                }
+                if !(1..=2).contains(&channels) {
+                    return Err("channels must be 1 or 2".to_string());
+                }
+                Ok(Self::Pcm {
+                    sample_rate,
+                    channels,
+                })
+            }
+            "opus" => OpusDecoder::new(SampleRate::Hz48000, Channels::Stereo)
+                .map(Self::Opus)
+                .map_err(|e| format!("couldn't set up the Opus decoder: {}", e)),
+            other => Err(format!("unknown format `{}`, use `pcm` or `opus`", other)),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Self::Pcm { sample_rate, .. } => *sample_rate,
+            Self::Opus(_) => 48_000,
+        }
+    }
+
+    /// Decodes one message of audio to mono samples.
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>, String> {
+        match self {
+            Self::Pcm { channels, .. } => {
+                let (samples, _) = data.as_chunks::<2>();
+                let samples: Vec<i16> = samples.iter().map(|s| i16::from_le_bytes(*s)).collect();
+                Ok(if *channels == 2 {
+                    stereo_to_mono(samples)
+                } else {
+                    samples
+                })
+            }
+            Self::Opus(decoder) => {
+                let mut output = vec![0; MAX_OPUS_SAMPLES];
+                let len = decoder
+                    .decode(Some(data), &mut output[..], false)
+                    .map_err(|e| format!("couldn't decode Opus packet: {}", e))?;
+                output.truncate(len * 2);
+                Ok(stereo_to_mono(output))
+            }
+        }
+    }
+}
+
+/// The utterance currently being streamed.
+struct Utterance {
+    sample_rate: u32,
+    audio: Vec<i16>,
+    /// How many samples have come in since the last interim transcript.
+    since_interim: usize,
+    /// Whether a request was taken out of the key's rate limit for this utterance yet.
+    charged: bool,
+    /// How many samples of silence the utterance ends with.
+    trailing_silence: usize,
+}
+
+impl Utterance {
+    fn samples_per_ms(&self) -> u64 {
+        u64::from(self.sample_rate) / 1000
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.audio.len() as u64 / self.samples_per_ms()
+    }
+
+    fn push(&mut self, samples: Vec<i16>) {
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.iter().map(|s| f64::from(*s).powi(2)).sum::<f64>() / samples.len() as f64)
+                .sqrt()
+        };
+        // leading silence isn't worth transcribing
+        if rms < SILENCE_RMS {
+            if self.audio.is_empty() {
+                return;
+            }
+            self.trailing_silence += samples.len();
+        } else {
+            self.trailing_silence = 0;
+        }
+        self.since_interim += samples.len();
+        self.audio.extend(samples);
+    }
+
+    /// Whether the utterance ended with a pause.
+    fn paused(&self) -> bool {
+        !self.audio.is_empty() && self.trailing_silence as u64 / self.samples_per_ms() >= SILENCE_MS
+    }
+
+    fn wants_interim(&self) -> bool {
+        // every interim transcript runs over the whole utterance, so spacing them out like this
+        // keeps them from adding up to more than about three times the final one
+        let covered = self.audio.len() - self.since_interim;
+        self.since_interim as u64 / self.samples_per_ms() >= INTERIM_INTERVAL_MS
+            && self.since_interim * 2 >= covered
+    }
+}
+
+/// Starts listening for streams on the configured address.
+pub async fn serve_streams() {
+    let address = match BotConfig::get() {
+        Some(config) => config.stream_address(),
+        None => return,
+    };
+    let listener = match TcpListener::bind(address).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::warn!("couldn't listen for streams on {}: {}", address, e);
+            return;
+        }
+    };
+    tracing::info!("listening for streams on {}", address);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream));
+            }
+            Err(e) => tracing::warn!("couldn't accept a stream: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream) {
+    let mut key = None;
+    let mut query = String::new();
+    let handshake = tokio_tungstenite::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            if request.uri().path() != "/v1/stream" {
+                let mut error = ErrorResponse::new(Some("not found".to_string()));
+                *error.status_mut() = StatusCode::NOT_FOUND;
+                return Err(error);
+            }
+            query = request.uri().query().unwrap_or_default().to_string();
+            key = request
+                .headers()
+                .get("Authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .or_else(|| request.headers().get("token")?.to_str().ok())
+                .map(str::to_string);
+            Ok(response)
+        },
+    )
+    .await;
+    let mut ws = match handshake {
+        Ok(ws) => ws,
+        Err(e) => {
+            tracing::debug!("stream handshake failed: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = stream_transcriptions(&mut ws, key, &query).await {
+        let _ = send(&mut ws, &ServerMessage::Error { error: e }).await;
+        let _ = ws
+            .close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: "".into(),
+            }))
+            .await;
+    }
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, message: &ServerMessage) -> Result<(), String> {
+    let json = serde_json::to_string(message).expect("messages are always valid JSON");
+    ws.send(Message::Text(json))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Authenticates a stream, then transcribes it until it's closed. Returns errors to tell the
+/// client about before closing it.
+async fn stream_transcriptions(
+    ws: &mut WebSocketStream<TcpStream>,
+    key: Option<String>,
+    query: &str,
+) -> Result<(), String> {
+    let key = UnchargedKey::lookup(&key.ok_or("a valid API key is required")?)
+        .await
+        .map_err(|(_, e)| key_error(e))?;
+    key.require(ApiScope::Transcribe).map_err(|e| e.message)?;
+    let max_ms = max_api_audio_secs(key.premium_level()) * 1000;
+    if max_ms == 0 {
+        return Err("the speech to text API is only for premium subscribers".to_string());
+    }
+    let max_ms = max_ms.min(MAX_UTTERANCE_MS);
+
+    let mut format = AudioFormat::from_query(query)?;
+    let new_utterance = |format: &AudioFormat| Utterance {
+        sample_rate: format.sample_rate(),
+        audio: Vec::new(),
+        since_interim: 0,
+        charged: false,
+        trailing_silence: 0,
+    };
+    let mut utterance = new_utterance(&format);
+
+    while let Some(message) = ws.next().await {
+        let message = match message {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::debug!("stream from key {} failed: {}", key.key_id(), e);
+                return Ok(());
+            }
+        };
+        let end = match message {
+            Message::Binary(data) => {
+                utterance.push(format.decode(&data)?);
+                if !utterance.charged && !utterance.audio.is_empty() {
+                    key.charge().map_err(|(_, e)| key_error(e))?;
+                    utterance.charged = true;
+                }
+                utterance.paused() || utterance.duration_ms() >= max_ms
+            }
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(ClientMessage::End) => true,
+                Err(e) => return Err(format!("couldn't read message: {}", e)),
+            },
+            Message::Close(_) => break,
+            // tungstenite answers pings by itself
+            _ => false,
+        };
+
+        if end {
+            let finished = std::mem::replace(&mut utterance, new_utterance(&format));
+            finish(ws, &key, finished).await?;
+        } else if utterance.wants_interim() {
+            utterance.since_interim = 0;
+            let transcript = run_stt_mono(
+                utterance.audio.clone(),
+                utterance.sample_rate,
+                1,
+                model().await,
+            )
+            .await
+            .map(|m| Transcription::from_metadata(&m, utterance.duration_ms()).transcript)
+            .map_err(|e| format!("speech to text failed: {}", e))?;
+            send(
+                ws,
+                &ServerMessage::Interim {
+                    transcript,
+                    duration_ms: utterance.duration_ms(),
+                },
+            )
+            .await?;
+        }
+    }
+
+    finish(ws, &key, utterance).await
+}
+
+/// What to tell a client whose key couldn't be used.
+fn key_error(e: ApiKeyError) -> String {
+    match e {
+        ApiKeyError::RateLimited(s) => format!(
+            "this API key is being rate limited, try again in {} seconds",
+            s.retry_after.unwrap_or_default()
+        ),
+        ApiKeyError::Database => "couldn't check your API key, try again later".to_string(),
+        _ => "a valid API key is required".to_string(),
+    }
+}
+
+/// Sends the final transcript of `utterance`, if there's anything in it.
+async fn finish(
+    ws: &mut WebSocketStream<TcpStream>,
+    key: &UnchargedKey,
+    utterance: Utterance,
+) -> Result<(), String> {
+    if utterance.audio.is_empty() {
+        return Ok(());
+    }
+    let duration_ms = utterance.duration_ms();
+    let metadata = run_stt_mono(
+        utterance.audio,
+        utterance.sample_rate,
+        NUM_RESULTS,
+        model().await,
+    )
+    .await
+    .map_err(|e| format!("speech to text failed: {}", e))?;
+    record_audio(key.key_id(), duration_ms);
+
+    send(
+        ws,
+        &ServerMessage::Final(Transcription::from_metadata(&metadata, duration_ms)),
+    )
+    .await
+}
@@ -0,0 +1,127 @@
+use crate::{record_audio, ApiError, ApiKey};
+use rocket::{
+    data::{Data, ToByteUnit},
+    http::Status,
+    serde::json::Json,
+};
+use schemars::JsonSchema;
+use scripty_audio_utils::{decode_audio, model, run_stt_mono, DecodedAudio, Metadata, SAMPLE_RATE};
+use scripty_db::{max_api_audio_secs, ApiScope};
+use serde::Serialize;
+
+/// How many candidate transcripts to return, counting the best one.
+pub(crate) const NUM_RESULTS: u32 = 3;
+
+#[derive(Serialize, JsonSchema)]
+pub struct Token {
+    pub text: String,
+    /// When this token starts, in milliseconds from the start of the audio.
+    pub start_ms: u32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Alternative {
+    pub transcript: String,
+    pub confidence: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Transcription {
+    /// The most likely transcript.
+    pub transcript: String,
+    pub confidence: f64,
+    /// Every token in `transcript`, with its timing.
+    pub tokens: Vec<Token>,
+    /// Less likely transcripts, most likely first.
+    pub alternatives: Vec<Alternative>,
+    /// How long the audio was, in milliseconds.
+    pub duration_ms: u64,
+}
+
+impl Transcription {
+    /// Picks the transcripts out of what the model returned for `duration_ms` of audio.
+    pub(crate) fn from_metadata(metadata: &Metadata, duration_ms: u64) -> Self {
+        let mut candidates = metadata.transcripts().iter().map(|t| {
+            let tokens: Vec<Token> = t
+                .tokens()
+                .iter()
+                .filter_map(|token| {
+                    Some(Token {
+                        text: token.text().ok()?.to_string(),
+                        start_ms: token.timestep() * 20,
+                    })
+                })
+                .collect();
+            (tokens, t.confidence())
+        });
+
+        let (tokens, confidence) = candidates.next().unwrap_or_default();
+        let alternatives = candidates
+            .map(|(tokens, confidence)| Alternative {
+                transcript: tokens.into_iter().map(|t| t.text).collect(),
+                confidence,
+            })
+            .collect();
+
+        Self {
+            transcript: tokens.iter().map(|t| t.text.as_str()).collect(),
+            confidence,
+            tokens,
+            alternatives,
+            duration_ms,
+        }
+    }
+}
+
+/// Decodes an uploaded audio file on a blocking thread, failing if it's longer than `max_secs`.
+pub(crate) async fn decode_body(body: Vec<u8>, max_secs: u64) -> Result<DecodedAudio, ApiError> {
+    tokio::task::spawn_blocking(move || decode_audio(&body, max_secs * 1000))
+        .await
+        .map_err(|e| {
+            tracing::warn!("audio decoding panicked: {}", e);
+            ApiError::new(Status::InternalServerError, "couldn't decode the audio")
+        })?
+        .map_err(ApiError::from)
+}
+
+/// Transcribes the audio file in the request body: WAV, FLAC, MP3, Ogg/Vorbis, Ogg/Opus or
+/// WebM/Opus.
+#[rocket::post("/v1/transcribe", data = "<audio>")]
+pub async fn transcribe(key: ApiKey, audio: Data<'_>) -> Result<Json<Transcription>, ApiError> {
+    key.require(ApiScope::Transcribe)?;
+
+    let max_secs = max_api_audio_secs(key.premium_level);
+    if max_secs == 0 {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "the speech to text API is only for premium subscribers",
+        ));
+    }
+
+    // 48kHz stereo is about as big as anyone will send, plus some room for the header
+    let limit = (max_secs * 48_000 * 2 * 2 + 4096).bytes();
+    let body = match audio.open(limit).into_bytes().await {
+        Ok(b) if b.is_complete() => b.into_inner(),
+        Ok(_) => {
+            return Err(ApiError::new(
+                Status::PayloadTooLarge,
+                format!("audio can be at most {} seconds long", max_secs),
+            ))
+        }
+        Err(e) => return Err(ApiError::new(Status::BadRequest, e.to_string())),
+    };
+
+    let audio = decode_body(body, max_secs).await?;
+    let duration_ms = audio.duration_ms();
+
+    let metadata = run_stt_mono(audio.samples, SAMPLE_RATE, NUM_RESULTS, model().await)
+        .await
+        .map_err(|e| {
+            tracing::warn!("API speech to text failed for {}: {}", key.user_id, e);
+            ApiError::new(Status::InternalServerError, "speech to text failed")
+        })?;
+
+    record_audio(key.key_id, duration_ms);
+
+    Ok(Json(Transcription::from_metadata(&metadata, duration_ms)))
+}